@@ -62,3 +62,17 @@ fn non_match_huge_strict_start_end(b: &mut Bencher) {
         assert!(!regex.test("pppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppppp"));
     });
 }
+
+#[bench]
+fn find_without_groups(b: &mut Bencher) {
+    let regex = Regex::compile("[a-z]+ing").unwrap();
+    let text = "walking and talking, then sleeping ".repeat(1000);
+    b.iter(|| assert_eq!(regex.find_matches(&text).count(), 3000));
+}
+
+#[bench]
+fn find_with_groups(b: &mut Bencher) {
+    let regex = Regex::compile("([a-z]+)(ing)").unwrap();
+    let text = "walking and talking, then sleeping ".repeat(1000);
+    b.iter(|| assert_eq!(regex.find_matches(&text).count(), 3000));
+}
@@ -0,0 +1,297 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::case::{self, MatchCase};
+use crate::compiler;
+use crate::{InlineFlags, Regex, RegexError, RegexErrorKind, Result};
+
+/// Builds a [Regex] programmatically, without writing its pattern
+///
+/// Each method appends an element to the expression. Quantifiers,
+/// like [`star`](Self::star), apply to the last element appended.
+///
+/// The capture groups are numbered in the order they're opened, just
+/// like in a pattern. The pattern of the resulting [Regex] is generated
+/// from the expression, so [`Regex::as_str`] can still be used.
+///
+/// # Example
+/// ```
+/// use regexpr::{Regex, RegexBuilder};
+///
+/// // a(b|c)
+/// let regex = RegexBuilder::new()
+///     .literal("a")
+///     .group(RegexBuilder::new().alt([
+///         RegexBuilder::new().literal("b"),
+///         RegexBuilder::new().literal("c"),
+///     ]))
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(regex.as_str(), "a(b|c)");
+/// assert_eq!(regex, Regex::compile("a(b|c)").unwrap());
+/// assert!(regex.test("ac"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RegexBuilder {
+    cases: Vec<MatchCase>,
+    /// First error found, reported by [`build`](Self::build)
+    error: Option<RegexError>,
+}
+
+impl RegexBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(mut self, case: MatchCase) -> Self {
+        self.cases.push(case);
+        self
+    }
+
+    /// Applies a quantifier to the last element
+    fn quantify(mut self, name: char, f: impl FnOnce(Box<MatchCase>) -> MatchCase) -> Self {
+        match self.cases.pop() {
+            Some(last) => self.cases.push(f(Box::new(last))),
+            None => {
+                self.error.get_or_insert_with(|| {
+                    RegexError::new(
+                        RegexErrorKind::UnexpectedQuantifier,
+                        format!("Expected pattern before '{name}'"),
+                    )
+                });
+            }
+        }
+        self
+    }
+
+    /// Keeps the first error of `other`, if this builder doesn't have one yet
+    fn take_error(&mut self, other: &mut RegexBuilder) {
+        if self.error.is_none() {
+            self.error = other.error.take();
+        }
+    }
+
+    /// Matches the given text literally
+    #[must_use]
+    pub fn literal(mut self, text: &str) -> Self {
+        self.cases.extend(text.chars().map(MatchCase::Char));
+        self
+    }
+
+    /// Matches any character. Like `.`
+    #[must_use]
+    pub fn any(self) -> Self {
+        self.push(MatchCase::AnyOne)
+    }
+
+    /// Matches the start of the input. Like `^`
+    #[must_use]
+    pub fn start(self) -> Self {
+        self.push(MatchCase::Start)
+    }
+
+    /// Matches the end of the input. Like `$`
+    #[must_use]
+    pub fn end(self) -> Self {
+        self.push(MatchCase::End)
+    }
+
+    /// Matches a decimal digit. Like `\d`
+    #[must_use]
+    pub fn digit(self) -> Self {
+        self.push(MatchCase::Decimal)
+    }
+
+    /// Matches a word character. Like `\w`
+    #[must_use]
+    pub fn word(self) -> Self {
+        self.push(MatchCase::Word)
+    }
+
+    /// Matches a whitespace character. Like `\s`
+    #[must_use]
+    pub fn whitespace(self) -> Self {
+        self.push(MatchCase::Whitespace)
+    }
+
+    /// Matches any of the given characters. Like `[abc]`
+    #[must_use]
+    pub fn class(self, chars: &str) -> Self {
+        let chars = chars.chars().map(MatchCase::Char).collect();
+        self.push(MatchCase::CharMatch(chars))
+    }
+
+    /// Matches a character between `start` and `end`, both included. Like `[a-z]`
+    #[must_use]
+    pub fn range(self, start: char, end: char) -> Self {
+        self.push(MatchCase::CharMatch(Box::new([MatchCase::Between(
+            start, end,
+        )])))
+    }
+
+    /// Matches the text captured by the n'th group. Like `\n`
+    #[must_use]
+    pub fn backref(self, n: usize) -> Self {
+        self.push(MatchCase::Capture(n))
+    }
+
+    /// Matches `inner` as a capture group. Like `(...)`
+    #[must_use]
+    pub fn group(mut self, mut inner: RegexBuilder) -> Self {
+        self.take_error(&mut inner);
+        let case = match <[MatchCase; 1]>::try_from(inner.cases) {
+            Ok([or @ MatchCase::Or(_)]) => or,
+            Ok(case) => MatchCase::List(Box::new(case)),
+            Err(cases) => MatchCase::List(cases.into_boxed_slice()),
+        };
+        self.push(MatchCase::Group {
+            case: Box::new(case),
+            capture_id: 0,
+        })
+    }
+
+    /// Matches any of the given branches. Like `a|b|c`
+    #[must_use]
+    pub fn alt(mut self, branches: impl IntoIterator<Item = RegexBuilder>) -> Self {
+        let mut branches: Vec<_> = branches
+            .into_iter()
+            .map(|mut b| {
+                self.take_error(&mut b);
+                b.cases
+            })
+            .collect();
+        // Same shape the compiler gives to `a|b|c`: every branch but
+        // the last one is unwrapped if it only has one element
+        let last = branches.pop().unwrap_or_default();
+        let branches = branches
+            .into_iter()
+            .map(|mut cases| match cases.len() {
+                1 => cases.remove(0),
+                _ => MatchCase::List(cases.into_boxed_slice()),
+            })
+            .chain([MatchCase::List(last.into_boxed_slice())])
+            .collect();
+        self.push(MatchCase::Or(branches))
+    }
+
+    /// Matches the last element zero or more times. Like `*`
+    #[must_use]
+    pub fn star(self) -> Self {
+        self.quantify('*', |case| MatchCase::Star { case, lazy: false })
+    }
+
+    /// Matches the last element one or more times. Like `+`
+    #[must_use]
+    pub fn plus(self) -> Self {
+        self.quantify('+', |case| MatchCase::OneOrMore { case, lazy: false })
+    }
+
+    /// Makes the last element optional. Like `?`
+    #[must_use]
+    pub fn opt(self) -> Self {
+        self.quantify('?', MatchCase::Opt)
+    }
+
+    /// Matches the last element between `min` and `max` times.
+    /// Like `{min,max}`. [`None`] means there's no bound
+    ///
+    /// The bounds are checked just like in a pattern: `min` can't be
+    /// greater than `max`, and neither can be too large
+    #[must_use]
+    pub fn repeat(mut self, min: Option<usize>, max: Option<usize>) -> Self {
+        if let Err(err) = compiler::check_repetition(min, max) {
+            self.error.get_or_insert(err);
+        }
+        self.quantify('{', |case| MatchCase::RangeLoop { case, min, max })
+    }
+
+    /// Makes the last [`star`](Self::star) or [`plus`](Self::plus) lazy.
+    /// Like `*?` and `+?`
+    #[must_use]
+    pub fn lazy(mut self) -> Self {
+        match self.cases.last_mut() {
+            Some(MatchCase::Star { lazy, .. } | MatchCase::OneOrMore { lazy, .. }) => *lazy = true,
+            _ => {
+                self.error.get_or_insert_with(|| {
+                    RegexError::new(
+                        RegexErrorKind::UnexpectedQuantifier,
+                        "Expected '*' or '+' before lazy",
+                    )
+                });
+            }
+        }
+        self
+    }
+
+    /// Builds the [Regex]
+    ///
+    /// # Errors
+    /// If the expression is invalid, for example if a quantifier has
+    /// nothing to apply to, or a backreference points to an unknown group
+    pub fn build(mut self) -> Result<Regex> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+
+        let mut opened = 0;
+        number_groups(&mut self.cases, &mut opened, &mut Vec::new())?;
+
+        let matches = self.cases.into_boxed_slice();
+        let src = case::to_pattern(&matches).into_boxed_str();
+        let names = vec![None; opened].into_boxed_slice();
+        Ok(Regex::from_parts(
+            matches,
+            src,
+            names,
+            InlineFlags::default(),
+        ))
+    }
+}
+
+/// Assigns the capture ids in the order the groups are opened,
+/// and checks that the backreferences point to a group closed before them.
+/// `open` holds the ids of the groups that enclose `cases`
+fn number_groups(cases: &mut [MatchCase], opened: &mut usize, open: &mut Vec<usize>) -> Result<()> {
+    for case in cases {
+        match case {
+            MatchCase::Group { case, capture_id } => {
+                *opened += 1;
+                *capture_id = *opened;
+                open.push(*opened);
+                number_groups(core::slice::from_mut(&mut **case), opened, open)?;
+                open.pop();
+            }
+            MatchCase::Capture(0) => {
+                return Err(RegexError::new(
+                    RegexErrorKind::UnknownBackreference,
+                    "Backreference to unknown group 0",
+                ));
+            }
+            MatchCase::Capture(n) if *n > *opened || open.contains(n) => {
+                return Err(RegexError::new(
+                    RegexErrorKind::ForwardReference,
+                    format!("Forward/self reference to group {n} not allowed"),
+                ));
+            }
+            MatchCase::List(cases) | MatchCase::Or(cases) | MatchCase::CharMatch(cases) => {
+                number_groups(cases, opened, open)?;
+            }
+            MatchCase::Opt(case)
+            | MatchCase::OneOrMore { case, .. }
+            | MatchCase::Star { case, .. }
+            | MatchCase::RangeLoop { case, .. }
+            | MatchCase::Not(case)
+            | MatchCase::Atomic(case) => {
+                number_groups(core::slice::from_mut(&mut **case), opened, open)?;
+            }
+            MatchCase::Conditional { then, else_, .. } => {
+                number_groups(core::slice::from_mut(&mut **then), opened, open)?;
+                number_groups(core::slice::from_mut(&mut **else_), opened, open)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
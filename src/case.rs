@@ -1,106 +1,305 @@
 use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use core::fmt::{self, Display, Write};
 
-use crate::matcher::{LookAhead, LookAheadKind, RegexCtx};
+use crate::RegexConf;
+#[cfg(all(feature = "diagnostics", feature = "std"))]
+use crate::matcher::TraceEvent;
+use crate::matcher::{Alternative, LookAheadKind, RegexCtx, Task};
 
-#[derive(Clone, Debug)]
+/// A node of a compiled [Regex]
+///
+/// This is a read-only view of the structure of the expression,
+/// returned by [`Regex::ast`]. New variants may be added in the future.
+///
+/// [Regex]: crate::Regex
+/// [`Regex::ast`]: crate::Regex::ast
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum MatchCase {
+    /// `^`: Start of the input, or of a line in multiline mode
     Start,
+    /// `$`: End of the input, or of a line in multiline mode
     End,
+    /// `\A`: Start of the input, even in multiline mode
+    InputStart,
+    /// `\z`: End of the input, even in multiline mode
+    InputEnd,
+    /// `\Z`: End of the input, or before a newline at the end of it
+    InputEndOrNewline,
+    /// `\G`: Where the previous match ended, or where the search started
+    PrevMatchEnd,
+    /// A literal character
     Char(char),
+    /// A sequence of cases, that must match one after the other
     List(Box<[MatchCase]>),
+    /// `(...)`: A capture group
     Group {
         case: Box<MatchCase>,
+        /// Index of the group, starting at 1
         capture_id: usize,
     },
+    /// `A|B`: Matches the first of the branches that succeeds
     Or(Box<[MatchCase]>),
+    /// `.`: Any character, except a newline unless in dot all mode
     AnyOne,
+    /// `?`: Optional case
     Opt(Box<MatchCase>),
-    OneOrMore {
-        case: Box<MatchCase>,
-        lazy: bool,
-    },
-    Star {
-        case: Box<MatchCase>,
-        lazy: bool,
-    },
+    /// `+` or `+?`
+    OneOrMore { case: Box<MatchCase>, lazy: bool },
+    /// `*` or `*?`
+    Star { case: Box<MatchCase>, lazy: bool },
+    /// `\s`
     Whitespace,
+    /// `\S`
     NotWhitespace,
+    /// `\d`
     Decimal,
+    /// `\D`
     NotDecimal,
+    /// `\w`
     Word,
+    /// `\W`
     NotWord,
+    /// `\b`
+    WordBoundary,
+    /// `\B`
+    NotWordBoundary,
+    /// `\n`: Backreference to the n'th capture group
     Capture(usize),
+    /// `a-z`: A range of characters, inside a [`CharMatch`](Self::CharMatch)
+    ///
+    /// The bounds are compared as Unicode scalar values, so ranges
+    /// like `α-ω` or `😀-😂` work on any character
     Between(char, char),
+    /// `[...]`: Matches a character if any of the cases inside matches it
     CharMatch(Box<[MatchCase]>),
+    /// `{n,m}`: Repeats the case between `min` and `max` times
     RangeLoop {
         case: Box<MatchCase>,
         min: Option<usize>,
         max: Option<usize>,
     },
+    /// `[^...]`: Negates a [`CharMatch`](Self::CharMatch)
+    ///
+    /// Always consumes exactly one character, however many bytes it takes
     Not(Box<MatchCase>),
+    /// `(?>...)`: An atomic group. Once it matches, the rest of the
+    /// pattern can't backtrack into it to make it match differently
+    Atomic(Box<MatchCase>),
+    /// `(?(n)A|B)`: Matches `then` if the n'th group has matched, and `else_` if not
+    Conditional {
+        /// Index of the group, starting at 1
+        group: usize,
+        then: Box<MatchCase>,
+        else_: Box<MatchCase>,
+    },
 }
 
-impl MatchCase {
-    fn lazy_star_loop<'a>(
-        &'a self,
-        ctx: &mut RegexCtx<'_, 'a>,
-        lookahead: &LookAhead<'_, 'a>,
-    ) -> bool {
-        loop {
-            if ctx.borrow_shallow(|ctx| (lookahead.match_all(ctx), false)) {
-                return true;
-            }
-            let is_match = ctx.borrow_shallow(|ctx| {
-                let ret = self.matches(ctx, lookahead);
-                (ret, ret)
-            });
-            if !is_match {
-                return true;
-            }
-        }
+/// Returns true if c matches `\w`. Only ASCII letters
+/// and digits are word characters if `unicode` is false
+#[inline]
+pub(crate) fn is_word_char(c: char, unicode: bool) -> bool {
+    if unicode {
+        c.is_alphanumeric() || c == '_'
+    } else {
+        c.is_ascii_alphanumeric() || c == '_'
     }
-    fn greedy_star_loop<'a>(
-        &'a self,
-        ctx: &mut RegexCtx<'_, 'a>,
-        lookahead: &LookAhead<'_, 'a>,
-    ) -> bool {
-        let mut last_next_match = None;
+}
 
-        loop {
-            if ctx.borrow_shallow(|ctx| (lookahead.match_all(ctx), false)) {
-                last_next_match = Some(ctx.clone());
-            }
+/// Returns true if c matches `\s`. Only ASCII spaces,
+/// including the vertical tab, are matched if `unicode` is false
+#[inline]
+fn is_space(c: char, unicode: bool) -> bool {
+    if unicode {
+        c.is_whitespace()
+    } else {
+        matches!(c, ' ' | '\t'..='\r')
+    }
+}
+
+/// Normalizes the case of `c`, unless the comparison is case sensitive
+#[inline]
+pub(crate) fn fold_case(c: char, conf: &RegexConf) -> char {
+    if conf.case_sensitive {
+        c
+    } else if conf.ascii_case_insensitive || !conf.unicode {
+        c.to_ascii_lowercase()
+    } else {
+        simple_fold(c)
+    }
+}
+
+/// Unicode simple case folding of `c`
+///
+/// Characters whose lowercase form takes more than one char, like `İ`,
+/// are left as they are. Without the `unicode-case` feature, the few
+/// characters whose folding isn't their lowercase (`ς`, `ſ`, Cherokee...)
+/// are left as they are too.
+fn simple_fold(c: char) -> char {
+    #[cfg(feature = "unicode-case")]
+    if let Some(folded) = unicode_fold(c) {
+        return folded;
+    }
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
 
-            let is_match = ctx.borrow_shallow(|ctx| {
-                let ret = self.matches(ctx, lookahead);
-                (ret, ret)
+/// Simple case foldings that differ from [`char::to_lowercase`]
+#[cfg(feature = "unicode-case")]
+const FOLDS: &[(char, char)] = &[
+    ('\u{B5}', 'μ'),
+    ('ſ', 's'),
+    ('\u{345}', 'ι'),
+    ('ς', 'σ'),
+    ('ϐ', 'β'),
+    ('ϑ', 'θ'),
+    ('ϕ', 'φ'),
+    ('ϖ', 'π'),
+    ('ϰ', 'κ'),
+    ('ϱ', 'ρ'),
+    ('ϵ', 'ε'),
+    ('\u{1C80}', 'в'),
+    ('\u{1C81}', 'д'),
+    ('\u{1C82}', 'о'),
+    ('\u{1C83}', 'с'),
+    ('\u{1C84}', 'т'),
+    ('\u{1C85}', 'т'),
+    ('\u{1C86}', 'ъ'),
+    ('\u{1C87}', 'ѣ'),
+    ('\u{1C88}', '\u{A64B}'),
+    ('ẛ', 'ṡ'),
+    ('\u{1FBE}', 'ι'),
+];
+
+#[cfg(feature = "unicode-case")]
+fn unicode_fold(c: char) -> Option<char> {
+    let shift = |c: char, from: u32, to: u32| char::from_u32(c as u32 - from + to);
+    match c {
+        // Cherokee folds to uppercase
+        '\u{13A0}'..='\u{13F5}' => Some(c),
+        '\u{13F8}'..='\u{13FD}' => shift(c, 0x13F8, 0x13F0),
+        '\u{AB70}'..='\u{ABBF}' => shift(c, 0xAB70, 0x13A0),
+        _ => FOLDS
+            .binary_search_by_key(&c, |&(from, _)| from)
+            .ok()
+            .map(|i| FOLDS[i].1),
+    }
+}
+
+impl MatchCase {
+    /// Returns true if this case matches deterministically, consuming
+    /// a fixed part of the input, so there's nothing to backtrack into
+    pub(crate) fn is_single(&self) -> bool {
+        // Groups don't nest here, so matching one stays within the depth limit
+        match self {
+            MatchCase::Group { case, .. } => match &**case {
+                MatchCase::List(cases) => cases.iter().all(MatchCase::is_leaf),
+                case => case.is_leaf(),
+            },
+            case => case.is_leaf(),
+        }
+    }
+    fn is_leaf(&self) -> bool {
+        matches!(
+            self,
+            MatchCase::Char(_)
+                | MatchCase::AnyOne
+                | MatchCase::Whitespace
+                | MatchCase::NotWhitespace
+                | MatchCase::Decimal
+                | MatchCase::NotDecimal
+                | MatchCase::Word
+                | MatchCase::NotWord
+                | MatchCase::WordBoundary
+                | MatchCase::NotWordBoundary
+                | MatchCase::Start
+                | MatchCase::End
+                | MatchCase::InputStart
+                | MatchCase::InputEnd
+                | MatchCase::InputEndOrNewline
+                | MatchCase::PrevMatchEnd
+                | MatchCase::Between(..)
+                | MatchCase::CharMatch(_)
+                | MatchCase::Not(_)
+                | MatchCase::Capture(_)
+        )
+    }
+    /// Starts matching this case, followed by the lookahead at the index
+    /// `then`, which is the rest of the pattern. Returns what to do next
+    ///
+    /// A case that can match in several ways takes the first one, and
+    /// saves the others, to go back to them if the rest doesn't match
+    pub(crate) fn start<'a>(&'a self, ctx: &mut RegexCtx<'a>, then: usize) -> Task<'a> {
+        #[cfg(all(feature = "diagnostics", feature = "std"))]
+        let then = ctx.trace_enter(self, then);
+        if self.is_single() {
+            let ret = self.match_single(ctx);
+            #[cfg(all(feature = "diagnostics", feature = "std"))]
+            ctx.trace(|offset| TraceEvent::Exit {
+                case: self,
+                offset,
+                matched: ret,
             });
-            if !is_match {
-                if let Some(it) = last_next_match {
-                    *ctx = it;
+            return if ret { Task::Then(then) } else { Task::Fail };
+        }
+        match self {
+            MatchCase::Group { case, capture_id } => {
+                ctx.start_capture(*capture_id, ctx.char_offset());
+                Task::Match(case, ctx.push_frame(LookAheadKind::EndCapture, then))
+            }
+            MatchCase::List(cases) => match_list(cases, ctx, then),
+            MatchCase::Or(branches) => ctx.branch(branches, 0, then),
+            MatchCase::Opt(case) => {
+                ctx.save(Alternative::Then(then));
+                Task::Match(case, then)
+            }
+            MatchCase::OneOrMore { .. } | MatchCase::Star { .. } | MatchCase::RangeLoop { .. } => {
+                self.repeat(ctx, 0, then)
+            }
+            MatchCase::Atomic(case) => {
+                let saved = ctx.saved_len();
+                Task::Match(
+                    case,
+                    ctx.push_frame(LookAheadKind::EndAtomic { saved }, then),
+                )
+            }
+            MatchCase::Conditional {
+                group,
+                then: yes,
+                else_,
+            } => {
+                if ctx.get_capture(*group).is_some() {
+                    Task::Match(yes, then)
+                } else {
+                    Task::Match(else_, then)
                 }
-                return true;
             }
+            _ => unreachable!(),
         }
     }
-    fn star_loop<'a>(
-        &'a self,
-        ctx: &mut RegexCtx<'_, 'a>,
-        lazy: bool,
-        lookahead: &LookAhead<'_, 'a>,
-    ) -> bool {
-        if lazy {
-            self.lazy_star_loop(ctx, lookahead)
-        } else {
-            self.greedy_star_loop(ctx, lookahead)
+    /// Matches a single case, without the rest of the pattern.
+    /// Used to repeat it in a loop
+    pub(crate) fn matches_single<'a>(&'a self, ctx: &mut RegexCtx<'a>) -> bool {
+        if !ctx.step() {
+            return false;
         }
+        #[cfg(all(feature = "diagnostics", feature = "std"))]
+        ctx.trace(|offset| TraceEvent::Enter { case: self, offset });
+        let ret = self.match_single(ctx);
+        #[cfg(all(feature = "diagnostics", feature = "std"))]
+        ctx.trace(|offset| TraceEvent::Exit {
+            case: self,
+            offset,
+            matched: ret,
+        });
+        ret
     }
-    #[allow(clippy::too_many_lines)]
-    pub(crate) fn matches<'a>(
-        &'a self,
-        ctx: &mut RegexCtx<'_, 'a>,
-        lookahead: &LookAhead<'_, 'a>,
-    ) -> bool {
+    /// Matches a case for which [`is_single`](Self::is_single) is true
+    pub(crate) fn match_single<'a>(&'a self, ctx: &mut RegexCtx<'a>) -> bool {
         macro_rules! next {
             () => {{
                 let Some(ch) = ctx.next_char() else {
@@ -111,137 +310,562 @@ impl MatchCase {
         }
 
         match self {
-            MatchCase::Char(expected) => next!() == *expected,
-            MatchCase::Whitespace => next!().is_whitespace(),
-            MatchCase::NotWhitespace => !next!().is_whitespace(),
-            MatchCase::Decimal => next!().is_digit(10),
-            MatchCase::Word => {
+            MatchCase::Char(expected) => next!() == fold_case(*expected, &ctx.conf()),
+            MatchCase::Whitespace => is_space(next!(), ctx.conf().unicode),
+            MatchCase::NotWhitespace => {
                 let c = next!();
-                c.is_alphanumeric() || c == '_'
+                !is_space(c, ctx.conf().unicode) && ctx.in_alphabet(c)
             }
+            MatchCase::Decimal => next!().is_digit(10),
+            MatchCase::Word => is_word_char(next!(), ctx.conf().unicode),
             MatchCase::NotWord => {
                 let c = next!();
-                !c.is_alphanumeric() && c != '_'
+                !is_word_char(c, ctx.conf().unicode) && ctx.in_alphabet(c)
             }
-            MatchCase::NotDecimal => !next!().is_digit(10),
-            MatchCase::Group { case, capture_id } => {
-                let curr = ctx.char_iter();
-                ctx.start_capture(*capture_id, curr);
-                let ret = case.matches(ctx, lookahead);
-                ctx.end_capture(&ctx.char_iter());
-                ret
+            MatchCase::WordBoundary => ctx.at_word_boundary(),
+            MatchCase::NotWordBoundary => !ctx.at_word_boundary(),
+            MatchCase::NotDecimal => {
+                let c = next!();
+                !c.is_ascii_digit() && ctx.in_alphabet(c)
             }
-            MatchCase::List(cases) => {
-                for i in 0..cases.len() {
-                    let rem = cases.get(i + 1..).unwrap_or(&[]);
-                    let look = LookAhead::new(LookAheadKind::List(rem), Some(lookahead));
-                    let is_match = cases[i].matches(ctx, &look);
-                    if !is_match {
-                        return false;
-                    }
-                }
-                true
+            MatchCase::AnyOne => {
+                let start = ctx.char_offset();
+                let c = next!();
+                (c != ctx.conf().line_end() || ctx.conf().dot_all)
+                    && ctx.in_alphabet(c)
+                    && ctx.skip_grapheme(start)
             }
-            MatchCase::Or(l) => l.iter().any(|rule| {
-                ctx.borrow_shallow(|newit| {
-                    let ret = rule.matches(newit, lookahead);
-                    (ret, ret)
-                })
-            }),
-            MatchCase::Opt(c) => {
-                ctx.borrow_shallow(|newit| {
-                    if c.matches(newit, lookahead)
-                        && lookahead.match_all(&mut newit.shallow_clone())
-                    {
-                        ((), true)
-                    } else {
-                        ((), false)
-                    }
-                });
-                true
+            MatchCase::Start => {
+                let conf = ctx.conf();
+                ctx.char_offset() == 0
+                    || (conf.multiline && ctx.prev_char() == Some(conf.line_end()))
             }
-            MatchCase::AnyOne => ctx.next_char().is_some(),
-            MatchCase::OneOrMore { case, lazy } => {
-                if !case.matches(ctx, lookahead) {
-                    return false;
-                }
-
-                case.star_loop(ctx, *lazy, lookahead)
-            }
-            MatchCase::Star { case, lazy } => case.star_loop(ctx, *lazy, lookahead),
-            MatchCase::Start => ctx.char_offset() == 0,
-            MatchCase::End => ctx.next_char().is_none(),
+            MatchCase::End => match ctx.peek_char() {
+                None => true,
+                Some(c) => c == ctx.conf().line_end() && ctx.conf().multiline,
+            },
+            MatchCase::InputStart => ctx.char_offset() == 0,
+            MatchCase::PrevMatchEnd => ctx.at_prev_match_end(),
+            MatchCase::InputEnd => ctx.peek_char().is_none(),
+            MatchCase::InputEndOrNewline => ctx.probe(|ctx| match ctx.next_char() {
+                None => true,
+                Some(c) => c == ctx.conf().line_end() && ctx.peek_char().is_none(),
+            }),
             MatchCase::Between(start, end) => {
                 let c = next!();
-                let (start, end) = if ctx.conf().case_sensitive {
-                    (*start, *end)
-                } else {
-                    (
-                        start.to_lowercase().next().unwrap_or(*start),
-                        end.to_lowercase().next().unwrap_or(*end),
-                    )
-                };
+                let conf = ctx.conf();
+                let (start, end) = (fold_case(*start, &conf), fold_case(*end, &conf));
                 c >= start && c <= end
             }
-            MatchCase::Not(match_case) => match ctx.peek_char() {
-                Some(_) => !match_case.matches(ctx, lookahead),
+            MatchCase::Not(case) => match ctx.peek_char() {
+                Some(c) => ctx.in_alphabet(c) && !case.match_single(ctx),
                 None => false,
             },
             MatchCase::CharMatch(cases) => {
                 let ret = cases
                     .iter()
-                    .any(|case| case.matches(&mut ctx.shallow_clone(), lookahead));
+                    .any(|case| ctx.probe(|ctx| case.match_single(ctx)));
                 ctx.next_char();
                 ret
             }
-            MatchCase::RangeLoop { case, min, max } => {
-                let mut n = 0;
-
-                if let Some(min) = min {
-                    for i in 0..*min {
-                        let look = LookAhead::new(
-                            LookAheadKind::Repeat {
-                                m: case,
-                                num: *min - i - 1,
-                            },
-                            Some(lookahead),
-                        );
-                        if !case.matches(ctx, &look) {
-                            return false;
-                        }
-                        n += 1;
-                    }
-                }
+            // Only inside a group. See `is_single`
+            MatchCase::List(cases) => cases.iter().all(|case| case.matches_single(ctx)),
+            MatchCase::Group { case, capture_id } => {
+                ctx.start_capture(*capture_id, ctx.char_offset());
+                let ret = case.matches_single(ctx);
+                ctx.end_capture(ctx.char_offset());
+                ret
+            }
+            MatchCase::Capture(n) => {
+                let conf = ctx.conf();
+                let Some(capture) = ctx.get_capture(*n) else {
+                    return false;
+                };
+                capture.chars().all(|c| next!() == fold_case(c, &conf))
+            }
+            _ => unreachable!(),
+        }
+    }
+    /// Returns the case this loop repeats, the minimum and maximum
+    /// number of times it must repeat, and whether the loop is lazy
+    fn loop_parts(&self) -> (&MatchCase, usize, Option<usize>, bool) {
+        match self {
+            MatchCase::OneOrMore { case, lazy } => (case, 1, None, *lazy),
+            MatchCase::Star { case, lazy } => (case, 0, None, *lazy),
+            MatchCase::RangeLoop { case, min, max } => (case, min.unwrap_or(0), *max, false),
+            _ => unreachable!(),
+        }
+    }
+    /// Minimum number of times this loop must repeat its case
+    pub(crate) fn min_repetitions(&self) -> usize {
+        self.loop_parts().1
+    }
+    /// Goes on with this loop, whose case has already matched `n`
+    /// times, followed by the lookahead at `then`
+    ///
+    /// Greedy loops try one more repetition before the rest of the pattern,
+    /// and lazy ones the other way around. An iteration that matches nothing
+    /// past the minimum ends the loop, since it would be repeated forever.
+    pub(crate) fn repeat<'a>(&'a self, ctx: &mut RegexCtx<'a>, n: usize, then: usize) -> Task<'a> {
+        let (case, min, max, lazy) = self.loop_parts();
+        if n < min {
+            self.iterate(ctx, n, then)
+        } else if max.is_some_and(|max| n >= max) {
+            Task::Then(then)
+        } else if lazy {
+            ctx.save(Alternative::Iterate {
+                case: self,
+                n,
+                then,
+            });
+            Task::Then(then)
+        } else if case.is_single() {
+            case.repeat_greedy(ctx, n, max, then)
+        } else {
+            ctx.save(Alternative::Then(then));
+            self.iterate(ctx, n, then)
+        }
+    }
+    /// Matches one more iteration of this loop. See [`repeat`](Self::repeat)
+    ///
+    /// Cases there's no need to backtrack into are matched right
+    /// away, and the others before a lookahead that goes on with
+    /// the loop once they match
+    pub(crate) fn iterate<'a>(&'a self, ctx: &mut RegexCtx<'a>, n: usize, then: usize) -> Task<'a> {
+        let (case, min, ..) = self.loop_parts();
+        let start = ctx.char_offset();
+        if !case.is_single() {
+            let kind = LookAheadKind::Loop {
+                case: self,
+                n: n + 1,
+                start,
+            };
+            return Task::Match(case, ctx.push_frame(kind, then));
+        }
+        if !case.matches_single(ctx) || (n >= min && ctx.char_offset() == start) {
+            return Task::Fail;
+        }
+        self.repeat(ctx, n + 1, then)
+    }
+    /// Greedy [`repeat`](Self::repeat) of a case there's no need to backtrack
+    /// into. Takes as many repetitions as possible, in a loop, saving the
+    /// position before each one, to give them back until the rest matches
+    fn repeat_greedy<'a>(
+        &'a self,
+        ctx: &mut RegexCtx<'a>,
+        mut n: usize,
+        max: Option<usize>,
+        then: usize,
+    ) -> Task<'a> {
+        let from = ctx.positions_len();
+        while max.is_none_or(|max| n < max) {
+            let offset = ctx.char_offset();
+            ctx.push_position();
+            if !self.matches_single(ctx) || ctx.char_offset() == offset {
+                ctx.pop_position();
+                break;
+            }
+            n += 1;
+        }
+        ctx.give_back(then, from);
+        Task::Then(then)
+    }
+}
 
-                loop {
-                    if max.is_some_and(|max| n >= max) {
-                        break;
-                    }
+impl MatchCase {
+    /// Returns the name of the variant, like `"Char"` or `"Star"`
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("a*").unwrap();
+    /// assert_eq!(regex.ast()[0].name(), "Star");
+    /// ```
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            MatchCase::Start => "Start",
+            MatchCase::End => "End",
+            MatchCase::InputStart => "InputStart",
+            MatchCase::InputEnd => "InputEnd",
+            MatchCase::InputEndOrNewline => "InputEndOrNewline",
+            MatchCase::PrevMatchEnd => "PrevMatchEnd",
+            MatchCase::Char(_) => "Char",
+            MatchCase::List(_) => "List",
+            MatchCase::Group { .. } => "Group",
+            MatchCase::Or(_) => "Or",
+            MatchCase::AnyOne => "AnyOne",
+            MatchCase::Opt(_) => "Opt",
+            MatchCase::OneOrMore { .. } => "OneOrMore",
+            MatchCase::Star { .. } => "Star",
+            MatchCase::Whitespace => "Whitespace",
+            MatchCase::NotWhitespace => "NotWhitespace",
+            MatchCase::Decimal => "Decimal",
+            MatchCase::NotDecimal => "NotDecimal",
+            MatchCase::Word => "Word",
+            MatchCase::NotWord => "NotWord",
+            MatchCase::WordBoundary => "WordBoundary",
+            MatchCase::NotWordBoundary => "NotWordBoundary",
+            MatchCase::Capture(_) => "Capture",
+            MatchCase::Between(..) => "Between",
+            MatchCase::CharMatch(_) => "CharMatch",
+            MatchCase::RangeLoop { .. } => "RangeLoop",
+            MatchCase::Not(_) => "Not",
+            MatchCase::Atomic(_) => "Atomic",
+            MatchCase::Conditional { .. } => "Conditional",
+        }
+    }
+    /// Minimum number of characters this case consumes when it matches
+    pub(crate) fn min_len(&self) -> usize {
+        match self {
+            MatchCase::Char(_)
+            | MatchCase::AnyOne
+            | MatchCase::Whitespace
+            | MatchCase::NotWhitespace
+            | MatchCase::Decimal
+            | MatchCase::NotDecimal
+            | MatchCase::Word
+            | MatchCase::NotWord
+            | MatchCase::Between(..)
+            | MatchCase::CharMatch(_)
+            | MatchCase::Not(_) => 1,
+            MatchCase::Start
+            | MatchCase::End
+            | MatchCase::InputStart
+            | MatchCase::InputEnd
+            | MatchCase::InputEndOrNewline
+            | MatchCase::PrevMatchEnd
+            | MatchCase::WordBoundary
+            | MatchCase::NotWordBoundary
+            | MatchCase::Opt(_)
+            | MatchCase::Star { .. }
+            | MatchCase::Capture(_) => 0,
+            MatchCase::List(cases) => min_len(cases),
+            MatchCase::Group { case, .. }
+            | MatchCase::Atomic(case)
+            | MatchCase::OneOrMore { case, .. } => case.min_len(),
+            MatchCase::Or(branches) => branches.iter().map(MatchCase::min_len).min().unwrap_or(0),
+            MatchCase::RangeLoop { case, min, .. } => {
+                case.min_len().saturating_mul(min.unwrap_or(0))
+            }
+            MatchCase::Conditional { then, else_, .. } => then.min_len().min(else_.min_len()),
+        }
+    }
+    /// Maximum number of characters this case consumes when it
+    /// matches, or [None] if there's no limit
+    pub(crate) fn max_len(&self) -> Option<usize> {
+        let unbounded = |case: &MatchCase| match case.max_len() {
+            Some(0) => Some(0),
+            _ => None,
+        };
+        match self {
+            MatchCase::Char(_)
+            | MatchCase::AnyOne
+            | MatchCase::Whitespace
+            | MatchCase::NotWhitespace
+            | MatchCase::Decimal
+            | MatchCase::NotDecimal
+            | MatchCase::Word
+            | MatchCase::NotWord
+            | MatchCase::Between(..)
+            | MatchCase::CharMatch(_)
+            | MatchCase::Not(_) => Some(1),
+            MatchCase::Start
+            | MatchCase::End
+            | MatchCase::InputStart
+            | MatchCase::InputEnd
+            | MatchCase::InputEndOrNewline
+            | MatchCase::PrevMatchEnd
+            | MatchCase::WordBoundary
+            | MatchCase::NotWordBoundary => Some(0),
+            // The captured text could be as long as the whole input
+            MatchCase::Capture(_) => None,
+            MatchCase::List(cases) => max_len(cases),
+            MatchCase::Group { case, .. } | MatchCase::Atomic(case) | MatchCase::Opt(case) => {
+                case.max_len()
+            }
+            MatchCase::OneOrMore { case, .. } | MatchCase::Star { case, .. } => unbounded(case),
+            MatchCase::Or(branches) => branches
+                .iter()
+                .try_fold(0, |max, case| Some(max.max(case.max_len()?))),
+            MatchCase::RangeLoop { case, max, .. } => match max {
+                Some(max) => case.max_len()?.checked_mul(*max),
+                None => unbounded(case),
+            },
+            MatchCase::Conditional { then, else_, .. } => {
+                Some(then.max_len()?.max(else_.max_len()?))
+            }
+        }
+    }
+}
 
-                    if !ctx.borrow_shallow(|it| {
-                        let ret = case.matches(it, lookahead);
-                        (ret, ret)
-                    }) {
-                        break;
-                    }
+/// Minimum number of characters a sequence of cases consumes when it matches
+pub(crate) fn min_len(cases: &[MatchCase]) -> usize {
+    cases
+        .iter()
+        .fold(0, |len, case| len.saturating_add(case.min_len()))
+}
 
-                    n += 1;
-                }
+/// Maximum number of characters a sequence of cases consumes when
+/// it matches, or [None] if there's no limit
+pub(crate) fn max_len(cases: &[MatchCase]) -> Option<usize> {
+    cases
+        .iter()
+        .try_fold(0usize, |len, case| len.checked_add(case.max_len()?))
+}
 
-                true
+/// Returns true if a sequence of cases can only match at the start of the input
+pub(crate) fn anchored_start(cases: &[MatchCase], multiline: bool) -> bool {
+    cases.first().is_some_and(|case| match case {
+        MatchCase::InputStart => true,
+        MatchCase::Start => !multiline,
+        MatchCase::List(cases) => anchored_start(cases, multiline),
+        MatchCase::Group { case, .. } | MatchCase::Atomic(case) => {
+            anchored_start(core::slice::from_ref(case), multiline)
+        }
+        MatchCase::Or(branches) => branches
+            .iter()
+            .all(|case| anchored_start(core::slice::from_ref(case), multiline)),
+        _ => false,
+    })
+}
+
+/// Returns true if a sequence of cases can only match at the end of the input
+pub(crate) fn anchored_end(cases: &[MatchCase], multiline: bool) -> bool {
+    cases.last().is_some_and(|case| match case {
+        MatchCase::InputEnd => true,
+        MatchCase::End => !multiline,
+        MatchCase::List(cases) => anchored_end(cases, multiline),
+        MatchCase::Group { case, .. } | MatchCase::Atomic(case) => {
+            anchored_end(core::slice::from_ref(case), multiline)
+        }
+        MatchCase::Or(branches) => branches
+            .iter()
+            .all(|case| anchored_end(core::slice::from_ref(case), multiline)),
+        _ => false,
+    })
+}
+
+/// Matches a list of cases, followed by the lookahead at `then`
+///
+/// The cases there's no need to backtrack into are matched right away,
+/// up to the first one that isn't, which is followed by a lookahead
+/// with the rest of the list
+pub(crate) fn match_list<'a>(
+    mut cases: &'a [MatchCase],
+    ctx: &mut RegexCtx<'a>,
+    then: usize,
+) -> Task<'a> {
+    while let Some((first, rest)) = cases.split_first() {
+        if !first.is_single() {
+            if rest.is_empty() {
+                return Task::Match(first, then);
             }
-            MatchCase::Capture(n) => {
-                let case_sensitive = ctx.conf().case_sensitive;
-                ctx.get_capture(*n)
-                    .chars()
-                    .map(|c| {
-                        if case_sensitive {
-                            c
-                        } else {
-                            c.to_lowercase().next().unwrap_or(c)
+            return Task::Match(first, ctx.push_frame(LookAheadKind::List(rest), then));
+        }
+        if !first.matches_single(ctx) {
+            return Task::Fail;
+        }
+        cases = rest;
+    }
+    Task::Then(then)
+}
+
+/// Returns true if a sequence of cases depends on what the groups
+/// captured, through backreferences or conditionals
+pub(crate) fn reads_groups(cases: &[MatchCase]) -> bool {
+    cases.iter().any(|case| match case {
+        MatchCase::Capture(_) | MatchCase::Conditional { .. } => true,
+        MatchCase::List(cases) | MatchCase::Or(cases) => reads_groups(cases),
+        MatchCase::Group { case, .. }
+        | MatchCase::Opt(case)
+        | MatchCase::OneOrMore { case, .. }
+        | MatchCase::Star { case, .. }
+        | MatchCase::RangeLoop { case, .. }
+        | MatchCase::Atomic(case) => reads_groups(core::slice::from_ref(case)),
+        _ => false,
+    })
+}
+
+/// Characters that must be escaped to be matched literally
+pub(crate) const META_CHARS: &str = ".^$*+?()[]{}|\\";
+
+/// Writes a sequence of cases. An alternation in it is wrapped in a
+/// group that doesn't capture, unless it's the only case, so it doesn't
+/// take the rest of the sequence as branches
+fn write_list(f: &mut fmt::Formatter<'_>, cases: &[MatchCase]) -> fmt::Result {
+    for (i, case) in cases.iter().enumerate() {
+        match case {
+            // A digit written right after the backreference, even a
+            // quantified one, would be read as part of its number
+            MatchCase::Capture(n) if cases.get(i + 1).is_some_and(starts_with_digit) => {
+                write!(f, "\\k<{n}>")?;
+            }
+            MatchCase::Or(_) if cases.len() > 1 => write!(f, "(?:{case})")?,
+            MatchCase::List(_) => write!(f, "(?:{case})")?,
+            _ => write!(f, "{case}")?,
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if the pattern of the case starts with a digit
+fn starts_with_digit(case: &MatchCase) -> bool {
+    case.to_string().starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Writes a branch of an alternation or a conditional.
+/// An alternation in it must be grouped, not to add branches to the outer one
+fn write_branch(f: &mut fmt::Formatter<'_>, case: &MatchCase) -> fmt::Result {
+    match case {
+        MatchCase::Or(_) => write!(f, "(?:{case})"),
+        MatchCase::List(cases) if matches!(&**cases, [MatchCase::Or(_)]) => {
+            write!(f, "(?:{case})")
+        }
+        case => write!(f, "{case}"),
+    }
+}
+
+/// Writes the case a quantifier applies to. If it's made of several
+/// cases, or is quantified itself, it's wrapped in a group that doesn't
+/// capture, so the quantifier applies to all of it
+fn write_quantified(f: &mut fmt::Formatter<'_>, case: &MatchCase) -> fmt::Result {
+    match case {
+        MatchCase::List(_)
+        | MatchCase::Or(_)
+        | MatchCase::Opt(_)
+        | MatchCase::Star { .. }
+        | MatchCase::OneOrMore { .. }
+        | MatchCase::RangeLoop { .. } => write!(f, "(?:{case})"),
+        case => write!(f, "{case}"),
+    }
+}
+
+/// Builds the pattern for a list of cases
+pub(crate) fn to_pattern(cases: &[MatchCase]) -> String {
+    struct Pattern<'a>(&'a [MatchCase]);
+    impl Display for Pattern<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write_list(f, self.0)
+        }
+    }
+    Pattern(cases).to_string()
+}
+
+fn write_class(f: &mut fmt::Formatter<'_>, cases: &[MatchCase], negated: bool) -> fmt::Result {
+    f.write_char('[')?;
+    if negated {
+        f.write_char('^')?;
+    }
+    let write_char = |f: &mut fmt::Formatter<'_>, c: char, first: bool| {
+        if matches!(c, ']' | '\\' | '-') || (c == '^' && first && !negated) {
+            f.write_char('\\')?;
+        }
+        f.write_char(c)
+    };
+    for (i, case) in cases.iter().enumerate() {
+        match case {
+            MatchCase::Char(c) => write_char(f, *c, i == 0)?,
+            MatchCase::Between(start, end) => {
+                write_char(f, *start, i == 0)?;
+                f.write_char('-')?;
+                write_char(f, *end, false)?;
+            }
+            case => write!(f, "{case}")?,
+        }
+    }
+    f.write_char(']')
+}
+
+/// Writes the [`MatchCase`] as a pattern that compiles back to it
+impl Display for MatchCase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lazy = |f: &mut fmt::Formatter<'_>, lazy: bool| {
+            if lazy { f.write_char('?') } else { Ok(()) }
+        };
+        match self {
+            MatchCase::Start => f.write_char('^'),
+            MatchCase::End => f.write_char('$'),
+            MatchCase::InputStart => f.write_str("\\A"),
+            MatchCase::PrevMatchEnd => f.write_str("\\G"),
+            MatchCase::InputEnd => f.write_str("\\z"),
+            MatchCase::InputEndOrNewline => f.write_str("\\Z"),
+            MatchCase::Char(c) => {
+                if META_CHARS.contains(*c) {
+                    f.write_char('\\')?;
+                }
+                f.write_char(*c)
+            }
+            MatchCase::List(cases) => write_list(f, cases),
+            MatchCase::Group { case, .. } => write!(f, "({case})"),
+            MatchCase::Or(cases) => {
+                for (i, case) in cases.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char('|')?;
+                    }
+                    write_branch(f, case)?;
+                }
+                Ok(())
+            }
+            MatchCase::AnyOne => f.write_char('.'),
+            MatchCase::Opt(case) => {
+                write_quantified(f, case)?;
+                f.write_char('?')
+            }
+            MatchCase::OneOrMore { case, lazy: l } => {
+                write_quantified(f, case)?;
+                f.write_char('+')?;
+                lazy(f, *l)
+            }
+            MatchCase::Star { case, lazy: l } => {
+                write_quantified(f, case)?;
+                f.write_char('*')?;
+                lazy(f, *l)
+            }
+            MatchCase::Whitespace => f.write_str("\\s"),
+            MatchCase::NotWhitespace => f.write_str("\\S"),
+            MatchCase::Decimal => f.write_str("\\d"),
+            MatchCase::NotDecimal => f.write_str("\\D"),
+            MatchCase::Word => f.write_str("\\w"),
+            MatchCase::NotWord => f.write_str("\\W"),
+            MatchCase::WordBoundary => f.write_str("\\b"),
+            MatchCase::NotWordBoundary => f.write_str("\\B"),
+            MatchCase::Capture(n) => write!(f, "\\{n}"),
+            MatchCase::Atomic(case) => write!(f, "(?>{case})"),
+            MatchCase::Conditional { group, then, else_ } => {
+                write!(f, "(?({group})")?;
+                write_branch(f, then)?;
+                if !matches!(&**else_, MatchCase::List(cases) if cases.is_empty()) {
+                    f.write_char('|')?;
+                    write_branch(f, else_)?;
+                }
+                f.write_char(')')
+            }
+            MatchCase::Between(start, end) => {
+                write_class(f, &[MatchCase::Between(*start, *end)], false)
+            }
+            MatchCase::CharMatch(cases) => write_class(f, cases, false),
+            MatchCase::Not(case) => match &**case {
+                MatchCase::CharMatch(cases) => write_class(f, cases, true),
+                case => write_class(f, core::slice::from_ref(case), true),
+            },
+            MatchCase::RangeLoop { case, min, max } => {
+                write_quantified(f, case)?;
+                f.write_char('{')?;
+                match (min, max) {
+                    (Some(min), Some(max)) if min == max => write!(f, "{min}")?,
+                    _ => {
+                        if let Some(min) = min {
+                            write!(f, "{min}")?;
+                        }
+                        f.write_char(',')?;
+                        if let Some(max) = max {
+                            write!(f, "{max}")?;
                         }
-                    })
-                    .all(|c| next!() == c)
+                    }
+                }
+                f.write_char('}')
             }
         }
     }
@@ -0,0 +1,86 @@
+use alloc::string::String;
+
+use crate::{MatchEvent, Regex, RegexConf, StreamingMatcher};
+
+/// Number of characters taken from the iterator at a time
+const CHUNK_CHARS: usize = 1024;
+
+/// Iterator over the matches of a [Regex] on a sequence of [chars](char)
+///
+/// Useful when the characters are produced lazily, for example decoded
+/// from another source, so the input doesn't need to be collected into
+/// a single string. The characters are taken in chunks, and fed to a
+/// [`StreamingMatcher`].
+///
+/// Yields the spans of the matches, as byte offsets of the input encoded
+/// as UTF-8. The input that can't be part of any future match is discarded,
+/// but the text of a match, or of a partial match, is kept until it's
+/// decided, since backreferences need to read it again. So the memory
+/// used grows with the size of the longest match, not with the whole input.
+/// See [`StreamingMatcher`] for the details.
+///
+/// # Example
+/// ```
+/// use regexpr::Regex;
+///
+/// let regex = Regex::compile("(a+)b\\1").unwrap();
+/// let mut chars = "xaabaa_aba".chars();
+/// let spans: Vec<_> = regex.find_matches_chars(&mut chars).collect();
+/// assert_eq!(spans, [(1, 6), (7, 10)]);
+/// ```
+#[derive(Debug)]
+pub struct CharsMatcher<'r, I> {
+    stream: StreamingMatcher<'r>,
+    chars: I,
+    chunk: String,
+}
+
+impl<'r, I: Iterator<Item = char>> CharsMatcher<'r, I> {
+    #[must_use]
+    pub fn new(regex: &'r Regex, chars: I, conf: RegexConf) -> Self {
+        CharsMatcher {
+            stream: StreamingMatcher::new(regex, conf),
+            chars,
+            chunk: String::new(),
+        }
+    }
+
+    /// Returns the input taken so far that hasn't been discarded yet
+    ///
+    /// See [`StreamingMatcher::input`]
+    #[must_use]
+    pub fn input(&self) -> &str {
+        self.stream.input()
+    }
+
+    /// Returns the offset of [`input`](Self::input) in the whole input
+    #[must_use]
+    pub fn input_offset(&self) -> usize {
+        self.stream.input_offset()
+    }
+
+    /// Takes the next chunk of characters, and feeds it to the stream
+    fn fill(&mut self) {
+        self.chunk.clear();
+        self.chunk.extend(self.chars.by_ref().take(CHUNK_CHARS));
+        if self.chunk.is_empty() {
+            self.stream.finish();
+        } else {
+            self.stream.feed(&self.chunk);
+        }
+    }
+}
+
+impl<I: Iterator<Item = char>> Iterator for CharsMatcher<'_, I> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stream.poll()? {
+                MatchEvent::Match { span } => return Some(span),
+                MatchEvent::NoMatch => return None,
+                MatchEvent::NeedMore => self.fill(),
+            }
+        }
+    }
+}
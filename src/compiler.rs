@@ -3,29 +3,51 @@ use alloc::vec::Vec;
 use core::str::Chars;
 use std::collections::HashMap;
 
-use crate::Regex;
-use crate::Result;
 use crate::case::MatchCase;
+use crate::{InlineFlags, Regex, RegexError, RegexErrorKind, Result};
 
 type OrList = Vec<MatchCase>;
 type RegexCompilerScope = (Vec<MatchCase>, Option<OrList>, Option<usize>);
 
+/// Scopes that don't build a plain group when closed
+enum SpecialScope {
+    /// `(?(n)A|B)`, on the given group
+    Condition(usize),
+    /// `(?>...)`
+    Atomic,
+    /// `(?:...)`
+    NonCapturing,
+}
+
 pub struct RegexCompiler<'a> {
+    src: &'a str,
     chars: Chars<'a>,
     open: usize,
     accc: Vec<RegexCompilerScope>,
     captures_map: HashMap<String, usize>,
     n_captures: usize,
+    /// Backreferences found, and whether their group was closed before them
+    backrefs: Vec<(usize, bool)>,
+    /// Groups referenced by the conditionals
+    conditions: Vec<usize>,
+    /// Depth and kind of the special scopes being parsed
+    open_special: Vec<(usize, SpecialScope)>,
+    flags: InlineFlags,
 }
 
 impl<'a> RegexCompiler<'a> {
     pub fn new(src: &'a str) -> Self {
         let mut compiler = RegexCompiler {
+            src,
             chars: src.chars(),
             open: 0,
             accc: Vec::new(),
             n_captures: 0,
             captures_map: HashMap::new(),
+            backrefs: Vec::new(),
+            conditions: Vec::new(),
+            open_special: Vec::new(),
+            flags: InlineFlags::default(),
         };
         compiler
             .enter_scope(false)
@@ -34,16 +56,29 @@ impl<'a> RegexCompiler<'a> {
     }
     fn enter_scope(&mut self, capt: bool) -> Result<()> {
         self.open += 1;
+        // The outermost scope is the pattern itself
+        if self.open > MAX_NESTING + 1 {
+            return Err(RegexError::new(
+                RegexErrorKind::TooDeep,
+                format!("More than {MAX_NESTING} nested groups"),
+            ));
+        }
         let cid = if capt {
             self.n_captures += 1;
 
             if self.chars.clone().next().is_some_and(|c| c == '?') {
                 self.chars.next();
                 if self.chars.next().is_none_or(|c| c != '<') {
-                    return Err("Expected an opening '<'".into());
+                    return Err(RegexError::new(
+                        RegexErrorKind::BadGroupName,
+                        "Expected an opening '<'",
+                    ));
                 }
                 let Some(close) = self.chars.as_str().find('>') else {
-                    return Err("Expected closing '<'".into());
+                    return Err(RegexError::new(
+                        RegexErrorKind::BadGroupName,
+                        "Expected closing '<'",
+                    ));
                 };
                 let name = self.chars.as_str()[..close].to_string();
                 for _ in 0..=close {
@@ -58,10 +93,26 @@ impl<'a> RegexCompiler<'a> {
         self.accc.push((Vec::new(), None, cid));
         Ok(())
     }
-    fn close_scope(&mut self) -> MatchCase {
+    fn close_scope(&mut self) -> Result<MatchCase> {
+        if let Some(&(depth, _)) = self.open_special.last()
+            && depth == self.open
+        {
+            return match self.open_special.pop() {
+                Some((_, SpecialScope::Condition(group))) => self.close_condition(group),
+                Some((_, SpecialScope::Atomic)) => {
+                    Ok(MatchCase::Atomic(Box::new(self.close_scope()?)))
+                }
+                // A single case doesn't need the group
+                Some((_, SpecialScope::NonCapturing)) => Ok(match self.close_scope()? {
+                    MatchCase::List(cases) if cases.len() == 1 => cases.into_vec().remove(0),
+                    case => case,
+                }),
+                None => unreachable!(),
+            };
+        }
         self.open -= 1;
 
-        match self.accc.pop() {
+        let case = match self.accc.pop() {
             Some((acc, orlist, cid)) => {
                 let list = MatchCase::List(acc.into_boxed_slice());
                 let mut case = if let Some(mut orl) = orlist {
@@ -79,23 +130,119 @@ impl<'a> RegexCompiler<'a> {
                 case
             }
             None => unreachable!(),
-        }
+        };
+        Ok(case)
+    }
+    /// Parses the group of a `(?(n)A|B)` conditional, after the `(?(`,
+    /// and opens the scope of its branches
+    fn open_condition(&mut self) -> Result<()> {
+        let rest = self.chars.as_str();
+        let Some(close) = rest.find(')') else {
+            return Err(RegexError::new(
+                RegexErrorKind::UnterminatedGroup,
+                "Expected closing ')' after condition",
+            ));
+        };
+        let cond = &rest[..close];
+        let group = if let Ok(n) = cond.parse::<usize>() {
+            n
+        } else if let Some(name) = cond.strip_prefix('<').and_then(|c| c.strip_suffix('>')) {
+            self.group_id(name)?
+        } else {
+            return Err(RegexError::new(
+                RegexErrorKind::BadGroupName,
+                format!("Invalid condition '{cond}'"),
+            ));
+        };
+        self.chars = rest[close + 1..].chars();
+        self.conditions.push(group);
+        self.enter_scope(false)?;
+        self.open_special
+            .push((self.open, SpecialScope::Condition(group)));
+        Ok(())
+    }
+    /// Closes the scope of a conditional, that can have up to two branches
+    fn close_condition(&mut self, group: usize) -> Result<MatchCase> {
+        let (then, else_) = match self.close_scope()? {
+            MatchCase::Or(branches) => match <[MatchCase; 2]>::try_from(branches.into_vec()) {
+                Ok([then, else_]) => (then, else_),
+                Err(_) => {
+                    return Err(RegexError::new(
+                        RegexErrorKind::Other,
+                        format!("Conditional on group {group} has more than two branches"),
+                    ));
+                }
+            },
+            case => (case, MatchCase::List(Box::new([]))),
+        };
+        Ok(MatchCase::Conditional {
+            group,
+            then: Box::new(then),
+            else_: Box::new(else_),
+        })
     }
     fn last_acc(&mut self) -> &mut RegexCompilerScope {
         self.accc.last_mut().unwrap_or_else(|| unreachable!())
     }
     fn next(&mut self, c: char) -> Result<char> {
-        self.chars
-            .next()
-            .ok_or_else(|| format!("Expected character after {c}").into())
+        self.chars.next().ok_or_else(|| {
+            RegexError::new(
+                RegexErrorKind::UnterminatedClass,
+                format!("Expected character after {c}"),
+            )
+        })
+    }
+    /// Byte offset of the last character consumed
+    fn offset(&self) -> usize {
+        self.src.len() - self.chars.as_str().len() - 1
+    }
+    /// Pops the last case of the current scope, to apply the quantifier `c` to it
+    fn quantified(&mut self, c: char) -> Result<MatchCase> {
+        let pos = self.offset();
+        self.last_acc().0.pop().ok_or_else(|| {
+            RegexError::new(
+                RegexErrorKind::UnexpectedQuantifier,
+                format!("Expected pattern before '{c}' at byte {pos}"),
+            )
+        })
+    }
+    /// Consumes the character after a backslash, which must already be consumed
+    fn escaped(&mut self) -> Result<char> {
+        let pos = self.offset();
+        self.chars.next().ok_or_else(|| incomplete_escape(pos))
+    }
+    /// Parses the code point of a `\xHH`, `\x{H..}` or `\u{H..}` escape,
+    /// after the `x` or `u`. Any other escaped char `c` is returned as is
+    fn hex_escape(&mut self, c: char) -> Result<char> {
+        if c != 'x' && c != 'u' {
+            return Ok(c);
+        }
+        let pos = self.offset() - 1;
+        let rest = self.chars.as_str();
+        let (digits, len) = if let Some(braced) = rest.strip_prefix('{') {
+            let close = braced.find('}').ok_or_else(|| incomplete_escape(pos))?;
+            (&braced[..close], close + 2)
+        } else if c == 'x' {
+            let Some(digits) = rest.get(..2) else {
+                return Err(match rest.len() {
+                    0 | 1 => incomplete_escape(pos),
+                    _ => invalid_hex(pos),
+                });
+            };
+            (digits, 2)
+        } else {
+            return Err(invalid_hex(pos));
+        };
+        let code = u32::from_str_radix(digits, 16)
+            .ok()
+            .filter(|_| !digits.starts_with('+'))
+            .and_then(char::from_u32)
+            .ok_or_else(|| invalid_hex(pos))?;
+        self.chars = rest[len..].chars();
+        Ok(code)
     }
     fn multiplier(&mut self, c: char) -> Result<MatchCase> {
-        let last = self
-            .last_acc()
-            .0
-            .pop()
-            .ok_or_else(|| format!("Expected pattern before '{c}'"))?;
-        let last = Box::new(last);
+        let last = Box::new(self.quantified(c)?);
 
         let lazy = self.chars.clone().next().is_some_and(|c| c == '?');
         if lazy {
@@ -110,39 +257,34 @@ impl<'a> RegexCompiler<'a> {
         };
         Ok(case)
     }
+    /// Parses a `{n,m}` quantifier. If the text after the `{`
+    /// isn't a valid one, the `{` is taken literally
     fn repeat(&mut self, c: char) -> Result<MatchCase> {
-        let last = self
-            .last_acc()
-            .0
-            .pop()
-            .ok_or_else(|| format!("Expected pattern before '{c}'"))?;
-
         /* a{100,1000} */
 
-        let i = self.chars.as_str().find('}').ok_or("Missing closing '}'")?;
-        let slice = &self.chars.as_str()[..i];
-        let mut min = None;
-        let mut max = None;
-        if slice.contains(',') {
-            let mut split = slice.split(',');
-            let mi = split
-                .next()
-                .ok_or("Range must be split by ','. Ex: {12,15}")?;
-            let ma = split
-                .next()
-                .ok_or("Range must be split by ','. Ex: {12,15}")?;
-
-            if !mi.is_empty() {
-                min = Some(mi.parse().ok().ok_or("Error parsing number")?);
-            }
-            if !ma.is_empty() {
-                max = Some(ma.parse().ok().ok_or("Error parsing number")?);
+        let rest = self.chars.as_str();
+        let Some(i) = rest.find('}').filter(|&i| is_repetition(&rest[..i])) else {
+            return Ok(MatchCase::Char(c));
+        };
+        let slice = &rest[..i];
+
+        let last = self.quantified(c)?;
+
+        let parse = |n: &str| -> Result<Option<usize>> {
+            if n.is_empty() {
+                return Ok(None);
             }
+            n.parse()
+                .map(Some)
+                .map_err(|_| RegexError::new(RegexErrorKind::BadRepetition, "Error parsing number"))
+        };
+        let (min, max) = if let Some((min, max)) = slice.split_once(',') {
+            (parse(min)?, parse(max)?)
         } else {
-            let n = slice.parse().ok().ok_or("Error parsing number")?;
-            min = Some(n);
-            max = Some(n);
-        }
+            let n = parse(slice)?;
+            (n, n)
+        };
+        check_repetition(min, max)?;
 
         for _ in 0..=i {
             self.chars.next();
@@ -162,18 +304,39 @@ impl<'a> RegexCompiler<'a> {
         }
 
         let mut list = Vec::new();
+        // A `]` right after the opening is taken literally, like in `[]a]`
+        let mut leading_close = curr == ']';
 
-        while curr != ']' {
+        while curr != ']' || leading_close {
+            leading_close = false;
             if curr == '\\' {
-                curr = self.next(c)?;
+                curr = self.escaped()?;
+                if let Some(class) = shorthand_class(curr) {
+                    list.push(class);
+                    curr = self.next(curr)?;
+                    continue;
+                }
+                curr = self.hex_escape(curr)?;
             }
             let c = curr;
             curr = self.next(c)?;
 
             if curr == '-' {
-                let end = self.next(c)?;
+                let mut end = self.next(c)?;
                 if end == ']' {
-                    return Err("Expectend end of range [.. - ..]".into());
+                    // A `-` at the end is taken literally, like in `[a-]`
+                    list.extend([MatchCase::Char(c), MatchCase::Char('-')]);
+                    break;
+                }
+                if end == '\\' {
+                    end = self.escaped()?;
+                    if shorthand_class(end).is_some() {
+                        return Err(RegexError::new(
+                            RegexErrorKind::BadRange,
+                            format!("Invalid end of range {c}-\\{end}"),
+                        ));
+                    }
+                    end = self.hex_escape(end)?;
                 }
                 list.push(MatchCase::Between(c, end));
                 curr = self.next(c)?;
@@ -193,10 +356,11 @@ impl<'a> RegexCompiler<'a> {
     fn or(&mut self) {
         match self.accc.pop() {
             Some((mut acc, mut opt, cid)) => {
-                let m = if acc.len() > 1 {
-                    MatchCase::List(acc.into_boxed_slice())
-                } else {
+                // An empty branch is an empty list, that matches the empty string
+                let m = if acc.len() == 1 {
                     acc.remove(0)
+                } else {
+                    MatchCase::List(acc.into_boxed_slice())
                 };
                 opt.get_or_insert_with(Vec::new).push(m);
                 self.accc.push((Vec::new(), opt, cid));
@@ -204,20 +368,25 @@ impl<'a> RegexCompiler<'a> {
             None => unreachable!(),
         }
     }
-    fn escape(&mut self, c: char) -> Result<MatchCase> {
-        let next = self.next(c)?;
-        if next == 's' {
-            return Ok(MatchCase::Whitespace);
-        } else if next == 'S' {
-            return Ok(MatchCase::NotWhitespace);
-        } else if next == 'd' {
-            return Ok(MatchCase::Decimal);
-        } else if next == 'D' {
-            return Ok(MatchCase::NotDecimal);
-        } else if next == 'w' {
-            return Ok(MatchCase::Word);
-        } else if next == 'W' {
-            return Ok(MatchCase::NotWord);
+    fn escape(&mut self) -> Result<MatchCase> {
+        let pos = self.offset();
+        let next = self.escaped()?;
+        if let Some(class) = shorthand_class(next) {
+            return Ok(class);
+        } else if next == 'b' {
+            return Ok(MatchCase::WordBoundary);
+        } else if next == 'B' {
+            return Ok(MatchCase::NotWordBoundary);
+        } else if next == 'A' {
+            return Ok(MatchCase::InputStart);
+        } else if next == 'G' {
+            return Ok(MatchCase::PrevMatchEnd);
+        } else if next == 'z' {
+            return Ok(MatchCase::InputEnd);
+        } else if next == 'Z' {
+            return Ok(MatchCase::InputEndOrNewline);
+        } else if next == 'x' || next == 'u' {
+            return self.hex_escape(next).map(MatchCase::Char);
         }
 
         let mut is_cap = next.is_numeric();
@@ -232,17 +401,9 @@ impl<'a> RegexCompiler<'a> {
             let mut captn = 0;
             if named {
                 let Some(close) = self.chars.as_str().find('>') else {
-                    return Err("Expected closing '>'".into());
+                    return Err(incomplete_escape(pos));
                 };
-                let name = &self.chars.as_str()[..close];
-                if let Ok(id) = name.parse::<usize>() {
-                    captn = id;
-                } else {
-                    match self.captures_map.get(name) {
-                        Some(id) => captn = *id,
-                        None => return Err(format!("Unknown capture '{name}'").into()),
-                    }
-                }
+                captn = self.group_id(&self.chars.as_str()[..close])?;
                 for _ in 0..=close {
                     self.chars.next();
                 }
@@ -260,25 +421,96 @@ impl<'a> RegexCompiler<'a> {
                     }
                 }
             }
-            if self.n_captures < captn {
-                return Err("Trying to recall uncaptured".into());
-            }
-            MatchCase::Capture(captn)
+            self.backref(captn)
         } else {
             MatchCase::Char(next)
         };
         Ok(case)
     }
+    /// Returns the index of the group referenced by `name`,
+    /// which can also be the index itself
+    fn group_id(&self, name: &str) -> Result<usize> {
+        if let Ok(id) = name.parse::<usize>() {
+            return Ok(id);
+        }
+        self.captures_map.get(name).copied().ok_or_else(|| {
+            RegexError::new(
+                RegexErrorKind::UnknownBackreference,
+                format!("Unknown capture '{name}'"),
+            )
+        })
+    }
+    /// Builds a backreference to the group `captn`, to be validated
+    /// once the whole pattern is parsed
+    fn backref(&mut self, captn: usize) -> MatchCase {
+        let closed =
+            captn <= self.n_captures && !self.accc.iter().any(|(_, _, id)| *id == Some(captn));
+        self.backrefs.push((captn, closed));
+        MatchCase::Capture(captn)
+    }
+    /// Parses a `(?P=name)` backreference, after the `(?P=`
+    fn named_backref(&mut self) -> Result<MatchCase> {
+        let rest = self.chars.as_str();
+        let Some(close) = rest.find(')') else {
+            return Err(RegexError::new(
+                RegexErrorKind::BadGroupName,
+                "Expected closing ')'",
+            ));
+        };
+        let captn = self.group_id(&rest[..close])?;
+        self.chars = rest[close + 1..].chars();
+        Ok(self.backref(captn))
+    }
     pub fn process(&mut self) -> Result<Regex> {
         while let Some(c) = self.chars.next() {
             let newcase = match c {
                 '.' => MatchCase::AnyOne,
-                '\\' => self.escape(c)?,
+                '\\' if self.chars.as_str().starts_with('Q') => {
+                    self.chars.next();
+                    self.quoted();
+                    continue;
+                }
+                '\\' => self.escape()?,
+                '(' if self.chars.as_str().starts_with("?(") => {
+                    self.chars.next();
+                    self.chars.next();
+                    self.open_condition()?;
+                    continue;
+                }
+                '(' if self.chars.as_str().starts_with("?#") => {
+                    self.comment()?;
+                    continue;
+                }
+                '(' if self.chars.as_str().starts_with("?P=") => {
+                    for _ in 0..3 {
+                        self.chars.next();
+                    }
+                    self.named_backref()?
+                }
+                '(' if self.chars.as_str().starts_with("?>") => {
+                    self.chars.next();
+                    self.chars.next();
+                    self.enter_scope(false)?;
+                    self.open_special.push((self.open, SpecialScope::Atomic));
+                    continue;
+                }
+                '(' if self.chars.as_str().starts_with('?')
+                    && !self.chars.as_str()[1..].starts_with('<') =>
+                {
+                    self.inline_flags()?;
+                    continue;
+                }
                 '(' => {
                     self.enter_scope(true)?;
                     continue;
                 }
-                ')' => self.close_scope(),
+                ')' if self.open > 1 => self.close_scope()?,
+                ')' => {
+                    return Err(RegexError::new(
+                        RegexErrorKind::UnopenedGroup,
+                        format!("Unexpected ')' at byte {}", self.offset()),
+                    ));
+                }
                 '|' => {
                     self.or();
                     continue;
@@ -293,13 +525,114 @@ impl<'a> RegexCompiler<'a> {
             self.append(newcase);
         }
 
-        let matches = match self.close_scope() {
+        if self.open > 1 {
+            return Err(RegexError::new(
+                RegexErrorKind::UnterminatedGroup,
+                "Expected closing ')'",
+            ));
+        }
+        self.check_backrefs()?;
+
+        let matches = match self.close_scope()? {
             MatchCase::List(cases) => cases,
             MatchCase::Or(l) => Box::from([MatchCase::Or(l)]),
             _ => unreachable!(),
         };
 
-        Ok(Regex { matches })
+        let mut names = vec![None; self.n_captures];
+        for (name, id) in self.captures_map.drain() {
+            names[id - 1] = Some(name.into_boxed_str());
+        }
+
+        Ok(Regex::from_parts(
+            matches,
+            self.src.into(),
+            names.into_boxed_slice(),
+            self.flags,
+        ))
+    }
+    /// Appends the text of a `\Q...\E` section, after the `\Q`, as literal
+    /// characters. Without an `\E`, the rest of the pattern is literal
+    fn quoted(&mut self) {
+        let rest = self.chars.as_str();
+        let (text, len) = match rest.find("\\E") {
+            Some(end) => (&rest[..end], end + 2),
+            None => (rest, rest.len()),
+        };
+        for c in text.chars() {
+            self.append(MatchCase::Char(c));
+        }
+        self.chars = rest[len..].chars();
+    }
+    /// Skips a `(?#...)` comment, after the `(`. It ends at the first `)`
+    fn comment(&mut self) -> Result<()> {
+        let rest = self.chars.as_str();
+        let Some(close) = rest.find(')') else {
+            return Err(RegexError::new(
+                RegexErrorKind::UnterminatedGroup,
+                "Unterminated (?#...) comment",
+            ));
+        };
+        self.chars = rest[close + 1..].chars();
+        Ok(())
+    }
+    /// Parses the flags of a `(?flags)` group. In a `(?flags:...)` group,
+    /// opens its scope, which doesn't capture. The flags still apply to
+    /// the whole expression
+    fn inline_flags(&mut self) -> Result<()> {
+        self.chars.next();
+        loop {
+            match self.chars.next() {
+                Some('i') => self.flags.case_insensitive = true,
+                Some('m') => self.flags.multiline = true,
+                Some('s') => self.flags.dot_all = true,
+                Some(')') => return Ok(()),
+                Some(':') => {
+                    self.enter_scope(false)?;
+                    self.open_special
+                        .push((self.open, SpecialScope::NonCapturing));
+                    return Ok(());
+                }
+                Some(c) => {
+                    return Err(RegexError::new(
+                        RegexErrorKind::UnknownFlag,
+                        format!("Unknown flag '{c}'"),
+                    ));
+                }
+                None => {
+                    return Err(RegexError::new(
+                        RegexErrorKind::UnknownFlag,
+                        "Expected closing ')' after flags",
+                    ));
+                }
+            }
+        }
+    }
+    /// Validates the backreferences once all the groups are known
+    fn check_backrefs(&self) -> Result<()> {
+        for &(captn, closed) in &self.backrefs {
+            if captn == 0 || captn > self.n_captures {
+                return Err(RegexError::new(
+                    RegexErrorKind::UnknownBackreference,
+                    format!("Backreference to unknown group {captn}"),
+                ));
+            }
+            if !closed {
+                return Err(RegexError::new(
+                    RegexErrorKind::ForwardReference,
+                    format!("Forward/self reference to group {captn} not allowed"),
+                ));
+            }
+        }
+        for &group in &self.conditions {
+            if group == 0 || group > self.n_captures {
+                return Err(RegexError::new(
+                    RegexErrorKind::UnknownBackreference,
+                    format!("Condition on unknown group {group}"),
+                ));
+            }
+        }
+        Ok(())
     }
     fn append(&mut self, case: MatchCase) {
         if self.accc.is_empty() {
@@ -308,3 +641,146 @@ impl<'a> RegexCompiler<'a> {
         self.last_acc().0.push(case);
     }
 }
+
+/// Error for an escape cut short by the end of the pattern,
+/// like `\` or `\k<`. `pos` is the byte offset of its backslash
+fn incomplete_escape(pos: usize) -> RegexError {
+    RegexError::new(
+        RegexErrorKind::BadEscape,
+        format!("Incomplete escape at byte {pos}"),
+    )
+}
+
+/// Error for a `\x` or `\u` escape whose digits aren't the hex code
+/// point of a char, like `\xZZ` or `\u{110000}`. `pos` is the byte
+/// offset of its backslash
+fn invalid_hex(pos: usize) -> RegexError {
+    RegexError::new(
+        RegexErrorKind::BadEscape,
+        format!("Invalid hex escape at byte {pos}"),
+    )
+}
+
+/// Returns the class of a shorthand escape, like `\d`.
+/// They can also be used inside `[...]`
+fn shorthand_class(c: char) -> Option<MatchCase> {
+    Some(match c {
+        's' => MatchCase::Whitespace,
+        'S' => MatchCase::NotWhitespace,
+        'd' => MatchCase::Decimal,
+        'D' => MatchCase::NotDecimal,
+        'w' => MatchCase::Word,
+        'W' => MatchCase::NotWord,
+        _ => return None,
+    })
+}
+
+/// Maximum number of groups that can be nested inside each other.
+/// Matching keeps its own stack, but computing the length bounds,
+/// printing and dropping a [`MatchCase`] still recurse on the
+/// nested groups, so deeper patterns could overflow the stack
+pub(crate) const MAX_NESTING: usize = 250;
+
+/// Largest bound allowed in a `{n,m}` quantifier
+pub(crate) const MAX_REPETITION: usize = u16::MAX as usize;
+
+/// Checks that the bounds of a `{n,m}` quantifier are in order,
+/// and not larger than [`MAX_REPETITION`]
+pub(crate) fn check_repetition(min: Option<usize>, max: Option<usize>) -> Result<()> {
+    if let Some(n) = min.max(max).filter(|&n| n > MAX_REPETITION) {
+        return Err(RegexError::new(
+            RegexErrorKind::BadRepetition,
+            format!("Repetition bound {n} is larger than the maximum of {MAX_REPETITION}"),
+        ));
+    }
+    if let (Some(lo), Some(hi)) = (min, max)
+        && lo > hi
+    {
+        return Err(RegexError::new(
+            RegexErrorKind::BadRepetition,
+            format!("Repetition min greater than max: {{{lo},{hi}}}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns true if `s` is the inside of a `{n,m}` quantifier.
+/// Both numbers are optional, but there must be at least one or a `,`
+fn is_repetition(s: &str) -> bool {
+    let digits = |n: &str| n.bytes().all(|b| b.is_ascii_digit());
+    match s.split_once(',') {
+        Some((min, max)) => digits(min) && digits(max),
+        None => !s.is_empty() && digits(s),
+    }
+}
+
+/// Computes the first set of a pattern: the cases, each matching a single
+/// character, one of which must match the first character of any match.
+///
+/// Returns [None] if the pattern can match an empty string, or if
+/// its first character can't be told in advance, like with `.`
+pub(crate) fn first_set(cases: &[MatchCase]) -> Option<Box<[MatchCase]>> {
+    let mut set = Vec::new();
+    if first_of_list(cases, &mut set)? {
+        return None;
+    }
+    Some(set.into_iter().cloned().collect())
+}
+
+/// Adds the first set of a sequence of cases to `set`. Returns whether
+/// they can match an empty string, or [None] if it can't be known
+fn first_of_list<'c>(cases: &'c [MatchCase], set: &mut Vec<&'c MatchCase>) -> Option<bool> {
+    for case in cases {
+        if !first_of(case, set)? {
+            return Some(false);
+        }
+    }
+    Some(true)
+}
+
+/// Same as [`first_of_list`], for a single case
+fn first_of<'c>(case: &'c MatchCase, set: &mut Vec<&'c MatchCase>) -> Option<bool> {
+    let empty = match case {
+        MatchCase::Char(_)
+        | MatchCase::Between(..)
+        | MatchCase::CharMatch(_)
+        | MatchCase::Not(_)
+        | MatchCase::Whitespace
+        | MatchCase::NotWhitespace
+        | MatchCase::Decimal
+        | MatchCase::NotDecimal
+        | MatchCase::Word
+        | MatchCase::NotWord => {
+            set.push(case);
+            false
+        }
+        MatchCase::Start
+        | MatchCase::End
+        | MatchCase::InputStart
+        | MatchCase::InputEnd
+        | MatchCase::InputEndOrNewline
+        | MatchCase::PrevMatchEnd
+        | MatchCase::WordBoundary
+        | MatchCase::NotWordBoundary => true,
+        MatchCase::List(cases) => first_of_list(cases, set)?,
+        MatchCase::Group { case, .. }
+        | MatchCase::Atomic(case)
+        | MatchCase::OneOrMore { case, .. } => first_of(case, set)?,
+        MatchCase::Opt(case) | MatchCase::Star { case, .. } => {
+            first_of(case, set)?;
+            true
+        }
+        MatchCase::RangeLoop { case, min, .. } => first_of(case, set)? || min.unwrap_or(0) == 0,
+        MatchCase::Or(branches) => {
+            let mut empty = false;
+            for branch in branches {
+                empty |= first_of(branch, set)?;
+            }
+            empty
+        }
+        MatchCase::Conditional { then, else_, .. } => first_of(then, set)? | first_of(else_, set)?,
+        // `.` matches almost anything, and backreferences can be empty
+        MatchCase::AnyOne | MatchCase::Capture(_) => return None,
+    };
+    Some(empty)
+}
@@ -4,50 +4,123 @@ use core::fmt::Display;
 
 use alloc::borrow::Cow;
 
-#[derive(Debug)]
-pub struct RegexError(Cow<'static, str>);
+/// Kind of a [`RegexError`], to tell the errors apart without
+/// looking at their message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RegexErrorKind {
+    /// A quantifier with nothing to apply to, like `*a`
+    UnexpectedQuantifier,
+    /// A group that isn't closed, like `(a`
+    UnterminatedGroup,
+    /// A `)` without a group to close, like `a)`
+    UnopenedGroup,
+    /// A character class that isn't closed, like `[a`
+    UnterminatedClass,
+    /// A range with an invalid end in a character class, like `[a-\d]`
+    BadRange,
+    /// A `{n,m}` repetition whose numbers can't be parsed
+    BadRepetition,
+    /// A backreference to a group that doesn't exist
+    UnknownBackreference,
+    /// A backreference to a group that isn't closed before it
+    ForwardReference,
+    /// A `\` at the end of the pattern, or a malformed escape, like `\x{41` or `\xZZ`
+    BadEscape,
+    /// A malformed group name, like `(?<name`
+    BadGroupName,
+    /// An unknown flag in a `(?flags)` group
+    UnknownFlag,
+    /// A pattern with too many nested groups
+    TooDeep,
+    /// A search that exceeded its limit of steps, its maximum depth or its deadline
+    Aborted,
+    /// An offset that isn't on a char boundary of the input
+    BadOffset,
+    /// A [`RegexConf`](crate::RegexConf) setting with an invalid value,
+    /// like a line terminator that isn't ASCII
+    BadConf,
+    /// Any other error
+    Other,
+}
+
+/// Error produced when compiling or searching a [Regex](crate::Regex)
+///
+/// It has a [kind](RegexErrorKind), to handle each error programmatically,
+/// and a human readable message, used by its [Display] implementation
+#[derive(Debug, Clone)]
+pub struct RegexError {
+    kind: RegexErrorKind,
+    msg: Cow<'static, str>,
+}
 
 impl RegexError {
+    /// Creates an error of the given kind
+    #[inline]
+    #[must_use]
+    pub fn new(kind: RegexErrorKind, msg: impl Into<Cow<'static, str>>) -> Self {
+        RegexError {
+            kind,
+            msg: msg.into(),
+        }
+    }
+
+    /// Returns the kind of the error
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::{Regex, RegexErrorKind};
+    ///
+    /// let err = Regex::compile("(ab").unwrap_err();
+    /// assert_eq!(err.kind(), RegexErrorKind::UnterminatedGroup);
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn kind(&self) -> RegexErrorKind {
+        self.kind
+    }
+
+    /// Returns the message of the error
     #[inline]
     #[must_use]
     pub fn inner(&self) -> &Cow<'static, str> {
-        &self.0
+        &self.msg
     }
 }
 
 impl From<&'static str> for RegexError {
     fn from(value: &'static str) -> Self {
-        RegexError(value.into())
+        RegexError::new(RegexErrorKind::Other, value)
     }
 }
 
 impl From<String> for RegexError {
     fn from(value: String) -> Self {
-        RegexError(value.into())
+        RegexError::new(RegexErrorKind::Other, value)
     }
 }
 
 impl From<Cow<'static, str>> for RegexError {
     fn from(value: Cow<'static, str>) -> Self {
-        RegexError(value)
+        RegexError::new(RegexErrorKind::Other, value)
     }
 }
 
 impl From<RegexError> for Cow<'static, str> {
     fn from(val: RegexError) -> Self {
-        val.0
+        val.msg
     }
 }
 
 impl From<RegexError> for String {
     fn from(val: RegexError) -> Self {
-        val.0.into_owned()
+        val.msg.into_owned()
     }
 }
 
 impl Display for RegexError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.msg)
     }
 }
 
@@ -1,11 +1,39 @@
 //! C bindings
 
-use crate::{DEFAULT_REGEX_CONF, Regex, RegexConf, RegexMatcher};
+use crate::{DEFAULT_REGEX_CONF, Regex, RegexConf, ReplaceRegex};
 use core::ffi::{CStr, c_char, c_ulong};
 use core::ptr;
+use std::cell::RefCell;
 
 extern crate alloc;
+use alloc::borrow::Cow;
 use alloc::boxed::Box;
+use alloc::ffi::CString;
+use alloc::vec::Vec;
+
+std::thread_local! {
+    /// Message of the last error in this thread
+    static LAST_ERROR: RefCell<Option<Cow<'static, str>>> = const { RefCell::new(None) };
+}
+
+/// Stores the message of an error, so it can be retrieved with [`regex_last_error`]
+fn set_last_error(msg: impl Into<Cow<'static, str>>) {
+    LAST_ERROR.with_borrow_mut(|last| *last = Some(msg.into()));
+}
+
+const INVALID_UTF8: &str = "String is not valid UTF-8";
+
+/// Iterator over the matches of a regex, as seen from C
+///
+/// Wraps a [`crate::RegexMatcher`], keeping the spans of the
+/// capture groups of the last match, so they can be queried
+/// after [`regex_matcher_next`] returns.
+pub struct RegexMatcher<'a> {
+    inner: crate::RegexMatcher<'a>,
+    src: &'a str,
+    /// Spans of the groups of the last match. Index 0 is the whole match
+    groups: Vec<Option<Span>>,
+}
 
 /// Compile the given string into a regex
 ///
@@ -17,13 +45,17 @@ use alloc::boxed::Box;
 pub unsafe extern "C" fn regex_compile(src: *const c_char) -> *mut Regex {
     let src = unsafe { CStr::from_ptr(src) };
     let Ok(src) = src.to_str() else {
+        set_last_error(INVALID_UTF8);
         return ptr::null_mut();
     };
 
-    let Ok(regex) = Regex::compile(src) else {
-        return ptr::null_mut();
-    };
-    Box::into_raw(Box::new(regex))
+    match Regex::compile(src) {
+        Ok(regex) => Box::into_raw(Box::new(regex)),
+        Err(err) => {
+            set_last_error(err);
+            ptr::null_mut()
+        }
+    }
 }
 
 /// Test if the given string matches the regex
@@ -85,15 +117,20 @@ pub unsafe extern "C" fn regex_find_matches_with_conf<'a>(
 ) -> *mut RegexMatcher<'a> {
     let src = unsafe { CStr::from_ptr(src) };
     let Ok(src) = src.to_str() else {
+        set_last_error(INVALID_UTF8);
         return ptr::null_mut();
     };
 
-    let matcher = unsafe { &*regex }.find_matches_with_conf(src, conf);
-    let matcher = Box::new(matcher);
-    Box::into_raw(matcher)
+    let matcher = RegexMatcher {
+        inner: unsafe { &*regex }.find_matches_with_conf(src, conf),
+        src,
+        groups: Vec::new(),
+    };
+    Box::into_raw(Box::new(matcher))
 }
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct Span {
     offset: c_ulong,
     len: c_ulong,
@@ -113,20 +150,115 @@ pub unsafe extern "C" fn regex_matcher_next(
     matcher: *mut RegexMatcher<'_>,
     span: *mut Span,
 ) -> bool {
-    match unsafe { &mut *matcher }.next() {
+    let matcher = unsafe { &mut *matcher };
+    matcher.groups.clear();
+    match matcher.inner.next() {
         Some(m) => {
-            unsafe {
-                *span = Span {
-                    offset: m.span().0 as c_ulong,
-                    len: m.slice().len() as c_ulong,
-                }
+            let base = matcher.src.as_ptr() as usize;
+            let to_span = |s: &str| Span {
+                offset: (s.as_ptr() as usize - base) as c_ulong,
+                len: s.len() as c_ulong,
             };
+            matcher.groups.push(Some(to_span(m.slice())));
+            matcher
+                .groups
+                .extend(m.groups().iter().map(|g| g.map(to_span)));
+            unsafe { *span = to_span(m.slice()) };
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns the number of capture groups of the last match
+///
+/// The whole match (group 0) isn't counted, so the valid
+/// indices for [`regex_matcher_group`] go from 0 to this number,
+/// both included. Returns 0 if there's no last match.
+///
+/// # Safety
+/// Ensure that matcher is a valid pointer to a `RegexMatcher`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn regex_matcher_group_count(matcher: *const RegexMatcher<'_>) -> c_ulong {
+    let matcher = unsafe { &*matcher };
+    matcher.groups.len().saturating_sub(1) as c_ulong
+}
+
+/// Gets the span of the n'th capture group of the last match.
+/// Group 0 is the whole match.
+///
+/// Returns false if the group doesn't exist, or didn't participate
+/// in the match. In that case, span is left untouched.
+///
+/// # Safety
+/// Ensure that.
+/// 1) matcher is a valid pointer to a `RegexMatcher`
+/// 2) span is a valid pointer to a Span struct
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn regex_matcher_group(
+    matcher: *const RegexMatcher<'_>,
+    index: c_ulong,
+    span: *mut Span,
+) -> bool {
+    let matcher = unsafe { &*matcher };
+    let group = usize::try_from(index)
+        .ok()
+        .and_then(|i| matcher.groups.get(i).copied().flatten());
+    match group {
+        Some(g) => {
+            unsafe { *span = g };
             true
         }
         None => false,
     }
 }
 
+/// Replaces all the matches of the regex in the source string
+/// with the replacement string
+///
+/// The `$n`, `${n}` and `${name}` references of the replacement
+/// are expanded with the capture groups of each match
+///
+/// Returns a newly allocated NULL terminated C-String with the result,
+/// or NULL if the regex fails to compile
+///
+/// # Safety
+/// Ensure that.
+/// 1) regex, src and replacement are valid NULL terminated C-Strings
+/// 2) You call `regex_string_free` on the returned pointer after you're done
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn regex_replace(
+    regex: *const c_char,
+    src: *const c_char,
+    replacement: *const c_char,
+) -> *mut c_char {
+    let [Ok(regex), Ok(src), Ok(replacement)] =
+        [regex, src, replacement].map(|s| unsafe { CStr::from_ptr(s) }.to_str())
+    else {
+        set_last_error(INVALID_UTF8);
+        return ptr::null_mut();
+    };
+
+    let result = match src.replace_regex(regex, replacement) {
+        Ok(result) => result,
+        Err(err) => {
+            set_last_error(err);
+            return ptr::null_mut();
+        }
+    };
+    CString::new(result.into_owned()).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Frees a string returned by this library
+///
+/// # Safety
+/// Ensure that.
+/// 1) s is a pointer returned by `regex_replace` that HAS NOT BEEN FREED before
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn regex_string_free(s: *mut c_char) {
+    drop(unsafe { CString::from_raw(s) });
+}
+
 /// Frees the regex matcher
 ///
 /// # Safety
@@ -148,3 +280,33 @@ pub unsafe extern "C" fn regex_free(regex: *mut Regex) {
     let r = unsafe { Box::from_raw(regex) };
     drop(r);
 }
+
+/// Copies the message of the last error that happened in this thread
+/// into buf, as a NULL terminated C-String. If the message doesn't fit,
+/// it's truncated.
+///
+/// The functions that return NULL on failure, like [`regex_compile`],
+/// store the reason of the failure so it can be retrieved by this function.
+///
+/// Returns the size of the buffer needed to hold the whole message,
+/// including the NULL terminator, or 0 if there hasn't been any error.
+/// Passing a NULL buf, or a len of 0, just returns the needed size.
+///
+/// # Safety
+/// Ensure that buf is NULL, or a valid pointer to at least len bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn regex_last_error(buf: *mut c_char, len: c_ulong) -> c_ulong {
+    LAST_ERROR.with_borrow(|last| {
+        let Some(msg) = last else { return 0 };
+        let msg = msg.as_bytes();
+        let len = usize::try_from(len).unwrap_or(usize::MAX);
+        if !buf.is_null() && len > 0 {
+            let n = msg.len().min(len - 1);
+            unsafe {
+                ptr::copy_nonoverlapping(msg.as_ptr().cast::<c_char>(), buf, n);
+                *buf.add(n) = 0;
+            }
+        }
+        (msg.len() + 1) as c_ulong
+    })
+}
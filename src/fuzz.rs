@@ -0,0 +1,167 @@
+//! Deterministic generator of random patterns and inputs,
+//! used to check the invariants of the engine.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{DEFAULT_REGEX_CONF, Regex, RegexConf};
+
+/// Characters used to build both the patterns and the inputs
+const ALPHABET: &[char] = &['a', 'b', 'c', '1', ' ', '\n', 'ñ'];
+
+/// Simple xorshift PRNG, so the cases are reproducible from a seed
+pub struct Fuzzer {
+    state: u64,
+}
+
+impl Fuzzer {
+    pub fn new(seed: u64) -> Self {
+        Fuzzer {
+            state: seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1,
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+
+    /// Random number in [0, n)
+    #[allow(clippy::cast_possible_truncation)]
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn char(&mut self) -> char {
+        ALPHABET[self.below(ALPHABET.len())]
+    }
+
+    /// Generates a random valid pattern
+    pub fn pattern(&mut self) -> String {
+        let mut pattern = String::new();
+        if self.below(4) == 0 {
+            pattern.push('^');
+        }
+        self.sequence(&mut pattern, 3);
+        if self.below(4) == 0 {
+            pattern.push('$');
+        }
+        pattern
+    }
+
+    /// Generates a random input
+    pub fn input(&mut self) -> String {
+        let len = self.below(12);
+        (0..len).map(|_| self.char()).collect()
+    }
+
+    fn sequence(&mut self, dst: &mut String, depth: usize) {
+        for _ in 0..=self.below(4) {
+            self.quantified(dst, depth);
+        }
+    }
+
+    /// Pushes an atom, maybe followed by a quantifier.
+    /// Only atoms that can't match the empty string are quantified.
+    fn quantified(&mut self, dst: &mut String, depth: usize) {
+        self.atom(dst, depth);
+        let quantifier = match self.below(8) {
+            0 => "*",
+            1 => "+",
+            2 => "?",
+            3 => "*?",
+            4 => "+?",
+            5 => "{1,2}",
+            _ => return,
+        };
+        dst.push_str(quantifier);
+    }
+
+    fn atom(&mut self, dst: &mut String, depth: usize) {
+        match self.below(if depth > 0 { 6 } else { 5 }) {
+            0 => dst.push('.'),
+            1 => {
+                dst.push('[');
+                if self.below(2) == 0 {
+                    dst.push('^');
+                }
+                for _ in 0..=self.below(2) {
+                    dst.push(self.char());
+                }
+                dst.push(']');
+            }
+            2 => dst.push_str(["\\w", "\\d", "\\s"][self.below(3)]),
+            5 => {
+                dst.push('(');
+                for i in 0..=self.below(2) {
+                    if i > 0 {
+                        dst.push('|');
+                    }
+                    for _ in 0..=self.below(3) {
+                        self.atom(dst, depth - 1);
+                    }
+                }
+                dst.push(')');
+            }
+            _ => dst.push(self.char()),
+        }
+    }
+}
+
+impl Regex {
+    /// Generates a random pattern, and some inputs to match it against.
+    ///
+    /// The same seed always produces the same case.
+    pub(crate) fn fuzz_seed(seed: u64) -> (String, Vec<String>) {
+        let mut fuzzer = Fuzzer::new(seed);
+        let pattern = fuzzer.pattern();
+        let inputs = (0..8).map(|_| fuzzer.input()).collect();
+        (pattern, inputs)
+    }
+}
+
+/// Checks the invariants of the engine for the given pattern and input
+///
+/// Returns a description of the first violation found
+pub fn check(regex: &Regex, src: &str) -> Result<(), String> {
+    let conf = RegexConf {
+        max_steps: 100_000,
+        ..DEFAULT_REGEX_CONF
+    };
+
+    let mut matcher = regex.find_matches_with_conf(src, conf);
+    let mut spans = Vec::new();
+    for m in matcher.by_ref() {
+        let (start, end) = m.span();
+        if src.get(start..end) != Some(m.slice()) {
+            return Err(format!(
+                "Span {start}..{end} doesn't slice to {:?}",
+                m.slice()
+            ));
+        }
+        spans.push((start, end));
+    }
+    if matcher.aborted() {
+        return Ok(());
+    }
+
+    for w in spans.windows(2) {
+        let [(s1, e1), (s2, _)] = w else {
+            unreachable!()
+        };
+        if s2 < e1 || s2 <= s1 {
+            return Err(format!("Matches {w:?} overlap or aren't increasing"));
+        }
+    }
+    if regex.test_with_conf(src, conf) == spans.is_empty() {
+        return Err(String::from("test doesn't agree with find_matches"));
+    }
+    if regex.find_with_conf(src, conf).map(|m| m.span()) != spans.first().copied() {
+        return Err(String::from("find doesn't agree with find_matches"));
+    }
+    Ok(())
+}
@@ -135,7 +135,7 @@ impl Splitter {
             matches.clear();
             matches.extend(
                 regex.find_matches(&text).map(|m| {
-                    Match { offset: m.span().0, str: m.slice().to_string() }
+                    Match { offset: m.start(), str: m.slice().to_string() }
                 })
             );
 
@@ -17,7 +17,7 @@
 //!
 //! | Rule  | Meaning |
 //! |---------|---------|
-//!  |  .   |  Matches any character |
+//!  |  .   |  Matches any character, except a newline |
 //!  |  * | Matches the previous rule zero or more times |
 //!  |  + | Matches the previous rule one or more times |
 //!  |  ? | Makes the previous rule optional |
@@ -27,26 +27,73 @@
 //!  | \[^...] | Same as the rules above but negated |
 //!  | A \| B | Maches A or B |
 //!  | (ABC) | Groups rules A B and C [^group] |
+//!  | (?:ABC) | Groups rules A B and C, without capturing them |
 //!  | \\c | Escapes the character c[^esc] |
+//!  | \\xHH _OR_ \\x{H..} _OR_ \\u{H..} | Matches the character with the given hex code point[^hex] |
+//!  | \\Q...\\E | Matches the text between \\Q and \\E literally[^quote] |
+//!  | (?flags) _OR_ (?flags:ABC) | Enables the given flags for the whole expression[^flags] |
+//!  | (?#text) | A comment, that is ignored. It ends at the first ) |
+//!  | \\b | Matches a word boundary[^boundary] |
+//!  | \\B | Matches anywhere that's not a word boundary |
+//!  | \\A | Matches the start of the input, even in multiline mode |
+//!  | \\z | Matches the end of the input, even in multiline mode |
+//!  | \\Z | Matches the end of the input, or before a newline at the end |
+//!  | \\G | Matches where the previous match ended, or where the search started |
 //!  | __\\n__  _OR_ __\\k\<n\>__ | Match the n'th capture group[^capture] |
+//!  | (?>ABC) | Matches ABC, without backtracking into it once it has matched[^atomic] |
+//!  | (?(n)A\|B) | Matches A if the n'th group has matched, or B if not[^cond] |
 //!
 //! [^min_max]: If min or max are not present, it means there's no limit on that size. \
 //! Examples:\
 //!     {,12} matches a rule up to 12 \
 //!     {3,} matches a rule at least 3 times. \
-//!     {,} is the same as *
+//!     {,} is the same as * \
+//! If the braces don't hold a valid range, like in a{b}, they are matched literally. \
+//! The min can't be greater than the max, and neither can be greater than 65535.
 //!
 //! [^ranged]: The ranges can be mixed. \
 //! Examples: \
 //!     \[a-z123]: Matches any character in the ranges a-z , 1, 2 or 3 \
-//!     \[^0-9ab]: Matches a character that IS NOT a number or a or b
+//!     \[^0-9ab]: Matches a character that IS NOT a number or a or b \
+//!     \[\\d\\s]: Matches a digit or a whitespace. \\w, \\s, \\d and their negations can be used \
+//!     \[]a-]: Matches ], a or -. A ] right after the opening, and a - at the end are literal. \
+//!     They can also be escaped, like in \[a\\]\\-]
 //!
 //! [^esc]: Example: "\\." Matches a literal dot character.
 //!
-//! [^group]: This captured groups can be later referenced
+//! [^hex]: Example: "\\x41", "\\x{41}" and "\\u{41}" match "A". They can also be used inside classes,
+//!     like in \[\\x41-\\x5A]. \
+//!     An escape cut short by the end of the pattern, like "\\x4" or "\\k\<name", is an error. \
+//!     \\x and \\u no longer match a literal x and u, like other escaped letters do.
+//!     They must be followed by a code point, so "\\x" or "\\uZZ" are errors.
+//!
+//! [^quote]: Example: "\\Qa.b*\\E+" matches "a.b" followed by one or more "*". \
+//!     Without a closing \\E, the rest of the pattern is taken literally.
+//!
+//! [^flags]: The flags are single letters: \
+//!     i: case insensitive \
+//!     m: multiline, ^ and $ match at the start and end of each line \
+//!     s: dot all, . also matches a newline \
+//!  Examples: (?i)abc (?sm)^.*$ \
+//!  In (?flags:ABC), ABC is a group that doesn't capture, but the flags
+//!  still apply to the whole expression, not only to ABC.
+//!
+//! [^boundary]: A word boundary is a position between a word character (\\w)
+//!               and a non-word character, or the start or end of the input.
+//!
+//! [^group]: This captured groups can be later referenced. \
+//!     Up to 250 groups can be nested inside each other.
 //!
 //! [^capture]: n must be an integer in the range \[1,L\] where L is the number
-//!             of capture groups in the expression
+//!             of capture groups in the expression. \
+//!             Named groups can be referenced with \\k\<name\>, or with (?P=name)
+//!
+//! [^atomic]: Example: (?>a+)b matches "aab", but (?>a+)a never matches,
+//!     since a+ takes all the a's and doesn't give one back. \
+//!     Atomic groups don't capture.
+//!
+//! [^cond]: The group can also be given by its name, like (?(\<name\>)A|B). \
+//!     The B branch is optional: (?(1)A) matches A if group 1 has matched, or nothing if not.
 //!
 //!
 //!
@@ -82,64 +129,427 @@ extern crate alloc;
 use alloc::borrow::Cow;
 use alloc::boxed::Box;
 use alloc::string::String;
+use alloc::vec::Vec;
 
 use core::fmt::Display;
+use core::hash::{Hash, Hasher};
 
 mod case;
-use case::MatchCase;
+#[doc(inline)]
+pub use case::MatchCase;
+
+mod builder;
+pub use builder::RegexBuilder;
 
 mod compiler;
 use compiler::RegexCompiler;
 
 mod error;
 mod matcher;
-pub use error::RegexError;
+pub use error::{RegexError, RegexErrorKind};
 type Result<T> = core::result::Result<T, RegexError>;
 
+#[cfg(feature = "diagnostics")]
 #[doc(inline)]
-pub use matcher::{RegexMatch, RegexMatcher};
+pub use matcher::MatchStats;
+#[cfg(all(feature = "diagnostics", feature = "std"))]
+#[doc(inline)]
+pub use matcher::TraceEvent;
+#[doc(inline)]
+pub use matcher::{Captures, Piece, RegexMatch, RegexMatcher};
+
+#[cfg(feature = "std")]
+mod reader;
+#[cfg(feature = "std")]
+pub use reader::ReaderMatcher;
+
+mod streaming;
+#[doc(inline)]
+pub use streaming::{MatchEvent, StreamingMatcher};
+
+mod chars;
+pub use chars::CharsMatcher;
 
 /// Main Regex struct
 ///
 /// Holds a regular expression
+///
+/// A [Regex] is immutable once compiled, so it's [Send] and [Sync]:
+/// it can be compiled once and shared between threads, for example
+/// behind an [`Arc`](alloc::sync::Arc). The state of each search lives
+/// in its [`RegexMatcher`], which belongs to the thread that started
+/// it, and isn't meant to be shared.
+///
+/// # Example
+/// ```
+/// use regexpr::Regex;
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let regex = Arc::new(Regex::compile("[0-9]+").unwrap());
+/// let handles: Vec<_> = ["a1", "22b", "c"]
+///     .into_iter()
+///     .map(|src| {
+///         let regex = Arc::clone(&regex);
+///         thread::spawn(move || regex.match_count(src))
+///     })
+///     .collect();
+/// let counts: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+/// assert_eq!(counts, [1, 1, 0]);
+/// ```
 #[derive(Debug)]
 pub struct Regex {
     matches: Box<[MatchCase]>,
+    /// Literal text every match starts with
+    prefix: Box<str>,
+    /// Cases one of which matches the first character of any match.
+    /// See [`compiler::first_set`]
+    first_set: Option<Box<[MatchCase]>>,
+    /// Characters `.` and the negated classes can match
+    alphabet: Option<Box<[char]>>,
+    /// Whether the search can remember where parts of the
+    /// pattern failed to match. See [`case::reads_groups`]
+    memoize: bool,
+    src: Box<str>,
+    /// Name of each capture group, if it has one
+    names: Box<[Option<Box<str>>]>,
+    flags: InlineFlags,
+}
+
+// A compiled [Regex] must stay shareable between threads
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Regex>();
+    assert_send_sync::<MatchCase>();
+    assert_send_sync::<RegexError>();
+};
+
+/// Two [Regex] are equal if they compile to the same expression,
+/// even if they were written differently (e.g. `a\-b` and `a-b`)
+impl PartialEq for Regex {
+    fn eq(&self, other: &Self) -> bool {
+        self.matches == other.matches
+            && self.names == other.names
+            && self.flags == other.flags
+            && self.alphabet == other.alphabet
+    }
+}
+
+impl Eq for Regex {}
+
+impl Hash for Regex {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.matches.hash(state);
+        self.names.hash(state);
+        self.flags.hash(state);
+        self.alphabet.hash(state);
+    }
 }
 
 impl Display for Regex {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        let mut first = true;
-        for c in &self.matches {
-            if !first {
-                write!(f, " => ")?;
-            }
-            first = false;
-            write!(f, "{c:#?}")?;
-        }
-        Ok(())
+        write!(f, "{}", self.src)
     }
 }
 
+/// Configuration of a match
+///
+/// Build it from [`RegexConf::new`] (or [`Default`]) and its setters,
+/// so the code keeps compiling when new options are added. Struct
+/// literals must end with `..RegexConf::default()` for the same reason.
+///
+/// The struct is `#[repr(C)]`, so it can be passed by value through the
+/// C bindings. New fields are only ever appended at the end, and the C
+/// side must declare the same fields, in the same order.
+///
+/// # Example
+/// ```
+/// use regexpr::{Regex, RegexConf};
+///
+/// let conf = RegexConf::new().case_sensitive(false).multiline(true);
+/// let regex = Regex::compile("^abc$").unwrap();
+/// assert!(regex.test_with_conf("x\nABC", conf));
+/// ```
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct RegexConf {
+    /// When false, the characters are compared by their simple case folding.
+    /// Enable the `unicode-case` feature to fold the characters whose
+    /// folding isn't their lowercase, like `ς` and `ſ`
     pub case_sensitive: bool,
     pub ignore_captures_in_result: bool,
+    /// Makes `^` and `$` also match at the start and end of each line
+    pub multiline: bool,
+    /// Makes `.` also match a newline
+    pub dot_all: bool,
+    /// Only attempt to match at the start of the input, as if
+    /// the pattern started with `^`
+    pub anchored: bool,
+    /// Only match whole words, as if the pattern was surrounded by `\b`
+    pub whole_word: bool,
+    /// Maximum number of steps a search can take before being aborted.
+    /// An aborted search yields no more matches. 0 means there's no limit
+    ///
+    /// This guards against catastrophic backtracking on
+    /// patterns like `(a+)+b`
+    pub max_steps: usize,
+    /// Record which branch was taken on each alternation.
+    /// See [`RegexMatch::decision_trace`]
+    pub trace_decisions: bool,
+    /// Keep the span of each capture group, as of the last match
+    /// it participated in. See [`RegexMatcher::group_spans`]
+    pub track_group_spans: bool,
+    /// Start the next match attempt one character after the start of
+    /// the previous match, instead of at its end, so matches can overlap
+    pub overlapping: bool,
+    /// Only fold the case of ASCII letters when matching case insensitively.
+    /// Any other character must match exactly, which is faster on ASCII input
+    ///
+    /// This has no effect on its own: the matching is only case insensitive
+    /// if [`case_sensitive`](Self::case_sensitive) is false, or the `i` flag is set
+    pub ascii_case_insensitive: bool,
+    /// Makes `.` match a whole grapheme cluster, like an emoji with
+    /// modifiers or a letter followed by combining accents, instead
+    /// of a single character
+    ///
+    /// This requires the `unicode-segmentation` feature.
+    /// Without it, this option has no effect
+    pub grapheme_dot: bool,
+    /// Find the longest match at the leftmost position, like POSIX
+    /// engines do, instead of the first one the pattern prefers
+    ///
+    /// For example, `a|ab` matches `ab` entirely, and `a+?` takes all the `a`s.
+    /// The capture groups are the ones of the first way, in the order the
+    /// pattern prefers, that reaches the end of the longest match.
+    /// This is slower, since each match attempt is retried for each longer end.
+    /// See [`Regex::find_longest`]
+    pub longest: bool,
+    /// Character that ends a line, `\n` by default. It's where `^` and `$`
+    /// match in multiline mode, and what `.` doesn't match unless in dot
+    /// all mode. Set it with [`line_terminator`](Self::line_terminator)
+    /// to handle `\r` line endings, or records separated by `\x1e`
+    ///
+    /// It must be an ASCII character. Any other byte is taken as `\n`
+    pub line_terminator: u8,
+    /// Maximum depth the search can reach before being aborted, like
+    /// [`max_steps`](Self::max_steps). 0 means there's no limit
+    ///
+    /// The depth is the size of the stack the search keeps for the parts
+    /// of the pattern left to match, which grows with the nesting of the
+    /// pattern and with each repetition of a quantifier. That stack lives
+    /// on the heap, so this caps the memory a search can use, not the
+    /// native stack, which the search doesn't grow
+    pub max_depth: usize,
+    /// Use the Unicode definitions of the character classes. Enabled by default
+    ///
+    /// When disabled, `\w` and `\b` only take ASCII letters, digits and `_`
+    /// as word characters, and `\s` only matches ASCII spaces. `\d` only
+    /// matches ASCII digits either way. `.` still matches a single char,
+    /// but never a whole grapheme cluster, even with [`grapheme_dot`](Self::grapheme_dot).
+    ///
+    /// It also limits case insensitive matching to ASCII letters, like
+    /// [`ascii_case_insensitive`](Self::ascii_case_insensitive), so the
+    /// foldings of the `unicode-case` feature aren't used
+    pub unicode: bool,
 }
 
 const DEFAULT_REGEX_CONF: RegexConf = RegexConf {
     case_sensitive: true,
     ignore_captures_in_result: false,
+    multiline: false,
+    dot_all: false,
+    anchored: false,
+    whole_word: false,
+    max_steps: 0,
+    trace_decisions: false,
+    track_group_spans: false,
+    overlapping: false,
+    ascii_case_insensitive: false,
+    grapheme_dot: false,
+    longest: false,
+    line_terminator: b'\n',
+    max_depth: 0,
+    unicode: true,
 };
 
+/// FNV-1a hasher, used by [`Regex::structural_hash`]
+/// because it's deterministic and available without `std`
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Flags enabled inside the pattern, with `(?flags)`
+///
+/// They apply to the whole expression, on top of the [`RegexConf`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+struct InlineFlags {
+    case_insensitive: bool,
+    multiline: bool,
+    dot_all: bool,
+}
+
+impl InlineFlags {
+    fn apply(self, mut conf: RegexConf) -> RegexConf {
+        conf.case_sensitive &= !self.case_insensitive;
+        conf.multiline |= self.multiline;
+        conf.dot_all |= self.dot_all;
+        conf
+    }
+}
+
+impl Display for InlineFlags {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if !self.case_insensitive && !self.multiline && !self.dot_all {
+            return Ok(());
+        }
+        write!(f, "(?")?;
+        for (set, flag) in [
+            (self.case_insensitive, "i"),
+            (self.multiline, "m"),
+            (self.dot_all, "s"),
+        ] {
+            if set {
+                write!(f, "{flag}")?;
+            }
+        }
+        write!(f, ")")
+    }
+}
+
 impl Default for RegexConf {
     fn default() -> Self {
         DEFAULT_REGEX_CONF
     }
 }
 
+macro_rules! conf_setters {
+    ($($field:ident : $ty:ty),* $(,)?) => {
+        $(
+            #[doc = concat!("Sets [`", stringify!($field), "`](Self::", stringify!($field), ")")]
+            #[must_use]
+            #[inline]
+            pub const fn $field(mut self, $field: $ty) -> Self {
+                self.$field = $field;
+                self
+            }
+        )*
+    };
+}
+
+impl RegexConf {
+    /// Returns the default configuration. Same as [`Default::default`],
+    /// but usable in `const` contexts
+    #[must_use]
+    #[inline]
+    pub const fn new() -> Self {
+        DEFAULT_REGEX_CONF
+    }
+
+    conf_setters! {
+        case_sensitive: bool,
+        ignore_captures_in_result: bool,
+        multiline: bool,
+        dot_all: bool,
+        anchored: bool,
+        whole_word: bool,
+        max_steps: usize,
+        trace_decisions: bool,
+        track_group_spans: bool,
+        overlapping: bool,
+        ascii_case_insensitive: bool,
+        grapheme_dot: bool,
+        longest: bool,
+        max_depth: usize,
+        unicode: bool,
+    }
+
+    /// Sets [`line_terminator`](Self::line_terminator)
+    ///
+    /// # Errors
+    /// If `terminator` is not an ASCII character
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::{Regex, RegexConf, RegexErrorKind};
+    ///
+    /// let conf = RegexConf::new().multiline(true).line_terminator(0x1e).unwrap();
+    /// let regex = Regex::compile("^b.*$").unwrap();
+    /// let m = regex.find_with_conf("a\x1eb\nc\x1ed", conf).unwrap();
+    /// assert_eq!(m.slice(), "b\nc");
+    ///
+    /// let err = RegexConf::new().line_terminator(0x80).unwrap_err();
+    /// assert_eq!(err.kind(), RegexErrorKind::BadConf);
+    /// ```
+    #[inline]
+    pub fn line_terminator(mut self, terminator: u8) -> Result<Self> {
+        if !terminator.is_ascii() {
+            return Err(RegexError::new(
+                RegexErrorKind::BadConf,
+                format!("The line terminator must be ASCII, found {terminator:#04x}"),
+            ));
+        }
+        self.line_terminator = terminator;
+        Ok(self)
+    }
+
+    /// The [`line_terminator`](Self::line_terminator), as a char
+    #[inline]
+    pub(crate) const fn line_end(&self) -> char {
+        if self.line_terminator.is_ascii() {
+            self.line_terminator as char
+        } else {
+            '\n'
+        }
+    }
+}
+
 impl Regex {
+    /// Builds a [Regex] from its parts, computing the rest of its fields
+    fn from_parts(
+        matches: Box<[MatchCase]>,
+        src: Box<str>,
+        names: Box<[Option<Box<str>>]>,
+        flags: InlineFlags,
+    ) -> Self {
+        let prefix = matches
+            .iter()
+            .map_while(|m| match m {
+                MatchCase::Char(c) => Some(*c),
+                _ => None,
+            })
+            .collect::<String>();
+        let first_set = compiler::first_set(&matches);
+        let memoize = !case::reads_groups(&matches);
+
+        Regex {
+            matches,
+            prefix: prefix.into_boxed_str(),
+            first_set,
+            alphabet: None,
+            memoize,
+            src,
+            names,
+            flags,
+        }
+    }
+
     /// Compile the given string into a [Regex]
     ///
     /// Returns error if the regex is invalid and fails to compile
@@ -152,140 +562,1238 @@ impl Regex {
         RegexCompiler::new(src.as_ref()).process()
     }
 
-    /// Returns an [Iterator] over all the [`matches`] of the [Regex] in the given string
+    /// Just like [`compile`](Self::compile), but `.` and the negated
+    /// classes (`[^...]`, `\W`, `\D` and `\S`) only match the characters in `allowed`
     ///
-    /// [`matches`]: RegexMatch
-    #[must_use]
-    #[inline]
-    pub fn find_matches<'a>(&'a self, src: &'a str) -> RegexMatcher<'a> {
-        self.find_matches_with_conf(src, DEFAULT_REGEX_CONF)
+    /// # Errors
+    /// If the regex fails to compile
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile_with_alphabet("^.+$", &['a', 'b', 'c']).unwrap();
+    /// assert!(regex.test("abcab"));
+    /// assert!(!regex.test("abd"));
+    /// ```
+    pub fn compile_with_alphabet(src: impl AsRef<str>, allowed: &[char]) -> Result<Self> {
+        let mut regex = Self::compile(src)?;
+        regex.alphabet = Some(allowed.into());
+        Ok(regex)
     }
 
-    /// Just like [`find_matches`](Self::find_matches), but uses a different configuration
+    /// Returns the pattern this [Regex] was compiled from
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("a(bc)*").unwrap();
+    /// assert_eq!(regex.as_str(), "a(bc)*");
+    /// ```
     #[must_use]
     #[inline]
-    pub fn find_matches_with_conf<'a>(&'a self, src: &'a str, conf: RegexConf) -> RegexMatcher<'a> {
-        RegexMatcher::new(src, &self.matches, conf)
+    pub fn as_str(&self) -> &str {
+        &self.src
     }
 
-    /// Returns true if the regex matches the given string
+    /// Returns true if the [Regex] can only match at the start of the input,
+    /// because it starts with `\A`, or with `^` outside of multiline mode
     ///
-    /// This is the same as calling ``find_matches``
-    /// and then checking if the iterator contains at least one element
+    /// Only the pattern is taken into account, so a [`RegexConf`] that
+    /// enables [multiline](RegexConf::multiline) mode makes `^` unanchored.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// assert!(Regex::compile("^ab|^c").unwrap().is_anchored_start());
+    /// assert!(!Regex::compile("^ab|c").unwrap().is_anchored_start());
+    /// assert!(!Regex::compile("(?m)^ab").unwrap().is_anchored_start());
+    /// ```
     #[must_use]
-    #[inline]
-    pub fn test(&self, src: &str) -> bool {
-        self.find_matches(src).next().is_some()
+    pub fn is_anchored_start(&self) -> bool {
+        case::anchored_start(&self.matches, self.flags.multiline)
     }
 
-    /// Just like [`test`](Self::test) but with a different configuration
+    /// Returns true if the [Regex] can only match at the end of the input,
+    /// because it ends with `\z`, or with `$` outside of multiline mode
+    ///
+    /// See [`is_anchored_start`](Self::is_anchored_start)
     #[must_use]
-    #[inline]
-    pub fn test_with_conf(&self, src: &str, conf: RegexConf) -> bool {
-        self.find_matches_with_conf(src, conf).next().is_some()
+    pub fn is_anchored_end(&self) -> bool {
+        case::anchored_end(&self.matches, self.flags.multiline)
     }
 
-    /// Replaces all matches of `self` on `src` with the `replacement` string
+    /// Returns the minimum number of characters a match of the [Regex] can have
+    ///
+    /// Strings with fewer [chars](str::chars) can't contain a match.
     ///
     /// # Example
     /// ```
     /// use regexpr::Regex;
-    /// use std::borrow::Cow;
     ///
-    /// let regex = Regex::compile("a[0-9]b").unwrap();
+    /// let regex = Regex::compile("ab?(c|de)+").unwrap();
+    /// assert_eq!(regex.min_len(), 2);
+    /// assert_eq!(regex.max_len(), None);
+    /// ```
+    #[must_use]
+    pub fn min_len(&self) -> usize {
+        case::min_len(&self.matches)
+    }
+
+    /// Returns the maximum number of characters a match of the [Regex]
+    /// can have, or [None] if there's no limit
     ///
-    /// assert_eq!(
-    ///     regex.replace("a1b_a2b_a12b", "P"),
-    ///     Cow::<str>::Owned(String::from("P_P_a12b"))
-    /// );
-    /// assert_eq!(regex.replace("ABCD", "P"), Cow::Borrowed("ABCD"));
+    /// There's no limit if the pattern has a `*`, a `+`, a `{n,}`
+    /// or a backreference. With [`RegexConf::grapheme_dot`], a `.` can
+    /// match more than one character, so the limit doesn't hold.
+    ///
+    /// # Example
     /// ```
-    pub fn replace<'a>(&self, src: &'a str, replacement: &str) -> Cow<'a, str> {
-        let matches = self.find_matches(src);
-        if matches.clone().next().is_none() {
-            return Cow::Borrowed(src);
-        }
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("ab?(c|de){1,3}").unwrap();
+    /// assert_eq!(regex.min_len(), 2);
+    /// assert_eq!(regex.max_len(), Some(8));
+    /// ```
+    #[must_use]
+    pub fn max_len(&self) -> Option<usize> {
+        case::max_len(&self.matches)
+    }
 
-        let mut result = String::new();
-        let mut curr = 0;
-        for m in matches {
-            let (start, end) = m.span();
-            result.push_str(&src[curr..start]);
-            result.push_str(replacement);
-            curr = end;
-        }
-        if let Some(remainder) = src.get(curr..) {
-            result.push_str(remainder);
-        }
+    /// Builds a normalized pattern from the compiled [Regex]
+    ///
+    /// Unlike [`as_str`](Self::as_str), which returns the original source,
+    /// this is reconstructed from the compiled expression. Compiling the
+    /// returned pattern results in an equivalent [Regex].
+    ///
+    /// Named capture groups and backreferences are emitted by their index.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("(?<x>a)[0-9.]{2,2}\\k<x>").unwrap();
+    /// assert_eq!(regex.to_pattern(), "(a)[0-9.]{2}\\1");
+    /// ```
+    #[must_use]
+    pub fn to_pattern(&self) -> String {
+        format!("{}{}", self.flags, case::to_pattern(&self.matches))
+    }
 
-        Cow::Owned(result)
+    /// Returns the compiled expression, as a list of [`MatchCase`]
+    /// that must match one after the other
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::{MatchCase, Regex};
+    ///
+    /// let regex = Regex::compile("a(b|c)").unwrap();
+    /// let [MatchCase::Char('a'), MatchCase::Group { case, capture_id: 1 }] = regex.ast() else {
+    ///     panic!()
+    /// };
+    /// assert!(matches!(**case, MatchCase::Or(_)));
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn ast(&self) -> &[MatchCase] {
+        &self.matches
     }
-}
 
-macro_rules! impl_tryfrom {
-    ($ty:ty) => {
-        impl TryFrom<$ty> for Regex {
-            type Error = RegexError;
+    /// Returns the number of capture groups in the [Regex]
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("(a)(b(c))").unwrap();
+    /// assert_eq!(regex.capture_count(), 3);
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn capture_count(&self) -> usize {
+        self.names.len()
+    }
 
-            fn try_from(value: $ty) -> Result<Self> {
-                Regex::compile(value)
-            }
-        }
-    };
-    (@with_refs $($ty:ty),* $(,)?) => {
-        impl_tryfrom!($( $ty , &$ty),* );
-    };
-    ($($ty:ty),* $(,)?) => {
-        $( impl_tryfrom!($ty); )*
+    /// Returns an [Iterator] over the names of the capture groups, in order.
+    /// Unnamed groups yield [None]
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("(?<year>\\d+)-(\\d+)").unwrap();
+    /// let names: Vec<_> = regex.capture_names().collect();
+    /// assert_eq!(names, [Some("year"), None]);
+    /// ```
+    pub fn capture_names(&self) -> impl Iterator<Item = Option<&str>> {
+        self.names.iter().map(Option::as_deref)
     }
-}
 
-impl_tryfrom!(@with_refs String, Cow<'_, str>, alloc::string::Drain<'_>);
-impl_tryfrom!(&str);
+    /// Returns a hash of the compiled expression
+    ///
+    /// Patterns that compile to the same expression have the same
+    /// hash, consistent with the [`PartialEq`] implementation.
+    /// Unlike [`Hash`], the result doesn't depend on a [`Hasher`],
+    /// and it's the same across runs of the program, so it can be
+    /// used as a key for an external cache.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let a = Regex::compile("a\\-b").unwrap();
+    /// let b = Regex::compile("a-b").unwrap();
+    /// assert_eq!(a, b);
+    /// assert_eq!(a.structural_hash(), b.structural_hash());
+    /// ```
+    #[must_use]
+    pub fn structural_hash(&self) -> u64 {
+        let mut hasher = FnvHasher::default();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
 
-/// This trait is used to add an extension method
-/// ``matches_regex`` to &str
-pub trait RegexTestable {
-    /// Returns true if it matches the given [Regex]
+    /// Returns an [Iterator] over all the [`matches`] of the [Regex] in the given string
     ///
-    /// __Note__: This method compiles the regex on each call. A more
-    /// optimal approach would be to use the `Regex::test` method
-    fn matches_regex(&self, regex: &str) -> bool;
-}
+    /// [`matches`]: RegexMatch
+    #[must_use]
+    #[inline]
+    pub fn find_matches<'a>(&'a self, src: &'a str) -> RegexMatcher<'a> {
+        self.find_matches_with_conf(src, DEFAULT_REGEX_CONF)
+    }
 
-impl<S: AsRef<str>> RegexTestable for S {
-    fn matches_regex(&self, regex: &str) -> bool {
-        Regex::compile(regex).is_ok_and(|regex| regex.test(self.as_ref()))
+    /// Just like [`find_matches`](Self::find_matches), but uses a different configuration
+    #[must_use]
+    #[inline]
+    pub fn find_matches_with_conf<'a>(&'a self, src: &'a str, conf: RegexConf) -> RegexMatcher<'a> {
+        RegexMatcher::new(src, &self.matches, self.flags.apply(conf))
+            .with_names(&self.names)
+            .with_prefix(&self.prefix)
+            .with_first_set(self.first_set.as_deref())
+            .with_memo(self.memoize)
+            .with_alphabet(self.alphabet.as_deref())
     }
-}
 
-pub trait ReplaceRegex {
-    /// Extension method for &str, that replaces all instances of a regex with a replacement string
+    /// Just like [`find_matches`](Self::find_matches), but calls `tracer`
+    /// each time the search enters or leaves a [`MatchCase`]
     ///
-    /// __Note__: This method compiles the regex on each call. A more optimal approach would be to use
-    /// the `Regex::replace` method
+    /// This is meant to debug why a pattern matches, or doesn't, where
+    /// it does. Each match attempt reports the cases it tries, in order,
+    /// including the ones of the branches later discarded. The clones
+    /// of the returned matcher share the same `tracer`.
     ///
-    /// # Errors
-    /// If the regex fails to compile
+    /// This requires the `diagnostics` and `std` features.
+    /// Without them, the search isn't traced at all
     ///
     /// # Example
     /// ```
-    /// use regexpr::ReplaceRegex;
+    /// use regexpr::{Regex, TraceEvent};
+    ///
+    /// let regex = Regex::compile("a|b").unwrap();
+    /// let mut trace = Vec::new();
+    /// let m = regex
+    ///     .find_matches_traced("b", |event| {
+    ///         if let TraceEvent::Exit { matched, .. } = event {
+    ///             trace.push((event.name(), event.offset(), matched));
+    ///         }
+    ///     })
+    ///     .next();
+    /// assert_eq!(m.unwrap().slice(), "b");
+    /// assert_eq!(
+    ///     trace,
+    ///     [
+    ///         ("Char", 1, false),
+    ///         ("Char", 1, true),
+    ///         ("List", 1, true),
+    ///         ("Or", 1, true),
+    ///     ]
+    /// );
+    /// ```
+    #[cfg(all(feature = "diagnostics", feature = "std"))]
+    #[must_use]
+    pub fn find_matches_traced<'a>(
+        &'a self,
+        src: &'a str,
+        tracer: impl FnMut(TraceEvent<'a>) + Send + 'a,
+    ) -> RegexMatcher<'a> {
+        self.find_matches(src).with_tracer(tracer)
+    }
+
+    /// Just like [`find_matches`](Self::find_matches), but starts
+    /// searching at the byte offset `start` of `src`
+    ///
+    /// See [`RegexMatcher::starting_at`]
+    ///
+    /// # Errors
+    /// If `start` is out of bounds, or not on a char boundary
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("\\bab").unwrap();
+    /// let spans: Vec<_> = regex.find_matches_at("ab xab ab", 1).unwrap().map(|m| m.span()).collect();
+    /// assert_eq!(spans, [(7, 9)]);
+    /// ```
+    pub fn find_matches_at<'a>(&'a self, src: &'a str, start: usize) -> Result<RegexMatcher<'a>> {
+        self.find_matches(src).starting_at(start)
+    }
+
+    /// Returns a [`StreamingMatcher`], that matches input fed incrementally
+    #[must_use]
+    #[inline]
+    pub fn streaming_matcher(&self) -> StreamingMatcher<'_> {
+        self.streaming_matcher_with_conf(DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`streaming_matcher`](Self::streaming_matcher), but uses a different configuration
+    #[must_use]
+    #[inline]
+    pub fn streaming_matcher_with_conf(&self, conf: RegexConf) -> StreamingMatcher<'_> {
+        StreamingMatcher::new(self, conf)
+    }
+
+    /// Returns a [`ReaderMatcher`], that finds the matches on
+    /// the contents of the given [reader](std::io::Read)
+    #[cfg(feature = "std")]
+    #[must_use]
+    #[inline]
+    pub fn find_matches_reader<R: std::io::Read>(&self, reader: R) -> ReaderMatcher<'_, R> {
+        self.find_matches_reader_with_conf(reader, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`find_matches_reader`](Self::find_matches_reader), but uses a different configuration
+    #[cfg(feature = "std")]
+    #[must_use]
+    #[inline]
+    pub fn find_matches_reader_with_conf<R: std::io::Read>(
+        &self,
+        reader: R,
+        conf: RegexConf,
+    ) -> ReaderMatcher<'_, R> {
+        ReaderMatcher::new(self, reader, conf)
+    }
+
+    /// Returns a [`CharsMatcher`], that finds the matches on
+    /// the characters of the given iterator
+    #[must_use]
+    #[inline]
+    pub fn find_matches_chars<I: Iterator<Item = char>>(&self, chars: I) -> CharsMatcher<'_, I> {
+        self.find_matches_chars_with_conf(chars, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`find_matches_chars`](Self::find_matches_chars), but uses a different configuration
+    #[must_use]
+    #[inline]
+    pub fn find_matches_chars_with_conf<I: Iterator<Item = char>>(
+        &self,
+        chars: I,
+        conf: RegexConf,
+    ) -> CharsMatcher<'_, I> {
+        CharsMatcher::new(self, chars, conf)
+    }
+
+    /// Just like [`find_matches`](Self::find_matches), but stops
+    /// searching once the deadline has passed
+    ///
+    /// The matches found before the deadline are still returned.
+    /// Use [`RegexMatcher::aborted`] to know if the search was cut short.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let regex = Regex::compile("a+b").unwrap();
+    /// let deadline = Instant::now() + Duration::from_millis(50);
+    /// let mut matches = regex.find_matches_with_deadline("aab", deadline);
+    /// assert_eq!(matches.next().unwrap().slice(), "aab");
+    /// ```
+    #[cfg(feature = "std")]
+    #[must_use]
+    #[inline]
+    pub fn find_matches_with_deadline<'a>(
+        &'a self,
+        src: &'a str,
+        deadline: std::time::Instant,
+    ) -> RegexMatcher<'a> {
+        self.find_matches(src).with_deadline(deadline)
+    }
+
+    /// Returns the first match of the [Regex] in the given string
+    ///
+    /// This is the same as calling [`find_matches`](Self::find_matches)
+    /// and taking the first element of the iterator
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("[0-9]+").unwrap();
+    /// assert_eq!(regex.find("abc 123 45").unwrap().span(), (4, 7));
+    /// assert!(regex.find("abc").is_none());
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn find<'a>(&'a self, src: &'a str) -> Option<RegexMatch<'a>> {
+        self.find_matches(src).next()
+    }
+
+    /// Just like [`find`](Self::find) but with a different configuration
+    #[must_use]
+    #[inline]
+    pub fn find_with_conf<'a>(&'a self, src: &'a str, conf: RegexConf) -> Option<RegexMatch<'a>> {
+        self.find_matches_with_conf(src, conf).next()
+    }
+
+    /// Returns the end offset of the first match of the [Regex] in the given string
+    ///
+    /// The match is the same one [`find`](Self::find) returns: the leftmost
+    /// one, choosing the alternatives and repetitions in the order the pattern
+    /// prefers them (leftmost-first). So it isn't necessarily the shortest
+    /// match, nor the longest. See [`find_longest`](Self::find_longest) for that.
+    ///
+    /// The capture groups aren't collected, which makes it faster than [`find`](Self::find).
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("[0-9]+").unwrap();
+    /// assert_eq!(regex.shortest_match("ab123 4"), Some(5));
+    /// let regex = Regex::compile("a+?").unwrap();
+    /// assert_eq!(regex.shortest_match("aaa"), Some(1));
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn shortest_match(&self, src: &str) -> Option<usize> {
+        self.shortest_match_with_conf(src, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`shortest_match`](Self::shortest_match) but with a different configuration
+    #[must_use]
+    pub fn shortest_match_with_conf(&self, src: &str, conf: RegexConf) -> Option<usize> {
+        let conf = RegexConf {
+            ignore_captures_in_result: true,
+            ..conf
+        };
+        self.find_with_conf(src, conf).map(|m| m.end())
+    }
+
+    /// Returns the capture groups of the first match of the [Regex] in the given string
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("(?<key>\\w+)=(\\d+)?").unwrap();
+    /// let caps = regex.captures("x a=1").unwrap();
+    /// assert_eq!(caps.get(0), Some("a=1"));
+    /// assert_eq!(caps.name("key"), Some("a"));
+    /// assert_eq!(caps.get(2), Some("1"));
+    /// assert_eq!(caps.get_match().span(), (2, 5));
+    ///
+    /// let caps = regex.captures("b=").unwrap();
+    /// assert_eq!(caps.get(2), None);
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn captures<'a>(&'a self, src: &'a str) -> Option<Captures<'a>> {
+        self.captures_with_conf(src, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`captures`](Self::captures) but with a different configuration
+    ///
+    /// The groups are always collected, even if the configuration
+    /// sets [`ignore_captures_in_result`](RegexConf::ignore_captures_in_result)
+    #[must_use]
+    pub fn captures_with_conf<'a>(&'a self, src: &'a str, conf: RegexConf) -> Option<Captures<'a>> {
+        let conf = RegexConf {
+            ignore_captures_in_result: false,
+            ..conf
+        };
+        self.find_with_conf(src, conf).map(Captures::from)
+    }
+
+    /// Returns the capture groups of all the matches of the [Regex] in the given string
+    ///
+    /// Each [`Captures`] holds the groups of its own match.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("(?<key>\\w+)=(?<value>\\w+)").unwrap();
+    /// let pairs: Vec<_> = regex
+    ///     .captures_iter("a=1 b=2")
+    ///     .map(|caps| (caps.name("key").unwrap(), caps.name("value").unwrap()))
+    ///     .collect();
+    /// assert_eq!(pairs, [("a", "1"), ("b", "2")]);
+    /// ```
+    #[inline]
+    pub fn captures_iter<'a>(&'a self, src: &'a str) -> impl Iterator<Item = Captures<'a>> {
+        self.captures_iter_with_conf(src, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`captures_iter`](Self::captures_iter) but with a different configuration
+    ///
+    /// The groups are always collected, even if the configuration
+    /// sets [`ignore_captures_in_result`](RegexConf::ignore_captures_in_result)
+    pub fn captures_iter_with_conf<'a>(
+        &'a self,
+        src: &'a str,
+        conf: RegexConf,
+    ) -> impl Iterator<Item = Captures<'a>> {
+        let conf = RegexConf {
+            ignore_captures_in_result: false,
+            ..conf
+        };
+        self.find_matches_with_conf(src, conf).map(Captures::from)
+    }
+
+    /// Returns the byte spans of the capture groups of all the
+    /// matches of the [Regex] in the given string
+    ///
+    /// Each item has the spans of one match. Index 0 is the whole match
+    /// and index n is the n'th group, so they line up with [`Captures::get`].
+    /// Groups that didn't participate in the match are [`None`], just
+    /// like in [`captures_iter`](Self::captures_iter).
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("(\\w)=(\\d)?").unwrap();
+    /// let spans: Vec<_> = regex.capture_indices("a=1 b=").collect();
+    /// assert_eq!(spans, [
+    ///     vec![Some((0, 3)), Some((0, 1)), Some((2, 3))],
+    ///     vec![Some((4, 6)), Some((4, 5)), None],
+    /// ]);
+    /// ```
+    #[inline]
+    pub fn capture_indices<'a>(
+        &'a self,
+        src: &'a str,
+    ) -> impl Iterator<Item = Vec<Option<(usize, usize)>>> + 'a {
+        self.capture_indices_with_conf(src, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`capture_indices`](Self::capture_indices) but with a different configuration
+    ///
+    /// The groups are always collected, even if the configuration
+    /// sets [`ignore_captures_in_result`](RegexConf::ignore_captures_in_result)
+    pub fn capture_indices_with_conf<'a>(
+        &'a self,
+        src: &'a str,
+        conf: RegexConf,
+    ) -> impl Iterator<Item = Vec<Option<(usize, usize)>>> + 'a {
+        let conf = RegexConf {
+            ignore_captures_in_result: false,
+            ..conf
+        };
+        let base = src.as_ptr() as usize;
+        let to_span = move |s: &str| {
+            let start = s.as_ptr() as usize - base;
+            (start, start + s.len())
+        };
+        self.find_matches_with_conf(src, conf).map(move |m| {
+            let mut spans = Vec::with_capacity(m.groups().len() + 1);
+            spans.push(Some(m.span()));
+            spans.extend(m.groups().iter().map(|g| g.map(to_span)));
+            spans
+        })
+    }
+
+    /// Returns the longest match of the [Regex] that starts at the
+    /// same position as [`find`](Self::find)
+    ///
+    /// Lazy quantifiers don't affect the length of the match, they
+    /// are only taken into account to decide the capture groups.
+    ///
+    /// Each possible end of the match is tried, from the end of the
+    /// input backwards, so this is slower than [`find`](Self::find).
+    /// This is the same as [`find_with_conf`](Self::find_with_conf)
+    /// with [`RegexConf::longest`] enabled
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("a+?").unwrap();
+    /// assert_eq!(regex.find("baaa").unwrap().slice(), "a");
+    /// assert_eq!(regex.find_longest("baaa").unwrap().slice(), "aaa");
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn find_longest<'a>(&'a self, src: &'a str) -> Option<RegexMatch<'a>> {
+        self.find_longest_with_conf(src, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`find_longest`](Self::find_longest) but with a different configuration
+    #[must_use]
+    pub fn find_longest_with_conf<'a>(
+        &'a self,
+        src: &'a str,
+        conf: RegexConf,
+    ) -> Option<RegexMatch<'a>> {
+        let conf = RegexConf {
+            longest: true,
+            ..conf
+        };
+        self.find_with_conf(src, conf)
+    }
+
+    /// Returns the last match of the [Regex] in the given string
+    ///
+    /// This is the same as taking the last element of [`find_matches`](Self::find_matches),
+    /// so the matches never overlap with the ones it returns. The input is
+    /// still scanned from the start, but the previous matches aren't stored.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("aa").unwrap();
+    /// assert_eq!(regex.rfind("aaaaa").unwrap().span(), (2, 4));
+    /// assert!(regex.rfind("a").is_none());
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn rfind<'a>(&'a self, src: &'a str) -> Option<RegexMatch<'a>> {
+        self.rfind_with_conf(src, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`rfind`](Self::rfind) but with a different configuration
+    #[must_use]
+    #[inline]
+    pub fn rfind_with_conf<'a>(&'a self, src: &'a str, conf: RegexConf) -> Option<RegexMatch<'a>> {
+        self.find_matches_with_conf(src, conf).last()
+    }
+
+    /// Returns the matches of the [Regex] in the given string, from the last one to the first
+    ///
+    /// The matches are the same ones [`find_matches`](Self::find_matches) returns.
+    /// Since the search can only go forward, all of them are found and stored
+    /// before returning the first one, so this takes memory proportional to
+    /// the number of matches. To get only the last match, [`rfind`](Self::rfind)
+    /// doesn't store the others.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("[0-9]+").unwrap();
+    /// let matches: Vec<_> = regex.rev_matches("1 22 333").map(|m| m.slice()).collect();
+    /// assert_eq!(matches, ["333", "22", "1"]);
+    /// ```
+    pub fn rev_matches<'a>(
+        &'a self,
+        src: &'a str,
+    ) -> core::iter::Rev<alloc::vec::IntoIter<RegexMatch<'a>>> {
+        self.find_matches(src).collect::<Vec<_>>().into_iter().rev()
+    }
+
+    /// Returns the first `n` matches of the [Regex] in the given string
+    ///
+    /// This is the same as calling [`find_matches`](Self::find_matches)
+    /// and taking `n` elements of the iterator. The search stops after
+    /// the n'th match, so the rest of the input isn't scanned.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("[0-9]+").unwrap();
+    /// let matches = regex.find_first_n("1 22 333", 2);
+    /// assert_eq!(matches.len(), 2);
+    /// assert_eq!(matches[1].slice(), "22");
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn find_first_n<'a>(&'a self, src: &'a str, n: usize) -> Vec<RegexMatch<'a>> {
+        self.find_first_n_with_conf(src, n, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`find_first_n`](Self::find_first_n) but with a different configuration
+    #[must_use]
+    pub fn find_first_n_with_conf<'a>(
+        &'a self,
+        src: &'a str,
+        n: usize,
+        conf: RegexConf,
+    ) -> Vec<RegexMatch<'a>> {
+        self.find_matches_with_conf(src, conf).take(n).collect()
+    }
+
+    /// Returns the number of matches of the [Regex] in the given string
+    ///
+    /// This is faster than counting the elements of [`find_matches`](Self::find_matches),
+    /// since the capture groups of each match are not collected.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("(a)b").unwrap();
+    /// assert_eq!(regex.match_count("ab_ab_aab"), 3);
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn match_count(&self, src: &str) -> usize {
+        self.match_count_with_conf(src, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`match_count`](Self::match_count) but with a different configuration
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::{Regex, RegexConf};
+    ///
+    /// let regex = Regex::compile("ab").unwrap();
+    /// let conf = RegexConf::new().case_sensitive(false);
+    /// assert_eq!(regex.match_count_with_conf("ab_AB_aB", conf), 3);
+    /// ```
+    #[must_use]
+    pub fn match_count_with_conf(&self, src: &str, conf: RegexConf) -> usize {
+        let conf = RegexConf {
+            ignore_captures_in_result: true,
+            ..conf
+        };
+        self.find_matches_with_conf(src, conf).count()
+    }
+
+    /// Returns true if the regex matches the given string
+    ///
+    /// This is the same as calling ``find_matches``
+    /// and then checking if the iterator contains at least one element
+    #[must_use]
+    #[inline]
+    pub fn test(&self, src: &str) -> bool {
+        self.find_matches(src).next().is_some()
+    }
+
+    /// Just like [`test`](Self::test) but with a different configuration
+    #[must_use]
+    #[inline]
+    pub fn test_with_conf(&self, src: &str, conf: RegexConf) -> bool {
+        self.find_matches_with_conf(src, conf).next().is_some()
+    }
+
+    /// Attempts to match the [Regex] exactly at the byte offset `start` of `src`
+    ///
+    /// Returns the offset where the match ends, or [None] if it doesn't
+    /// match at `start`. The rest of the input isn't searched, which makes
+    /// it useful to try several regexes at the cursor of a lexer.
+    /// The text before `start` is still seen by anchors like `^` and `\b`.
+    ///
+    /// # Errors
+    /// If `start` is out of bounds, or not on a char boundary
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("[0-9]+").unwrap();
+    /// assert_eq!(regex.is_match_at("ab123 4", 2).unwrap(), Some(5));
+    /// assert_eq!(regex.is_match_at("ab123 4", 1).unwrap(), None);
+    /// assert!(regex.is_match_at("ab123 4", 8).is_err());
+    /// ```
+    #[inline]
+    pub fn is_match_at(&self, src: &str, start: usize) -> Result<Option<usize>> {
+        self.is_match_at_with_conf(src, start, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`is_match_at`](Self::is_match_at) but with a different configuration
+    ///
+    /// # Errors
+    /// If `start` is out of bounds, or not on a char boundary
+    pub fn is_match_at_with_conf(
+        &self,
+        src: &str,
+        start: usize,
+        conf: RegexConf,
+    ) -> Result<Option<usize>> {
+        if !src.is_char_boundary(start) {
+            return Err(RegexError::new(
+                RegexErrorKind::BadOffset,
+                format!("Offset {start} is not on a char boundary"),
+            ));
+        }
+        let conf = RegexConf {
+            ignore_captures_in_result: true,
+            ..conf
+        };
+        let m = self.find_matches_with_conf(src, conf).match_at(start);
+        Ok(m.map(|m| m.end()))
+    }
+
+    /// Returns true if the [Regex] matches the whole string
+    ///
+    /// A single match is attempted at the start of `src`, that must
+    /// end at the end of it. This is the same as wrapping the pattern
+    /// in `\A` and `\z`, which makes it convenient to validate input.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("[0-9]+|[a-z]+").unwrap();
+    /// assert!(regex.is_full_match("123"));
+    /// assert!(regex.is_full_match("abc"));
+    /// assert!(!regex.is_full_match("123abc"));
+    /// assert!(!regex.is_full_match(" 123"));
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn is_full_match(&self, src: &str) -> bool {
+        self.is_full_match_with_conf(src, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`is_full_match`](Self::is_full_match) but with a different configuration
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::{Regex, RegexConf};
+    ///
+    /// let regex = Regex::compile("[a-z]+").unwrap();
+    /// let conf = RegexConf::new().case_sensitive(false);
+    /// assert!(regex.is_full_match_with_conf("aBc", conf));
+    /// assert!(!regex.is_full_match("aBc"));
+    /// ```
+    #[must_use]
+    pub fn is_full_match_with_conf(&self, src: &str, conf: RegexConf) -> bool {
+        let conf = RegexConf {
+            ignore_captures_in_result: true,
+            ..conf
+        };
+        self.find_matches_with_conf(src, conf)
+            .ending_at(src.len())
+            .match_at(0)
+            .is_some()
+    }
+
+    /// Replaces all matches of `self` on `src` with the `replacement` string
+    ///
+    /// The replacement is inserted literally, so a `$1` in it stays as is.
+    /// To expand references to the capture groups, use [`replace_all`](Self::replace_all)
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    /// use std::borrow::Cow;
+    ///
+    /// let regex = Regex::compile("a[0-9]b").unwrap();
+    ///
+    /// assert_eq!(
+    ///     regex.replace("a1b_a2b_a12b", "P"),
+    ///     Cow::<str>::Owned(String::from("P_P_a12b"))
+    /// );
+    /// assert_eq!(regex.replace("ABCD", "P"), Cow::Borrowed("ABCD"));
+    /// assert_eq!(regex.replace("a1b", "$0"), "$0");
+    /// ```
+    #[inline]
+    pub fn replace<'a>(&self, src: &'a str, replacement: &str) -> Cow<'a, str> {
+        self.replacen(src, usize::MAX, replacement)
+    }
+
+    /// Just like [`replace`](Self::replace) but with a different configuration
+    #[inline]
+    pub fn replace_with_conf<'a>(
+        &self,
+        src: &'a str,
+        replacement: &str,
+        conf: RegexConf,
+    ) -> Cow<'a, str> {
+        self.replacen_with_conf(src, usize::MAX, replacement, conf)
+    }
+
+    /// Replaces all matches of `self` on `src` with the `replacement`
+    /// template, expanded with the capture groups of each match
+    ///
+    /// Unlike [`replace`](Self::replace), which inserts the replacement as is,
+    /// the `$n`, `${n}` and `${name}` references of the template are replaced
+    /// by the capture groups. See [`RegexMatch::expand`] for the syntax.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("(?<key>\\w+)=(\\w+)").unwrap();
+    /// assert_eq!(regex.replace_all("a=1, b=2", "$2=${key}"), "1=a, 2=b");
+    /// assert_eq!(regex.replace_all("a=1", "$$"), "$");
+    /// ```
+    #[inline]
+    pub fn replace_all<'a>(&self, src: &'a str, replacement: &str) -> Cow<'a, str> {
+        self.replace_all_with_conf(src, replacement, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`replace_all`](Self::replace_all) but with a different configuration
+    ///
+    /// The groups are always collected, even if the configuration
+    /// sets [`ignore_captures_in_result`](RegexConf::ignore_captures_in_result)
+    pub fn replace_all_with_conf<'a>(
+        &self,
+        src: &'a str,
+        replacement: &str,
+        conf: RegexConf,
+    ) -> Cow<'a, str> {
+        let conf = RegexConf {
+            ignore_captures_in_result: false,
+            ..conf
+        };
+        self.replace_impl(src, usize::MAX, conf, |m, dst| {
+            m.expand_into(replacement, dst);
+        })
+    }
+
+    /// Replaces the first match of `self` on `src` with the `replacement` string
+    ///
+    /// To use a different configuration, call
+    /// [`replacen_with_conf`](Self::replacen_with_conf) with a `limit` of 1
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("[0-9]").unwrap();
+    /// assert_eq!(regex.replace_first("a1b2", "_"), "a_b2");
+    /// ```
+    pub fn replace_first<'a>(&self, src: &'a str, replacement: &str) -> Cow<'a, str> {
+        self.replacen(src, 1, replacement)
+    }
+
+    /// Replaces the first `limit` matches of `self` on `src` with the `replacement` string
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    /// use std::borrow::Cow;
+    ///
+    /// let regex = Regex::compile("[0-9]").unwrap();
+    /// assert_eq!(regex.replacen("1234", 2, "_"), "__34");
+    /// assert_eq!(regex.replacen("1234", 0, "_"), Cow::Borrowed("1234"));
+    /// ```
+    #[inline]
+    pub fn replacen<'a>(&self, src: &'a str, limit: usize, replacement: &str) -> Cow<'a, str> {
+        self.replacen_with_conf(src, limit, replacement, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`replacen`](Self::replacen) but with a different configuration
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::{Regex, RegexConf};
+    ///
+    /// let regex = Regex::compile("[a-z]").unwrap();
+    /// let conf = RegexConf::new().case_sensitive(false);
+    /// assert_eq!(regex.replacen_with_conf("AbCd", 3, "_", conf), "___d");
+    /// ```
+    pub fn replacen_with_conf<'a>(
+        &self,
+        src: &'a str,
+        limit: usize,
+        replacement: &str,
+        conf: RegexConf,
+    ) -> Cow<'a, str> {
+        let conf = RegexConf {
+            ignore_captures_in_result: true,
+            ..conf
+        };
+        self.replace_impl(src, limit, conf, |_, dst| dst.push_str(replacement))
+    }
+
+    /// Replaces all matches of `self` on `src` with the result of calling `f` on them
+    ///
+    /// See [`replace_with_fn_conf`](Self::replace_with_fn_conf) to use a different configuration
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("[a-z]+").unwrap();
+    /// assert_eq!(
+    ///     regex.replace_with("ab 12 cde", |m| m.slice().len().to_string()),
+    ///     "2 12 3"
+    /// );
+    /// ```
+    pub fn replace_with<'a, S: AsRef<str>>(
+        &self,
+        src: &'a str,
+        f: impl FnMut(&RegexMatch<'_>) -> S,
+    ) -> Cow<'a, str> {
+        self.replace_with_fn_conf(src, f, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`replace_with`](Self::replace_with) but with a different configuration
+    ///
+    /// The groups are always collected, even if the configuration
+    /// sets [`ignore_captures_in_result`](RegexConf::ignore_captures_in_result)
+    pub fn replace_with_fn_conf<'a, S: AsRef<str>>(
+        &self,
+        src: &'a str,
+        mut f: impl FnMut(&RegexMatch<'_>) -> S,
+        conf: RegexConf,
+    ) -> Cow<'a, str> {
+        let conf = RegexConf {
+            ignore_captures_in_result: false,
+            ..conf
+        };
+        self.replace_impl(src, usize::MAX, conf, |m, dst| dst.push_str(f(m).as_ref()))
+    }
+
+    /// Splits `src` on the matches of `self`, keeping the matches
+    ///
+    /// The text between the matches is returned as [`Piece::Text`], and
+    /// each match as a [`Piece::Delim`]. The pieces cover the whole input,
+    /// so concatenating them gives back `src`. There's always a text
+    /// piece before and after each delimiter, even if it's empty.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::{Piece, Regex};
+    ///
+    /// let regex = Regex::compile(",").unwrap();
+    /// let pieces = regex.split_with_delimiters("a,,b");
+    /// let pieces: Vec<_> = pieces.iter().map(Piece::as_str).collect();
+    /// assert_eq!(pieces, ["a", ",", "", ",", "b"]);
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn split_with_delimiters<'a>(&'a self, src: &'a str) -> Vec<Piece<'a>> {
+        self.split_with_delimiters_with_conf(src, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`split_with_delimiters`](Self::split_with_delimiters) but with a different configuration
+    #[must_use]
+    pub fn split_with_delimiters_with_conf<'a>(
+        &'a self,
+        src: &'a str,
+        conf: RegexConf,
+    ) -> Vec<Piece<'a>> {
+        let mut pieces = Vec::new();
+        let mut curr = 0;
+        for m in self.find_matches_with_conf(src, conf) {
+            let (start, end) = m.span();
+            pieces.push(Piece::Text(&src[curr..start]));
+            pieces.push(Piece::Delim(m));
+            curr = end;
+        }
+        pieces.push(Piece::Text(&src[curr..]));
+        pieces
+    }
+
+    /// Splits `src` on the matches of `self`, keeping each match at
+    /// the end of the segment it terminates, like [`str::split_inclusive`]
+    ///
+    /// The text after the last match is the last segment, if it isn't empty.
+    /// Empty segments are skipped, so a zero-width match, like the ones of
+    /// `\b`, splits the input where it matches but never yields an empty one.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("\r?\n").unwrap();
+    /// let lines: Vec<_> = regex.split_inclusive("a\r\nb\n\nc").collect();
+    /// assert_eq!(lines, ["a\r\n", "b\n", "\n", "c"]);
+    ///
+    /// let regex = Regex::compile("\\b").unwrap();
+    /// let words: Vec<_> = regex.split_inclusive("ab cd").collect();
+    /// assert_eq!(words, ["ab", " ", "cd"]);
+    /// ```
+    #[inline]
+    pub fn split_inclusive<'a>(&'a self, src: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.split_inclusive_with_conf(src, DEFAULT_REGEX_CONF)
+    }
+
+    /// Just like [`split_inclusive`](Self::split_inclusive) but with a different configuration
+    pub fn split_inclusive_with_conf<'a>(
+        &'a self,
+        src: &'a str,
+        conf: RegexConf,
+    ) -> impl Iterator<Item = &'a str> + 'a {
+        let conf = RegexConf {
+            ignore_captures_in_result: true,
+            ..conf
+        };
+        let mut matches = self.find_matches_with_conf(src, conf);
+        let mut curr = 0;
+        core::iter::from_fn(move || {
+            for m in matches.by_ref() {
+                if m.end() > curr {
+                    let segment = &src[curr..m.end()];
+                    curr = m.end();
+                    return Some(segment);
+                }
+            }
+            let rest = &src[curr..];
+            curr = src.len();
+            (!rest.is_empty()).then_some(rest)
+        })
+    }
+
+    /// Replaces up to `limit` matches, writing the replacement of each one with `f`
+    ///
+    /// If nothing is replaced, `src` is returned borrowed, without allocating
+    fn replace_impl<'a>(
+        &self,
+        src: &'a str,
+        limit: usize,
+        conf: RegexConf,
+        mut f: impl FnMut(&RegexMatch<'_>, &mut String),
+    ) -> Cow<'a, str> {
+        let mut matches = self
+            .find_matches_with_conf(src, conf)
+            .take(limit)
+            .peekable();
+        if matches.peek().is_none() {
+            return Cow::Borrowed(src);
+        }
+
+        let mut result = String::new();
+        let mut curr = 0;
+        for m in matches {
+            let (start, end) = m.span();
+            result.push_str(&src[curr..start]);
+            f(&m, &mut result);
+            curr = end;
+        }
+        if let Some(remainder) = src.get(curr..) {
+            result.push_str(remainder);
+        }
+
+        Cow::Owned(result)
+    }
+}
+
+macro_rules! impl_tryfrom {
+    ($ty:ty) => {
+        impl TryFrom<$ty> for Regex {
+            type Error = RegexError;
+
+            fn try_from(value: $ty) -> Result<Self> {
+                Regex::compile(value)
+            }
+        }
+    };
+    (@with_refs $($ty:ty),* $(,)?) => {
+        impl_tryfrom!($( $ty , &$ty),* );
+    };
+    ($($ty:ty),* $(,)?) => {
+        $( impl_tryfrom!($ty); )*
+    }
+}
+
+impl_tryfrom!(@with_refs String, Cow<'_, str>, alloc::string::Drain<'_>);
+impl_tryfrom!(&str);
+
+/// Escapes all the metacharacters of `s`, so the
+/// resulting pattern matches `s` literally
+///
+/// # Example
+/// ```
+/// use regexpr::Regex;
+///
+/// let pattern = format!("^{}$", regexpr::escape("1+1=(2)"));
+/// assert_eq!(pattern, "^1\\+1=\\(2\\)$");
+/// assert!(Regex::compile(&pattern).unwrap().test("1+1=(2)"));
+/// ```
+#[must_use]
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if case::META_CHARS.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// This trait is used to add an extension method
+/// ``matches_regex`` to &str
+pub trait RegexTestable {
+    /// Returns true if it matches the given [Regex]
+    ///
+    /// __Note__: This method compiles the regex on each call. A more
+    /// optimal approach would be to use the `Regex::test` method
+    ///
+    /// __Warning__: If the regex fails to compile, this returns false,
+    /// just like if it didn't match. Use [`try_matches_regex`] to tell
+    /// both cases apart.
+    ///
+    /// [`try_matches_regex`]: Self::try_matches_regex
+    fn matches_regex(&self, regex: &str) -> bool;
+
+    /// Returns true if it matches the given [Regex]
+    ///
+    /// By default, it checks that the regex compiles, and then
+    /// calls [`matches_regex`](Self::matches_regex)
+    ///
+    /// # Errors
+    /// If the regex fails to compile
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::RegexTestable;
+    ///
+    /// assert!("abc".try_matches_regex("b+").unwrap());
+    /// assert!("abc".try_matches_regex("*b").is_err());
+    /// ```
+    fn try_matches_regex(&self, regex: &str) -> Result<bool> {
+        Regex::compile(regex)?;
+        Ok(self.matches_regex(regex))
+    }
+}
+
+impl<S: AsRef<str>> RegexTestable for S {
+    fn matches_regex(&self, regex: &str) -> bool {
+        self.try_matches_regex(regex).unwrap_or(false)
+    }
+
+    fn try_matches_regex(&self, regex: &str) -> Result<bool> {
+        Regex::compile(regex).map(|regex| regex.test(self.as_ref()))
+    }
+}
+
+pub trait ReplaceRegex {
+    /// Extension method for &str, that replaces all instances of a regex with a replacement string
+    ///
+    /// The `$n`, `${n}` and `${name}` references of the replacement are expanded
+    /// with the capture groups, like in [`Regex::replace_all`]
+    ///
+    /// __Note__: This method compiles the regex on each call. A more optimal approach would be to use
+    /// the `Regex::replace_all` method
+    ///
+    /// # Errors
+    /// If the regex fails to compile
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::ReplaceRegex;
     ///
     /// assert_eq!(
     ///     "_a12345b_".replace_regex("a[0-9]+b", "N").unwrap(),
     ///     "_N_",
-    /// )
+    /// );
+    /// assert_eq!("a=1".replace_regex("(\\w)=(\\d)", "$2=$1").unwrap(), "1=a");
     /// ```
     fn replace_regex<'a>(&'a self, regex: &str, replacement: &str) -> Result<Cow<'a, str>>;
 }
 
 impl ReplaceRegex for &str {
     fn replace_regex<'a>(&'a self, regex: &str, replacement: &str) -> Result<Cow<'a, str>> {
-        Regex::compile(regex).map(|regex| regex.replace(self, replacement))
+        Regex::compile(regex).map(|regex| regex.replace_all(self, replacement))
     }
 }
 
+#[cfg(feature = "serde")]
+mod serde;
+
+#[cfg(test)]
+mod fuzz;
 #[cfg(test)]
 mod test;
 
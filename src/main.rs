@@ -21,18 +21,11 @@ fn start_tui() {
         stdin().lines().map_while(Result::ok).for_each(|line| {
             #[allow(unused)]
             let time = std::time::Instant::now();
-            let mut it = regex.find_matches(&line);
-            if it.clone().next().is_none() {
+            if regex.find(&line).is_none() {
                 println!("No matches");
             } else {
-                for (i, m) in (&mut it).enumerate() {
-                    println!("{}) {m}", i + 1);
-                    if m.get_captures().iter().any(|l| !l.is_empty()) {
-                        println!("  Captures:");
-                        for (i, m) in m.get_captures().iter().enumerate() {
-                            println!("  {}) \"{m}\"", i + 1);
-                        }
-                    }
+                for (i, m) in regex.find_matches(&line).enumerate() {
+                    println!("{}) {m:#}", i + 1);
                 }
             }
             #[cfg(debug_assertions)]
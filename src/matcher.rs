@@ -1,8 +1,19 @@
-use crate::{MatchCase, RegexConf};
-use alloc::borrow::Cow;
+use crate::case::{self, fold_case, is_word_char};
+use crate::{MatchCase, RegexConf, RegexError, RegexErrorKind};
+use alloc::boxed::Box;
+use alloc::string::String;
+use core::cell::Cell;
 use core::fmt::Display;
 use core::iter::FusedIterator;
 use core::str::CharIndices;
+#[cfg(feature = "memchr")]
+use memchr::memmem;
+#[cfg(all(feature = "diagnostics", feature = "std"))]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "std")]
+use std::time::Instant;
+#[cfg(feature = "unicode-segmentation")]
+use unicode_segmentation::UnicodeSegmentation;
 
 #[cfg(doc)]
 use crate::Regex;
@@ -14,22 +25,59 @@ use crate::Regex;
 pub struct RegexMatch<'a> {
     start: usize,
     slice: &'a str,
-    captures: Option<Vec<&'a str>>,
+    captures: Option<Vec<Option<&'a str>>>,
+    names: &'a [Option<Box<str>>],
+    decisions: Vec<(usize, usize)>,
 }
 
 impl<'a> RegexMatch<'a> {
     /// Gets the span of the string where it matched the [Regex]
     #[must_use]
     pub fn span(&self) -> (usize, usize) {
-        let o = self.start;
-        (o, o + self.slice.len())
+        (self.start(), self.end())
+    }
+    /// Byte offset where the match starts
+    #[must_use]
+    #[inline]
+    pub fn start(&self) -> usize {
+        self.start
+    }
+    /// Byte offset where the match ends
+    #[must_use]
+    #[inline]
+    pub fn end(&self) -> usize {
+        self.start + self.slice.len()
+    }
+    /// Length of the match, in bytes
+    #[must_use]
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.slice.len()
+    }
+    /// Returns true if the match is empty, like the ones of `a*` or `\b`
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("a*").unwrap();
+    /// let m = regex.find("baa").unwrap();
+    /// assert!(m.is_empty());
+    /// assert_eq!((m.start(), m.end()), (0, 0));
+    /// let m = regex.find("aab").unwrap();
+    /// assert_eq!((m.start(), m.end(), m.len()), (0, 2, 2));
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.slice.is_empty()
     }
     /// Gets the slice of the string that matched the [Regex]
     ///
     /// This is the same as calling ``get_span``
     /// and then using it to slice the source string
     #[must_use]
-    pub fn slice(&self) -> &str {
+    pub fn slice(&self) -> &'a str {
         self.slice
     }
 
@@ -37,25 +85,283 @@ impl<'a> RegexMatch<'a> {
     ///
     /// The groups are returned in order, which means that
     /// capture group 1 will be at index 0, and so on.
+    ///
+    /// Groups that didn't participate in the match are returned
+    /// as an empty string. Use [`groups`](Self::groups) to tell them
+    /// apart from groups that matched an empty string.
     #[must_use]
-    pub fn get_captures(&self) -> &[&'a str] {
+    #[deprecated(note = "Use RegexMatch::groups")]
+    pub fn get_captures(&self) -> Vec<&'a str> {
+        self.groups().iter().map(|g| g.unwrap_or("")).collect()
+    }
+
+    /// Gets the capture groups of this match
+    ///
+    /// The groups are returned in order, which means that
+    /// capture group 1 will be at index 0, and so on.
+    /// Groups that didn't participate in the match are [`None`].
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("(a)|(b)()").unwrap();
+    /// let m = regex.find("b").unwrap();
+    /// assert_eq!(m.groups(), &[None, Some("b"), Some("")]);
+    /// ```
+    #[must_use]
+    pub fn groups(&self) -> &[Option<&'a str>] {
         self.captures.as_deref().unwrap_or(&[])
     }
+
+    /// Gets the n'th capture group of this match
+    ///
+    /// Group 0 is the whole match. Returns [`None`] if the group
+    /// doesn't exist, or didn't participate in the match.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("(a+)(c)?").unwrap();
+    /// let m = regex.find("xaab").unwrap();
+    /// assert_eq!(m.group(0), Some("aa"));
+    /// assert_eq!(m.group(1), Some("aa"));
+    /// assert_eq!(m.group(2), None);
+    /// ```
+    #[must_use]
+    pub fn group(&self, n: usize) -> Option<&'a str> {
+        match n {
+            0 => Some(self.slice),
+            n => self.groups().get(n - 1).copied().flatten(),
+        }
+    }
+
+    /// Expands the `template` with the capture groups of this match
+    ///
+    /// `$n` and `${n}` are replaced by the n'th capture group, and `${name}`
+    /// by the group with that name. `$0` is the whole match, and `$$` is
+    /// a literal `$`. References to groups that don't exist expand to an
+    /// empty string.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("(?<key>\\w+)=(\\w+)").unwrap();
+    /// let m = regex.find_matches("a=1").next().unwrap();
+    /// assert_eq!(m.expand("$2 <- ${key}"), "1 <- a");
+    /// ```
+    #[must_use]
+    pub fn expand(&self, template: &str) -> String {
+        let mut result = String::with_capacity(template.len());
+        self.expand_into(template, &mut result);
+        result
+    }
+
+    pub(crate) fn expand_into(&self, template: &str, dst: &mut String) {
+        let mut rest = template;
+        while let Some(i) = rest.find('$') {
+            dst.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+
+            let (group, len) = if rest.starts_with('$') {
+                dst.push('$');
+                rest = &rest[1..];
+                continue;
+            } else if let Some(braced) = rest.strip_prefix('{')
+                && let Some(end) = braced.find('}')
+            {
+                (&braced[..end], end + 2)
+            } else {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                if end == 0 {
+                    dst.push('$');
+                    continue;
+                }
+                (&rest[..end], end)
+            };
+            rest = &rest[len..];
+
+            if let Some(text) = self.group_by_name(group) {
+                dst.push_str(text);
+            }
+        }
+        dst.push_str(rest);
+    }
+
+    fn group_by_name(&self, name: &str) -> Option<&'a str> {
+        let id = match name.parse::<usize>() {
+            Ok(id) => id,
+            Err(_) => self.names.iter().position(|n| n.as_deref() == Some(name))? + 1,
+        };
+        self.group(id)
+    }
+
+    /// Gets the branches taken on each alternation while matching
+    ///
+    /// Each element is a pair of the offset where the alternation
+    /// started, and the index of the branch that matched.
+    ///
+    /// This is only recorded if [`RegexConf::trace_decisions`] is set,
+    /// otherwise it's empty.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::{Regex, RegexConf};
+    ///
+    /// let regex = Regex::compile("(a|ab)c").unwrap();
+    /// let conf = RegexConf {
+    ///     trace_decisions: true,
+    ///     ..RegexConf::default()
+    /// };
+    /// let m = regex.find_matches_with_conf("abc", conf).next().unwrap();
+    /// assert_eq!(m.decision_trace(), &[(0, 1)]);
+    /// ```
+    #[must_use]
+    pub fn decision_trace(&self) -> &[(usize, usize)] {
+        &self.decisions
+    }
 }
 
+/// Formats the span and the text of the match.
+///
+/// The alternate form (`{:#}`) also lists the capture groups,
+/// one per line, if any of them participated in the match.
+///
+/// # Example
+/// ```
+/// use regexpr::Regex;
+///
+/// let regex = Regex::compile("(a)(x)?b").unwrap();
+/// let m = regex.find("cab").unwrap();
+/// assert_eq!(m.to_string(), "[1,3]: \"ab\"");
+/// assert_eq!(
+///     format!("{m:#}"),
+///     "[1,3]: \"ab\"\n  Captures:\n  1) \"a\"\n  2) <unmatched>"
+/// );
+/// ```
 impl Display for RegexMatch<'_> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let (s, e) = self.span();
-        write!(f, "[{s},{e}]: \"{}\"", self.slice())
+        write!(f, "[{s},{e}]: \"{}\"", self.slice())?;
+        if !f.alternate() || self.groups().iter().all(Option::is_none) {
+            return Ok(());
+        }
+        write!(f, "\n  Captures:")?;
+        for (i, group) in self.groups().iter().enumerate() {
+            match group {
+                Some(group) => write!(f, "\n  {}) \"{group}\"", i + 1)?,
+                None => write!(f, "\n  {}) <unmatched>", i + 1)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The capture groups of a match, as returned by [`Regex::captures`]
+///
+/// Owns the groups of its match, so they stay valid
+/// while the search goes on.
+#[derive(Debug)]
+pub struct Captures<'a> {
+    m: RegexMatch<'a>,
+}
+
+impl<'a> Captures<'a> {
+    /// Gets the n'th capture group
+    ///
+    /// Group 0 is the whole match. Returns [`None`] if the group
+    /// doesn't exist, or didn't participate in the match.
+    #[must_use]
+    pub fn get(&self, n: usize) -> Option<&'a str> {
+        self.m.group(n)
+    }
+
+    /// Gets the capture group with the given name
+    ///
+    /// Returns [`None`] if there's no group with that name,
+    /// or it didn't participate in the match.
+    #[must_use]
+    pub fn name(&self, name: &str) -> Option<&'a str> {
+        let id = self
+            .m
+            .names
+            .iter()
+            .position(|n| n.as_deref() == Some(name))?;
+        self.m.group(id + 1)
+    }
+
+    /// Gets the whole match
+    #[must_use]
+    pub fn get_match(&self) -> &RegexMatch<'a> {
+        &self.m
+    }
+
+    /// Returns the number of groups, including the whole match
+    #[must_use]
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.m.groups().len() + 1
+    }
+}
+
+impl<'a> From<RegexMatch<'a>> for Captures<'a> {
+    fn from(m: RegexMatch<'a>) -> Self {
+        Captures { m }
+    }
+}
+
+/// A piece of the input, as returned by [`Regex::split_with_delimiters`]
+#[derive(Debug)]
+pub enum Piece<'a> {
+    /// Text between two matches
+    Text(&'a str),
+    /// A match of the [Regex]
+    Delim(RegexMatch<'a>),
+}
+
+impl<'a> Piece<'a> {
+    /// Returns the text covered by this piece
+    #[must_use]
+    pub fn as_str(&self) -> &'a str {
+        match self {
+            Piece::Text(text) => text,
+            Piece::Delim(m) => m.slice(),
+        }
     }
 }
 
 /// Iterator over all the matches of a string in a [Regex]
+///
+/// It holds the state of a single search, so it isn't [Sync].
+/// To search from several threads, share the [Regex] instead,
+/// and start a search on each thread.
 #[derive(Debug, Clone)]
 pub struct RegexMatcher<'a> {
     first: bool,
-    ctx: RegexCtx<'a, 'a>,
-    cases: LookAhead<'a, 'a>,
+    /// Whether a match has been attempted at the end of the input
+    end_tried: bool,
+    names: &'a [Option<Box<str>>],
+    /// Literal text every match starts with
+    prefix: &'a str,
+    /// Cases one of which matches the first character of any match
+    first_set: Option<&'a [MatchCase]>,
+    #[cfg(feature = "memchr")]
+    prefix_finder: memmem::Finder<'a>,
+    base: usize,
+    /// Offset of the first match attempt that read past the end of the input
+    end_reached_from: Option<usize>,
+    /// Last known span of each capture group
+    group_spans: Vec<Option<(usize, usize)>>,
+    /// Reason why the search was aborted
+    error: Option<RegexError>,
+    /// Whether the iterator has returned [`None`]
+    done: bool,
+    ctx: RegexCtx<'a>,
+    cases: &'a [MatchCase],
 }
 
 impl<'a> RegexMatcher<'a> {
@@ -63,246 +369,1405 @@ impl<'a> RegexMatcher<'a> {
     pub fn new(src: &'a str, matches: &'a [MatchCase], conf: RegexConf) -> Self {
         RegexMatcher {
             first: true,
-            cases: LookAhead::new(LookAheadKind::List(matches), None),
+            end_tried: false,
+            names: &[],
+            prefix: "",
+            first_set: None,
+            #[cfg(feature = "memchr")]
+            prefix_finder: memmem::Finder::new(""),
+            base: 0,
+            end_reached_from: None,
+            group_spans: Vec::new(),
+            error: None,
+            done: false,
+            cases: matches,
             ctx: RegexCtx {
-                captures: Cow::Borrowed(&[]),
-                open_captures: Cow::Borrowed(&[][..]),
+                src,
+                groups: Groups::default(),
                 conf,
                 nc: src.char_indices(),
+                eoi: None,
+                budget: Budget::new(conf.max_steps, conf.max_depth),
+                alphabet: None,
+                end_at: None,
+                furthest_end: None,
+                prev_end: Some(0),
+                memoize: false,
+                bookkeeping: true,
+                frames: Vec::new(),
+                saved: Vec::new(),
+                alternatives: 0,
+                positions: Vec::new(),
+                #[cfg(feature = "diagnostics")]
+                stats: Cell::default(),
+                #[cfg(all(feature = "diagnostics", feature = "std"))]
+                tracer: None,
             },
         }
     }
-}
 
-impl<'a> Iterator for RegexMatcher<'a> {
-    type Item = RegexMatch<'a>;
+    /// Sets the names of the capture groups, used by [`RegexMatch::expand`].
+    /// There's one for each group, so without them there's no capture
+    /// bookkeeping to do
+    pub(crate) fn with_names(mut self, names: &'a [Option<Box<str>>]) -> Self {
+        self.names = names;
+        self.ctx.bookkeeping = !names.is_empty() || self.ctx.conf.trace_decisions;
+        self
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Sets the literal text every match starts with, so the
+    /// positions where it doesn't appear can be skipped
+    pub(crate) fn with_prefix(mut self, prefix: &'a str) -> Self {
+        self.prefix = prefix;
+        #[cfg(feature = "memchr")]
+        {
+            self.prefix_finder = memmem::Finder::new(prefix);
+        }
+        self
+    }
+
+    /// Calls `tracer` each time the search enters or leaves a [`MatchCase`].
+    /// See [`Regex::find_matches_traced`]
+    #[cfg(all(feature = "diagnostics", feature = "std"))]
+    pub(crate) fn with_tracer(mut self, tracer: impl FnMut(TraceEvent<'a>) + Send + 'a) -> Self {
+        self.ctx.tracer = Some(Tracer(Arc::new(Mutex::new(tracer))));
+        self
+    }
+
+    /// Sets the cases one of which matches the first character of any
+    /// match, so the positions where none does can be skipped
+    pub(crate) fn with_first_set(mut self, first_set: Option<&'a [MatchCase]>) -> Self {
+        self.first_set = first_set;
+        self
+    }
+
+    /// Lets the search remember where parts of the pattern failed to
+    /// match, to not try them there again. Only if the pattern doesn't
+    /// depend on what the groups captured
+    pub(crate) fn with_memo(mut self, memoize: bool) -> Self {
+        self.ctx.memoize = memoize;
+        self
+    }
+
+    /// Restricts the characters `.` and the negated classes can match
+    pub(crate) fn with_alphabet(mut self, alphabet: Option<&'a [char]>) -> Self {
+        self.ctx.alphabet = alphabet;
+        self
+    }
+
+    /// Attempts a single match, starting at the given byte offset
+    pub(crate) fn match_at(mut self, offset: usize) -> Option<RegexMatch<'a>> {
+        self.skip_to(offset);
+        self.ctx.prev_end = Some(offset);
+        self.attempt()
+    }
+
+    /// Only accepts matches that end at the given byte offset
+    pub(crate) fn ending_at(mut self, end: usize) -> Self {
+        self.ctx.end_at = Some(end);
+        self
+    }
+
+    /// Sets where the previous match ended, for `\G`.
+    /// [`None`] means it's not in the input
+    pub(crate) fn with_prev_end(mut self, end: Option<usize>) -> Self {
+        self.ctx.prev_end = end;
+        self
+    }
+
+    /// Records in `flag` whether any match attempt tried to
+    /// read past the end of the input
+    pub(crate) fn track_end(mut self, flag: &'a Cell<bool>) -> Self {
+        self.ctx.eoi = Some(flag);
+        self
+    }
+
+    /// Byte offset at which the first match attempt that read past
+    /// the end of the input started. Requires [`track_end`](Self::track_end)
+    pub(crate) fn end_reached_from(&self) -> Option<usize> {
+        self.end_reached_from
+    }
+
+    /// Resumes the search at the given byte offset, as if all the
+    /// input before it had already been consumed by previous matches
+    pub(crate) fn skip_to(&mut self, offset: usize) {
+        while self.ctx.char_offset() < offset && self.ctx.nc.next().is_some() {
+            self.first = false;
+        }
+    }
+
+    /// Byte offset at which the next match attempt will start
+    pub(crate) fn offset(&self) -> usize {
+        self.ctx.char_offset()
+    }
+
+    /// Returns the part of the input that hasn't been consumed yet
+    ///
+    /// After a match, this is the input that follows it.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("^[a-z]+").unwrap();
+    /// let mut matcher = regex.find_matches("key=value");
+    /// assert_eq!(matcher.next().unwrap().slice(), "key");
+    /// assert_eq!(matcher.remaining(), "=value");
+    /// ```
+    #[must_use]
+    pub fn remaining(&self) -> &'a str {
+        self.ctx.remaining()
+    }
+
+    /// Starts the search at the given byte offset of the input
+    ///
+    /// The spans of the matches are still relative to the start of the input,
+    /// and anchors like `^` and `\b` still see the text before `offset`.
+    /// The search doesn't go back, so an offset before the current
+    /// position has no effect.
+    ///
+    /// # Errors
+    /// If `offset` is out of bounds, or not on a char boundary
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::{Regex, RegexConf};
+    ///
+    /// let regex = Regex::compile("[0-9]+").unwrap();
+    /// let conf = RegexConf::default();
+    /// let mut matcher = regex.find_matches_with_conf("1 22 333", conf).starting_at(3).unwrap();
+    /// assert_eq!(matcher.next().unwrap().span(), (3, 4));
+    /// assert_eq!(matcher.next().unwrap().span(), (5, 8));
+    /// ```
+    pub fn starting_at(mut self, offset: usize) -> Result<Self, RegexError> {
+        if !self.ctx.src.is_char_boundary(offset) {
+            return Err(RegexError::new(
+                RegexErrorKind::BadOffset,
+                format!("Offset {offset} is not on a char boundary"),
+            ));
+        }
+        self.skip_to(offset);
+        self.ctx.prev_end = Some(self.offset());
+        Ok(self)
+    }
+
+    /// Shifts the spans of all the matches by `base`
+    ///
+    /// Useful when `src` is a slice of a larger string, to get
+    /// the spans relative to the start of that larger string.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let doc = "first line\nsecond line";
+    /// let regex = Regex::compile("line").unwrap();
+    /// let m = regex.find_matches(&doc[11..]).with_base_offset(11).next().unwrap();
+    /// assert_eq!(m.span(), (18, 22));
+    /// assert_eq!(m.slice(), "line");
+    /// ```
+    #[must_use]
+    pub fn with_base_offset(mut self, base: usize) -> Self {
+        self.base = base;
+        self
+    }
+
+    /// Stops the search once the given deadline has passed
+    ///
+    /// The clock is checked periodically while matching, so the
+    /// search may run for a little longer than the deadline.
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.ctx.budget.deadline = Some(deadline);
+        self
+    }
+
+    /// Returns the reason why the search was [aborted](Self::aborted)
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::{Regex, RegexConf};
+    ///
+    /// let regex = Regex::compile("a").unwrap();
+    /// let conf = RegexConf {
+    ///     max_steps: 3,
+    ///     ..Default::default()
+    /// };
+    /// let mut matcher = regex.find_matches_with_conf("aaaaa", conf);
+    /// assert_eq!(matcher.by_ref().count(), 3);
+    /// assert_eq!(
+    ///     matcher.last_error().unwrap().to_string(),
+    ///     "Exceeded the limit of 3 steps"
+    /// );
+    /// assert!(!matcher.finished_cleanly());
+    /// ```
+    #[must_use]
+    pub fn last_error(&self) -> Option<&RegexError> {
+        self.error.as_ref()
+    }
+
+    /// Returns the work done by the search so far
+    ///
+    /// The counters accumulate over all the matches found, and
+    /// the attempts that failed, since the matcher was created.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::Regex;
+    ///
+    /// let regex = Regex::compile("(a|b)*c").unwrap();
+    /// let mut matcher = regex.find_matches("ababc");
+    /// assert_eq!(matcher.stats().steps, 0);
+    /// matcher.next().unwrap();
+    /// let stats = matcher.stats();
+    /// assert!(stats.steps > 0);
+    /// assert!(stats.checkpoints >= stats.backtracks);
+    /// ```
+    #[cfg(feature = "diagnostics")]
+    #[must_use]
+    pub fn stats(&self) -> MatchStats {
+        self.ctx.stats.get()
+    }
+
+    /// Returns true if the iteration is over, and all
+    /// the matches were found without being aborted
+    #[must_use]
+    pub fn finished_cleanly(&self) -> bool {
+        self.done && self.error.is_none()
+    }
+
+    /// Returns the span of each capture group, as of the last
+    /// match it participated in
+    ///
+    /// Groups that haven't participated in any match yet are [`None`].
+    /// The spans are only recorded if [`RegexConf::track_group_spans`] is set.
+    ///
+    /// # Example
+    /// ```
+    /// use regexpr::{Regex, RegexConf};
+    ///
+    /// let regex = Regex::compile("(a)|(b)").unwrap();
+    /// let conf = RegexConf {
+    ///     track_group_spans: true,
+    ///     ..Default::default()
+    /// };
+    /// let mut matcher = regex.find_matches_with_conf("ba_b", conf);
+    /// matcher.by_ref().for_each(drop);
+    /// assert_eq!(matcher.group_spans(), &[Some((1, 2)), Some((3, 4))]);
+    /// ```
+    #[must_use]
+    pub fn group_spans(&self) -> &[Option<(usize, usize)>] {
+        &self.group_spans
+    }
+
+    /// Returns true if the search was aborted because it exceeded
+    /// [`RegexConf::max_steps`], [`RegexConf::max_depth`] or its
+    /// [deadline](Self::with_deadline)
+    #[must_use]
+    pub fn aborted(&self) -> bool {
+        self.ctx.budget.exhausted.get()
+    }
+
+    /// Returns true if the search was aborted because it exceeded
+    /// [`RegexConf::max_steps`]
+    #[must_use]
+    #[deprecated(note = "Use RegexMatcher::aborted, which also covers the other limits")]
+    pub fn steps_exceeded(&self) -> bool {
+        self.aborted()
+    }
+
+    /// Skips to the next occurrence of the literal prefix, since
+    /// no match can start before it. If it doesn't appear, skips to
+    /// the end of the input, except for the chars that could be the
+    /// start of the prefix, so the search can still tell if a match
+    /// could continue past the end of the input
+    fn skip_to_prefix(&mut self) {
+        if !self.uses_prefix() {
+            return;
+        }
+        let rest = self.ctx.remaining();
+        let skip = self.find_prefix(rest).unwrap_or_else(|| {
+            let mut skip = rest.len().saturating_sub(self.prefix.len() - 1);
+            while !rest.is_char_boundary(skip) {
+                skip += 1;
+            }
+            skip
+        });
+        self.skip_to(self.offset() + skip);
+    }
+
+    /// Returns true if the search skips to the occurrences of the literal prefix
+    fn uses_prefix(&self) -> bool {
+        !self.prefix.is_empty() && self.ctx.conf.case_sensitive && !self.ctx.conf.anchored
+    }
+
+    /// Returns false if a match can't start at the current position,
+    /// because none of the first set matches the next character.
+    /// At the end of the input, the match is always attempted, since
+    /// more input could follow
+    fn can_start_here(&mut self) -> bool {
+        let Some(first_set) = self.first_set else {
+            return true;
+        };
+        // The search is already at an occurrence of the prefix
+        if self.ctx.remaining().is_empty() || self.uses_prefix() {
+            return true;
+        }
+        // The check isn't part of the search, so it doesn't show up in its
+        // stats. It still spends a step of its budget, since it's work done
+        let budget = &self.ctx.budget;
+        if budget.is_limited() && !budget.step() {
+            return false;
+        }
+        #[cfg(feature = "diagnostics")]
+        let stats = self.ctx.stats.get();
+        let ret = first_set
+            .iter()
+            .any(|case| self.ctx.probe(|ctx| case.match_single(ctx)));
+        #[cfg(feature = "diagnostics")]
+        self.ctx.stats.set(stats);
+        ret
+    }
+
+    /// Byte offset of the first occurrence of the literal prefix in `haystack`
+    #[cfg(feature = "memchr")]
+    fn find_prefix(&self, haystack: &str) -> Option<usize> {
+        self.prefix_finder.find(haystack.as_bytes())
+    }
+
+    #[cfg(not(feature = "memchr"))]
+    fn find_prefix(&self, haystack: &str) -> Option<usize> {
+        haystack.find(self.prefix)
+    }
+
+    fn find_next(&mut self) -> Option<RegexMatch<'a>> {
         loop {
-            if self.ctx.nc.as_str().is_empty() && !self.first {
+            // An empty match can still be found at the end of the input
+            let at_end = self.ctx.remaining().is_empty();
+            if at_end && self.end_tried {
                 return None;
             }
-            let LookAheadKind::List(l) = self.cases.kind else {
-                unreachable!()
-            };
-            if !self.first && l.first().is_some_and(|m| matches!(m, MatchCase::Start)) {
+            let l = self.cases;
+            let starts_anchored = l.first().is_some_and(|m| match m {
+                MatchCase::Start => !self.ctx.conf.multiline,
+                MatchCase::InputStart => true,
+                _ => false,
+            });
+            if !self.first && (self.ctx.conf.anchored || starts_anchored) {
+                return None;
+            }
+            // Once past the end of the previous match, `\G` can't match anymore
+            if matches!(l.first(), Some(MatchCase::PrevMatchEnd)) && !self.ctx.at_prev_match_end() {
                 return None;
             }
             self.first = false;
+            self.end_tried = at_end;
+            self.skip_to_prefix();
 
-            let mut chars = self.ctx.shallow_clone();
-            if !self.cases.match_all(&mut chars) {
-                match self.ctx.nc.next() {
-                    Some(_) => continue,
-                    None => return None,
-                };
+            if self.can_start_here()
+                && let Some(m) = self.attempt()
+            {
+                return Some(m);
             }
-
-            let start = self.ctx.nc.offset();
-            let end = chars.nc.offset();
-
-            let len = end - start;
-            let slice = &self.ctx.nc.as_str()[..len];
-
-            let RegexCtx { captures, nc, .. } = chars;
-            let mut caps = None;
-            if !self.ctx.conf.ignore_captures_in_result && !captures.is_empty() {
-                let mut v = Vec::with_capacity(captures.len());
-                v.extend(
-                    captures
-                        .iter()
-                        .map(|(c, l)| l.map_or("", |l| &c.as_str()[..l])),
-                );
-                caps = Some(v);
+            if self.aborted() {
+                return None;
             }
-            self.ctx.nc = nc;
+            self.ctx.nc.next()?;
+        }
+    }
+
+    /// Matches the whole pattern at the current position
+    fn match_all(&mut self) -> bool {
+        self.ctx.run(self.cases, false)
+    }
+
+    /// Looks for the longest match that starts where the one just found
+    /// does. The search ends in the state of the longest match, which at
+    /// worst is the one already found
+    ///
+    /// A first search goes through all the ways the pattern can match,
+    /// and records the furthest end it reaches. Then, a second one finds
+    /// the way the pattern prefers to match up to that end
+    fn extend_to_longest(&mut self, start: &CharIndices<'a>) {
+        // The end is already fixed
+        if self.ctx.end_at.is_some() {
+            return;
+        }
+        let end = self.ctx.char_offset();
+        // Kept in case the search is aborted before it finds a longer match
+        let found = (self.ctx.nc.clone(), self.ctx.groups.clone());
+        self.ctx.nc = start.clone();
+        self.ctx.reset();
+        self.ctx.furthest_end = Some(end);
+        self.match_all();
+        let furthest = self.ctx.furthest_end.take().unwrap_or(end);
+
+        self.ctx.nc = start.clone();
+        self.ctx.reset();
+        self.ctx.end_at = Some(furthest);
+        let matched = self.match_all();
+        self.ctx.end_at = None;
+        if !matched {
+            debug_assert!(self.aborted());
+            (self.ctx.nc, self.ctx.groups) = found;
+        }
+    }
 
-            if self.cases.is_empty() {
-                self.ctx.nc.next();
+    /// Attempts to match at the current position. If it
+    /// succeeds, moves to where the next attempt should start
+    fn attempt(&mut self) -> Option<RegexMatch<'a>> {
+        let start_nc = self.ctx.nc.clone();
+        self.ctx.reset();
+        let at_boundary = !self.ctx.conf.whole_word || self.ctx.at_word_boundary();
+        let matched = at_boundary && self.match_all();
+        if matched && self.ctx.conf.longest {
+            self.extend_to_longest(&start_nc);
+        }
+
+        let start = start_nc.offset();
+        if let Some(eoi) = self.ctx.eoi
+            && start == self.ctx.src.len()
+        {
+            // More input could change the outcome of an attempt at the end
+            eoi.set(true);
+        }
+        if self.end_reached_from.is_none() && self.ctx.eoi.is_some_and(Cell::get) {
+            self.end_reached_from = Some(start);
+        }
+        if !matched {
+            self.ctx.nc = start_nc;
+            return None;
+        }
+
+        let end = self.ctx.char_offset();
+        self.ctx.prev_end = Some(end);
+
+        let len = end - start;
+        let slice = &start_nc.as_str()[..len];
+
+        let Groups {
+            spans, decisions, ..
+        } = &mut self.ctx.groups;
+        let mut caps = None;
+        let n_groups = spans.len().max(self.names.len());
+        if !self.ctx.conf.ignore_captures_in_result && n_groups > 0 {
+            let mut v = Vec::with_capacity(n_groups);
+            v.extend(
+                spans
+                    .iter()
+                    .map(|&(start, end)| end.map(|end| &self.ctx.src[start..end])),
+            );
+            v.resize(n_groups, None);
+            caps = Some(v);
+        }
+        if self.ctx.conf.track_group_spans {
+            self.group_spans.resize(n_groups, None);
+            for (span, &(start, end)) in self.group_spans.iter_mut().zip(spans.iter()) {
+                if let Some(end) = end {
+                    *span = Some((self.base + start, self.base + end));
+                }
             }
+        }
+        let decisions = core::mem::take(decisions);
+        if self.ctx.conf.overlapping {
+            self.ctx.nc = start_nc;
+        }
 
-            return Some(RegexMatch {
-                start,
-                slice,
-                captures: caps,
-            });
+        // Any empty match, not only the one of an empty pattern, must
+        // move past its position, or the next attempt would find it again
+        if len == 0 || self.ctx.conf.overlapping {
+            self.ctx.nc.next();
         }
+
+        Some(RegexMatch {
+            start: self.base + start,
+            slice,
+            captures: caps,
+            names: self.names,
+            decisions,
+        })
     }
 }
 
-impl FusedIterator for RegexMatcher<'_> {}
+impl<'a> Iterator for RegexMatcher<'a> {
+    type Item = RegexMatch<'a>;
 
-#[derive(Clone, Debug)]
-pub enum LookAheadKind<'a> {
-    Repeat { m: &'a MatchCase, num: usize },
-    List(&'a [MatchCase]),
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.aborted() {
+            return None;
+        }
+        let m = self.find_next();
+        if self.aborted() {
+            // A match found before the abort is still valid
+            self.error = Some(self.ctx.budget.error());
+            self.done = true;
+        }
+        self.done |= m.is_none();
+        m
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done || self.aborted() {
+            return (0, Some(0));
+        }
+        // There can be a match at each position, including the end of
+        // the input, if they're empty
+        (0, Some(self.remaining().len() + 1))
+    }
+}
+
+/// Work done by a search, as returned by [`RegexMatcher::stats`]
+#[cfg(feature = "diagnostics")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatchStats {
+    /// Number of times a [`MatchCase`] was tried
+    pub steps: usize,
+    /// Number of points the search saved, to go back to them if a branch fails
+    pub checkpoints: usize,
+    /// Number of times the search went back to a saved point
+    pub backtracks: usize,
+}
+
+/// A step of a search, as reported to the callback of [`Regex::find_matches_traced`]
+#[cfg(all(feature = "diagnostics", feature = "std"))]
+#[derive(Debug, Clone, Copy)]
+pub enum TraceEvent<'a> {
+    /// The search starts trying `case` at the byte `offset` of the input,
+    /// or goes back into it after it matched, to try another way to match it
+    Enter { case: &'a MatchCase, offset: usize },
+    /// The search is done trying `case`, and is at the byte `offset` of the input
+    Exit {
+        case: &'a MatchCase,
+        offset: usize,
+        matched: bool,
+    },
+}
+
+#[cfg(all(feature = "diagnostics", feature = "std"))]
+impl<'a> TraceEvent<'a> {
+    /// The case being tried
+    #[must_use]
+    pub fn case(&self) -> &'a MatchCase {
+        match self {
+            TraceEvent::Enter { case, .. } | TraceEvent::Exit { case, .. } => case,
+        }
+    }
+    /// Byte offset of the input the search is at
+    #[must_use]
+    pub fn offset(&self) -> usize {
+        match self {
+            TraceEvent::Enter { offset, .. } | TraceEvent::Exit { offset, .. } => *offset,
+        }
+    }
+    /// Name of the variant of the [`case`](Self::case)
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        self.case().name()
+    }
+}
+
+/// Callback of a traced search. It's shared by the clones of the
+/// [`RegexMatcher`], so they must stay [Send]
+#[cfg(all(feature = "diagnostics", feature = "std"))]
+#[derive(Clone)]
+struct Tracer<'a>(Arc<Mutex<dyn FnMut(TraceEvent<'a>) + Send + 'a>>);
+
+#[cfg(all(feature = "diagnostics", feature = "std"))]
+impl core::fmt::Debug for Tracer<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("Tracer")
+    }
 }
 
+/// Limits on the work a search can do
 #[derive(Debug, Clone)]
-pub(crate) struct LookAhead<'l, 'a> {
-    kind: LookAheadKind<'a>,
-    then: Option<&'l LookAhead<'l, 'a>>,
+struct Budget {
+    steps: Cell<usize>,
+    max_steps: usize,
+    /// Depth the search reached when it exceeded the maximum
+    depth: Cell<usize>,
+    max_depth: usize,
+    #[cfg(feature = "std")]
+    deadline: Option<Instant>,
+    exhausted: Cell<bool>,
 }
 
-impl<'l, 'a> LookAhead<'l, 'a> {
-    pub fn new(l: LookAheadKind<'a>, then: Option<&'l LookAhead<'l, 'a>>) -> Self {
-        LookAhead { kind: l, then }
-    }
-
-    pub fn match_all(&self, ctx: &mut RegexCtx<'_, 'a>) -> bool {
-        let mut r = true;
-        match self.kind {
-            LookAheadKind::Repeat { m, mut num } if num > 0 => loop {
-                num -= 1;
-                r = m.matches(
-                    ctx,
-                    &LookAhead {
-                        kind: LookAheadKind::Repeat { m, num },
-                        then: self.then,
-                    },
-                );
-                if !r || num == 0 {
-                    break;
-                }
-            },
-            LookAheadKind::List(match_cases) if !match_cases.is_empty() => {
-                for i in 0..match_cases.len() {
-                    let rem = match_cases.get(i + 1..).unwrap_or(&[]);
-                    r = match_cases[i].matches(
-                        ctx,
-                        &LookAhead {
-                            kind: LookAheadKind::List(rem),
-                            then: self.then,
-                        },
-                    );
-                    if !r {
-                        break;
-                    }
-                }
-                ctx.end_capture(&ctx.char_iter());
-            }
-            _ => {}
+impl Budget {
+    /// How many steps are taken between checks of the deadline
+    #[cfg(feature = "std")]
+    const CLOCK_INTERVAL: usize = 1024;
+
+    fn new(max_steps: usize, max_depth: usize) -> Self {
+        Budget {
+            steps: Cell::new(0),
+            max_steps,
+            depth: Cell::new(0),
+            max_depth,
+            #[cfg(feature = "std")]
+            deadline: None,
+            exhausted: Cell::new(false),
         }
-        r && self.then.as_ref().is_none_or(|t| t.match_all(ctx))
     }
 
-    const fn is_empty(&self) -> bool {
-        self.then.is_none()
-            && match self.kind {
-                LookAheadKind::Repeat { num, .. } => num == 0,
-                LookAheadKind::List(match_cases) => match_cases.is_empty(),
-            }
+    fn is_limited(&self) -> bool {
+        #[cfg(feature = "std")]
+        if self.deadline.is_some() {
+            return true;
+        }
+        self.max_steps > 0
+    }
+
+    /// Error explaining why the budget was exhausted
+    fn error(&self) -> RegexError {
+        if self.max_depth > 0 && self.depth.get() > self.max_depth {
+            RegexError::new(
+                RegexErrorKind::Aborted,
+                format!("Exceeded the maximum depth of {}", self.max_depth),
+            )
+        } else if self.max_steps > 0 && self.steps.get() > self.max_steps {
+            RegexError::new(
+                RegexErrorKind::Aborted,
+                format!("Exceeded the limit of {} steps", self.max_steps),
+            )
+        } else {
+            RegexError::new(RegexErrorKind::Aborted, "Exceeded the deadline")
+        }
+    }
+
+    fn step(&self) -> bool {
+        if self.exhausted.get() {
+            return false;
+        }
+        let n = self.steps.get().saturating_add(1);
+        self.steps.set(n);
+
+        #[allow(unused_mut)]
+        let mut exceeded = self.max_steps > 0 && n > self.max_steps;
+        #[cfg(feature = "std")]
+        if !exceeded && n.is_multiple_of(Self::CLOCK_INTERVAL) {
+            exceeded = self.deadline.is_some_and(|d| Instant::now() >= d);
+        }
+        if exceeded {
+            self.exhausted.set(true);
+        }
+        !exceeded
+    }
+
+    /// Records that the search reached `depth`. Returns false if that
+    /// exceeds the maximum depth, which aborts the search.
+    /// Once exceeded, the depth is kept, so the error can tell why
+    fn reach(&self, depth: usize) -> bool {
+        if self.max_depth == 0 || depth <= self.max_depth {
+            return true;
+        }
+        self.depth.set(depth);
+        self.exhausted.set(true);
+        false
     }
 }
 
+impl FusedIterator for RegexMatcher<'_> {}
+
+/// What's left to match after a case: the continuation of the search
+///
+/// The lookaheads are kept in a stack, [`RegexCtx::frames`], and each one
+/// links to the one that follows it by its index there. A case that can
+/// match in several ways takes the first of them, and saves the others, to
+/// go back to them if the rest of the pattern fails to match.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum LookAheadKind<'a> {
+    /// The rest of a list of cases
+    List(&'a [MatchCase]),
+    /// End of the `n`th iteration of the loop `case`, that started
+    /// at the byte `start`. See [`MatchCase::repeat`]
+    Loop {
+        case: &'a MatchCase,
+        n: usize,
+        start: usize,
+    },
+    /// Closes the innermost open capture group
+    EndCapture,
+    /// Asserts that the input is at a word boundary
+    WordBoundary,
+    /// End of an atomic group. Discards the alternatives saved
+    /// inside it, of which there were `saved` before it started
+    EndAtomic { saved: usize },
+    /// Reports that `case`, whose [`Saved::Traced`] is at the index
+    /// `saved`, has matched
+    #[cfg(all(feature = "diagnostics", feature = "std"))]
+    Traced { case: &'a MatchCase, saved: usize },
+    /// End of the pattern. Checks that the match ends where it must
+    End,
+    /// End of a probe, that matches regardless of what comes after it
+    Accept,
+}
+
+/// A lookahead in the stack of a search. See [`LookAheadKind`]
+#[derive(Clone, Debug)]
+pub(crate) struct LookAhead<'a> {
+    kind: LookAheadKind<'a>,
+    /// Index of the lookahead that comes after this one
+    then: usize,
+    /// Number of alternatives saved when it was pushed
+    alternatives: usize,
+    /// Offset where this lookahead is known not to match. The same lookahead
+    /// is often tried at the same offset by the different ways a case can
+    /// match, like `(a|a)`, and would fail in the same way each time
+    failed_at: Option<usize>,
+}
+
+/// What the search does next
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Task<'a> {
+    /// Match a case, followed by the lookahead at the given index
+    Match(&'a MatchCase, usize),
+    /// Match the lookahead at the given index
+    Then(usize),
+    /// Go back to the last alternative saved
+    Fail,
+}
+
+/// Another way a case can match, to try if the one taken fails
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Alternative<'a> {
+    /// Go on with the lookahead at the given index, like
+    /// when skipping an optional case or ending a loop
+    Then(usize),
+    /// Try the `i`th branch of an alternation
+    Branch {
+        branches: &'a [MatchCase],
+        i: usize,
+        then: usize,
+    },
+    /// One more iteration of the lazy loop `case`, whose
+    /// case matched `n` times. See [`MatchCase::repeat`]
+    Iterate {
+        case: &'a MatchCase,
+        n: usize,
+        then: usize,
+    },
+}
+
+/// An entry of the backtracking stack of a search
 #[derive(Clone, Debug)]
-pub(crate) struct RegexCtx<'ctx, 'a> {
-    captures: Cow<'ctx, [(CharIndices<'a>, Option<usize>)]>,
-    open_captures: Cow<'ctx, [usize]>,
+enum Saved<'a> {
+    /// An alternative, with the point of the search it starts from,
+    /// and the number of lookaheads in the stack at that point
+    Alternative {
+        alt: Alternative<'a>,
+        at: Checkpoint<'a>,
+        frames: usize,
+    },
+    /// Gives back the repetitions of a greedy loop, whose positions
+    /// past `from` are in [`RegexCtx::positions`], one by one, going
+    /// on with the lookahead at `then` after each one
+    GiveBack {
+        then: usize,
+        from: usize,
+        frames: usize,
+        decisions: usize,
+    },
+    /// The lookahead at `frame` was tried at `offset`. Going back
+    /// past this means it didn't match there
+    Tried { frame: usize, offset: usize },
+    /// The search entered `case`. Going back past this means it didn't match
+    #[cfg(all(feature = "diagnostics", feature = "std"))]
+    Traced(&'a MatchCase),
+    /// The search left `case` after it matched. Going back
+    /// past this means it's trying another way to match it
+    #[cfg(all(feature = "diagnostics", feature = "std"))]
+    Reentered(&'a MatchCase),
+}
+
+/// State of a match attempt
+#[derive(Clone, Debug)]
+pub(crate) struct RegexCtx<'a> {
+    src: &'a str,
+    groups: Groups,
     conf: RegexConf,
     nc: CharIndices<'a>,
+    eoi: Option<&'a Cell<bool>>,
+    budget: Budget,
+    /// Characters `.` and the negated classes can match. [`None`] means any
+    alphabet: Option<&'a [char]>,
+    /// Offset where the match must end. [`None`] means anywhere
+    end_at: Option<usize>,
+    /// While looking for the longest match, the furthest
+    /// end found. See [`RegexMatcher::extend_to_longest`]
+    furthest_end: Option<usize>,
+    /// Whether a lookahead that failed at an offset can be assumed to fail
+    /// again there. Not if the pattern depends on what the groups captured
+    memoize: bool,
+    /// Whether the search can change the [`Groups`], so going back must
+    /// undo the changes. Not if the pattern has no groups and the
+    /// decisions aren't recorded
+    bookkeeping: bool,
+    /// Stack of what's left to match. See [`LookAhead`]
+    frames: Vec<LookAhead<'a>>,
+    /// Alternatives to go back to, the latest one last
+    saved: Vec<Saved<'a>>,
+    /// Number of [`Saved::Alternative`] and [`Saved::GiveBack`] in `saved`
+    alternatives: usize,
+    /// Positions greedy loops can give back, with the length the
+    /// log of [`Groups`] had at each one. See [`Saved::GiveBack`]
+    positions: Vec<(CharIndices<'a>, usize)>,
+    /// Offset where the previous match ended, for `\G`
+    prev_end: Option<usize>,
+    #[cfg(feature = "diagnostics")]
+    stats: Cell<MatchStats>,
+    #[cfg(all(feature = "diagnostics", feature = "std"))]
+    tracer: Option<Tracer<'a>>,
+}
+
+/// Capture groups and decisions of a match attempt
+///
+/// All the branches of the search work on the same state. Every change
+/// is logged, so it can be undone when a branch is discarded, instead
+/// of cloning the state for each branch.
+///
+/// Patterns without groups never change it, so matching them
+/// doesn't do any capture bookkeeping, nor allocates for it. Going
+/// back doesn't even look at its log, unless the decisions are recorded.
+#[derive(Clone, Debug, Default)]
+struct Groups {
+    /// Byte offsets where each capture group starts and ends.
+    /// The end is [`None`] while the group is open
+    spans: Vec<(usize, Option<usize>)>,
+    /// Ids of the open groups, the innermost one last
+    open: Vec<usize>,
+    decisions: Vec<(usize, usize)>,
+    /// Changes made to `spans` and `open`, in order
+    log: Vec<Undo>,
+}
+
+/// Reverts a change of [`Groups`]
+#[derive(Clone, Copy, Debug)]
+enum Undo {
+    /// Restores the span of a group
+    Span(usize, (usize, Option<usize>)),
+    /// Removes the groups past the given length
+    Truncate(usize),
+    /// Pops the last group opened
+    Opened,
+    /// Reopens a group that was closed
+    Closed(usize),
+}
+
+/// A point of the search a [`RegexCtx`] can go back to
+#[derive(Clone, Debug)]
+pub(crate) struct Checkpoint<'a> {
+    nc: CharIndices<'a>,
+    log: usize,
+    decisions: usize,
 }
 
 macro_rules! next {
     ($conf:expr, $chrs:expr) => {
-        $chrs.next().map(|(_, c)| {
-            if $conf.case_sensitive {
-                c
-            } else {
-                c.to_lowercase().next().unwrap_or(c)
-            }
-        })
+        $chrs.next().map(|(_, c)| fold_case(c, &$conf))
     };
 }
 
-impl<'a> RegexCtx<'_, 'a> {
+// Cursor helpers. The characters read ahead are case folded,
+// according to the configuration, and reading past the end of
+// the input is recorded, for the streaming matchers
+impl<'a> RegexCtx<'a> {
+    /// Consumes the next character
     #[inline]
     pub fn next_char(&mut self) -> Option<char> {
-        next!(self.conf, &mut self.nc)
+        let c = next!(self.conf, &mut self.nc);
+        if c.is_none() {
+            self.hit_end();
+        }
+        c
+    }
+    /// Returns the next character, without consuming it
+    #[inline]
+    pub fn peek_char(&mut self) -> Option<char> {
+        let c = next!(self.conf, self.nc.clone());
+        if c.is_none() {
+            self.hit_end();
+        }
+        c
+    }
+    /// Returns the character before the current position, as it is in the input
+    #[inline]
+    pub fn prev_char(&self) -> Option<char> {
+        self.src[..self.nc.offset()].chars().next_back()
     }
+    /// Byte offset of the current position in the input
     #[inline]
-    pub fn char_offset(&mut self) -> usize {
+    pub fn char_offset(&self) -> usize {
         self.nc.offset()
     }
+    /// Returns the input after the current position
     #[inline]
-    pub fn char_iter(&self) -> CharIndices<'a> {
-        self.nc.clone()
+    pub fn remaining(&self) -> &'a str {
+        self.nc.as_str()
     }
-
+    /// Returns true if the current position is where the previous match ended
     #[inline]
-    pub fn peek_char(&mut self) -> Option<char> {
-        next!(self.conf, self.nc.clone())
+    pub fn at_prev_match_end(&self) -> bool {
+        self.prev_end == Some(self.char_offset())
+    }
+    /// With [`grapheme_dot`](RegexConf::grapheme_dot), consumes the rest of
+    /// the grapheme cluster that starts at `start`. Returns false if it spans
+    /// a newline that `.` can't match
+    #[cfg(feature = "unicode-segmentation")]
+    pub fn skip_grapheme(&mut self, start: usize) -> bool {
+        if !self.conf.grapheme_dot || !self.conf.unicode {
+            return true;
+        }
+        let Some(cluster) = self.src[start..].graphemes(true).next() else {
+            return true;
+        };
+        let end = start + cluster.len();
+        if end == self.src.len() {
+            // More input could extend the cluster
+            self.hit_end();
+        }
+        while self.nc.offset() < end {
+            match self.nc.next() {
+                Some((_, c)) if c == self.conf.line_end() && !self.conf.dot_all => return false,
+                Some(_) => {}
+                None => break,
+            }
+        }
+        true
+    }
+    #[cfg(not(feature = "unicode-segmentation"))]
+    #[allow(clippy::unused_self)]
+    #[inline]
+    pub fn skip_grapheme(&mut self, _start: usize) -> bool {
+        true
+    }
+    /// Returns true if the previous and next characters are
+    /// not both word or both non-word characters
+    pub fn at_word_boundary(&mut self) -> bool {
+        let unicode = self.conf.unicode;
+        let prev = self.prev_char().is_some_and(|c| is_word_char(c, unicode));
+        let next = self.peek_char().is_some_and(|c| is_word_char(c, unicode));
+        prev != next
+    }
+    /// Counts a step of the search.
+    ///
+    /// Returns false if the search ran out of budget
+    #[inline]
+    pub fn step(&self) -> bool {
+        #[cfg(feature = "diagnostics")]
+        self.count(|stats| stats.steps += 1);
+        !self.budget.is_limited() || self.budget.step()
+    }
+    /// Reports `event` to the callback of a traced search
+    #[cfg(all(feature = "diagnostics", feature = "std"))]
+    #[inline]
+    pub fn trace(&self, event: impl FnOnce(usize) -> TraceEvent<'a>) {
+        if let Some(Tracer(tracer)) = &self.tracer {
+            let mut tracer = tracer
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            tracer(event(self.char_offset()));
+        }
+    }
+    #[cfg(feature = "diagnostics")]
+    #[inline]
+    fn count(&self, f: impl FnOnce(&mut MatchStats)) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
+    }
+    #[inline]
+    fn hit_end(&self) {
+        if let Some(eoi) = self.eoi {
+            eoi.set(true);
+        }
     }
     #[inline]
     pub fn conf(&self) -> RegexConf {
         self.conf
     }
-    pub fn get_capture(&self, id: usize) -> &'a str {
+    /// Returns true if `c` is in the alphabet `.` and the negated classes can match
+    pub fn in_alphabet(&self, c: char) -> bool {
+        self.alphabet
+            .is_none_or(|alphabet| alphabet.iter().any(|&a| fold_case(a, &self.conf) == c))
+    }
+    /// Returns the text captured by the group `id`, or [None]
+    /// if it hasn't participated in the match
+    pub fn get_capture(&self, id: usize) -> Option<&'a str> {
         let id = id.wrapping_sub(1);
-        let Some((nc, len)) = self.captures.get(id) else {
-            return "";
-        };
-        &nc.as_str()[..len.unwrap_or_else(|| (self.nc.offset() - nc.offset()).saturating_sub(1))]
+        let &(start, end) = self.groups.spans.get(id)?;
+        Some(&self.src[start..end?])
     }
-    pub fn start_capture(&mut self, id: usize, s: CharIndices<'a>) {
-        let caps = self.captures.to_mut();
-        if caps.len() <= (id - 1) {
-            caps.resize_with(id, || (s.clone(), None));
+    pub fn start_capture(&mut self, id: usize, start: usize) {
+        let groups = &mut self.groups;
+        if groups.spans.len() < id {
+            groups.log.push(Undo::Truncate(groups.spans.len()));
+            groups.spans.resize(id, (start, None));
         }
-        caps[id - 1] = (s, None);
-        self.open_captures.to_mut().push(id);
+        groups.log.push(Undo::Span(id - 1, groups.spans[id - 1]));
+        groups.spans[id - 1] = (start, None);
+        groups.log.push(Undo::Opened);
+        groups.open.push(id);
     }
-    pub fn end_capture(&mut self, s: &CharIndices<'a>) {
-        if self.open_captures.is_empty() {
-            return;
+    pub fn record_decision(&mut self, branch: usize) {
+        if self.conf.trace_decisions {
+            let offset = self.nc.offset();
+            self.groups.decisions.push((offset, branch));
         }
-        let Some(id) = self.open_captures.to_mut().pop() else {
-            unreachable!()
+    }
+    pub fn end_capture(&mut self, end: usize) {
+        let groups = &mut self.groups;
+        let Some(id) = groups.open.pop() else {
+            return;
         };
-        let c = &mut self.captures.to_mut()[id - 1];
-        c.1 = Some(s.offset() - c.0.offset());
+        groups.log.push(Undo::Closed(id));
+        groups.log.push(Undo::Span(id - 1, groups.spans[id - 1]));
+        groups.spans[id - 1].1 = Some(end);
+    }
+    /// Clears the state of the previous match attempt
+    fn reset(&mut self) {
+        let groups = &mut self.groups;
+        groups.spans.clear();
+        groups.open.clear();
+        groups.decisions.clear();
+        groups.log.clear();
     }
 
-    pub fn borrow_shallow<R>(&mut self, f: impl FnOnce(&mut RegexCtx<'_, 'a>) -> (R, bool)) -> R {
-        let mut ctx = RegexCtx {
-            captures: Cow::Borrowed(&self.captures),
-            open_captures: Cow::Borrowed(&self.open_captures),
+    /// Returns the current point of the search, to [`rewind`](Self::rewind) to it later
+    pub fn checkpoint(&self) -> Checkpoint<'a> {
+        #[cfg(feature = "diagnostics")]
+        self.count(|stats| stats.checkpoints += 1);
+        let (log, decisions) = self.marks();
+        Checkpoint {
             nc: self.nc.clone(),
-            conf: self.conf,
-        };
-        let (r, should_overwrite) = f(&mut ctx);
-        let RegexCtx {
-            captures,
-            open_captures,
-            nc,
-            ..
-        } = ctx;
-        if should_overwrite {
-            let mut cap = None;
-            let mut ocap = None;
-            if let Cow::Owned(o) = captures {
-                cap = Some(o);
+            log,
+            decisions,
+        }
+    }
+    /// Undoes all the changes made since the given checkpoint
+    pub fn rewind(&mut self, checkpoint: Checkpoint<'a>) {
+        #[cfg(feature = "diagnostics")]
+        self.count(|stats| stats.backtracks += 1);
+        if self.bookkeeping {
+            self.undo(checkpoint.log);
+            self.groups.decisions.truncate(checkpoint.decisions);
+        }
+        self.nc = checkpoint.nc;
+    }
+    /// Lengths of the log of changes to the groups, and of the decisions.
+    /// Without bookkeeping they never change, so they aren't looked at
+    #[inline]
+    fn marks(&self) -> (usize, usize) {
+        if self.bookkeeping {
+            (self.groups.log.len(), self.groups.decisions.len())
+        } else {
+            (0, 0)
+        }
+    }
+    /// Undoes the changes to the groups past the length `log` of their log
+    fn undo(&mut self, log: usize) {
+        let groups = &mut self.groups;
+        while groups.log.len() > log {
+            let Some(undo) = groups.log.pop() else {
+                unreachable!()
+            };
+            match undo {
+                Undo::Span(id, span) => groups.spans[id] = span,
+                Undo::Truncate(len) => groups.spans.truncate(len),
+                Undo::Opened => {
+                    groups.open.pop();
+                }
+                Undo::Closed(id) => groups.open.push(id),
             }
-            if let Cow::Owned(o) = open_captures {
-                ocap = Some(o);
+        }
+    }
+
+    /// Runs `f`, which returns a result and whether to keep
+    /// its changes. If not, the changes are undone
+    pub fn borrow_shallow<R>(&mut self, f: impl FnOnce(&mut Self) -> (R, bool)) -> R {
+        let checkpoint = self.checkpoint();
+        let (r, keep) = f(self);
+        if !keep {
+            self.rewind(checkpoint);
+        }
+        r
+    }
+    /// Runs `f`, and undoes all its changes
+    #[inline]
+    pub fn probe<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.borrow_shallow(|ctx| (f(ctx), false))
+    }
+}
+
+// Backtracking. Instead of recursing, the search loops over the tasks
+// to do, keeping what's left to match and the alternatives to go back
+// to in stacks, so long inputs don't overflow the stack
+impl<'a> RegexCtx<'a> {
+    /// Matches `cases` at the current position. If `probe` is true, they
+    /// match regardless of what comes after them. If not, they're the whole
+    /// pattern, and the match must end where the configuration says
+    ///
+    /// If it returns true, the search is left at the end of the match.
+    /// If not, the search can be in any state
+    pub fn run(&mut self, cases: &'a [MatchCase], probe: bool) -> bool {
+        self.frames.clear();
+        self.saved.clear();
+        self.alternatives = 0;
+        self.positions.clear();
+        let mut end = self.push_frame(LookAheadKind::End, 0);
+        if probe {
+            end = self.push_frame(LookAheadKind::Accept, 0);
+        } else if self.conf.whole_word {
+            end = self.push_frame(LookAheadKind::WordBoundary, end);
+        }
+        let mut task = Task::Then(self.push_frame(LookAheadKind::List(cases), end));
+        loop {
+            task = match task {
+                Task::Match(case, then) => {
+                    if !self.step() || !self.budget.reach(self.frames.len()) {
+                        return false;
+                    }
+                    case.start(self, then)
+                }
+                Task::Then(frame) => match self.match_frame(frame) {
+                    Some(task) => task,
+                    None => return true,
+                },
+                Task::Fail if self.budget.exhausted.get() => return false,
+                Task::Fail => match self.backtrack() {
+                    Some(task) => task,
+                    None => return false,
+                },
+            };
+        }
+    }
+    /// Pushes a lookahead, followed by the one at `then`, and returns its index
+    #[inline]
+    pub fn push_frame(&mut self, kind: LookAheadKind<'a>, then: usize) -> usize {
+        self.frames.push(LookAhead {
+            kind,
+            then,
+            alternatives: self.alternatives,
+            failed_at: None,
+        });
+        self.frames.len() - 1
+    }
+    /// Saves `alt`, to go back to it from the current point if the search fails
+    #[inline]
+    pub fn save(&mut self, alt: Alternative<'a>) {
+        let at = self.checkpoint();
+        self.save_at(alt, at);
+    }
+    /// Saves `alt`, to go back to it from the checkpoint `at`
+    pub fn save_at(&mut self, alt: Alternative<'a>, at: Checkpoint<'a>) {
+        let frames = self.frames.len();
+        self.saved.push(Saved::Alternative { alt, at, frames });
+        self.alternatives += 1;
+    }
+    /// Number of positions greedy loops can give back
+    pub fn positions_len(&self) -> usize {
+        self.positions.len()
+    }
+    /// Saves the current position, for a greedy loop to give it back
+    pub fn push_position(&mut self) {
+        let (log, _) = self.marks();
+        self.positions.push((self.nc.clone(), log));
+    }
+    /// Goes back to the last position saved, and forgets it
+    pub fn pop_position(&mut self) {
+        if let Some((nc, log)) = self.positions.pop() {
+            if self.bookkeeping {
+                self.undo(log);
             }
-            if let Some(o) = cap {
-                self.captures = Cow::Owned(o);
+            self.nc = nc;
+        }
+    }
+    /// Saves the positions past `from` as alternatives of a greedy loop,
+    /// to give them back if the lookahead at `then` doesn't match
+    pub fn give_back(&mut self, then: usize, from: usize) {
+        if self.positions.len() <= from {
+            return;
+        }
+        #[cfg(feature = "diagnostics")]
+        self.count(|stats| stats.checkpoints += self.positions.len() - from);
+        self.saved.push(Saved::GiveBack {
+            then,
+            from,
+            frames: self.frames.len(),
+            decisions: self.marks().1,
+        });
+        self.alternatives += 1;
+    }
+    /// Discards the entries of the backtracking stack past `len`
+    fn cut(&mut self, len: usize) {
+        for saved in self.saved.drain(len..) {
+            match saved {
+                Saved::Alternative { .. } => self.alternatives -= 1,
+                Saved::GiveBack { from, .. } => {
+                    self.alternatives -= 1;
+                    self.positions.truncate(from);
+                }
+                Saved::Tried { .. } => {}
+                #[cfg(all(feature = "diagnostics", feature = "std"))]
+                Saved::Traced(_) | Saved::Reentered(_) => {}
             }
-            if let Some(o) = ocap {
-                self.open_captures = Cow::Owned(o);
+        }
+    }
+    /// Number of entries in the backtracking stack
+    pub fn saved_len(&self) -> usize {
+        self.saved.len()
+    }
+    /// Tries the `i`th branch of an alternation,
+    /// saving the next one, if there is any
+    pub fn branch(&mut self, branches: &'a [MatchCase], i: usize, then: usize) -> Task<'a> {
+        if i + 1 < branches.len() {
+            self.save(Alternative::Branch {
+                branches,
+                i: i + 1,
+                then,
+            });
+        }
+        self.record_decision(i);
+        Task::Match(&branches[i], then)
+    }
+    /// Reports that the search enters `case`, which is followed by the
+    /// lookahead at `then`. Returns the lookahead to follow it with instead,
+    /// that also reports when it matches
+    #[cfg(all(feature = "diagnostics", feature = "std"))]
+    pub fn trace_enter(&mut self, case: &'a MatchCase, then: usize) -> usize {
+        if self.tracer.is_none() {
+            return then;
+        }
+        self.trace(|offset| TraceEvent::Enter { case, offset });
+        if case.is_single() {
+            return then;
+        }
+        self.saved.push(Saved::Traced(case));
+        let saved = self.saved.len() - 1;
+        self.push_frame(LookAheadKind::Traced { case, saved }, then)
+    }
+    /// Matches the lookahead at the index `frame`. Returns
+    /// [None] if the search is over, because it matched
+    fn match_frame(&mut self, frame: usize) -> Option<Task<'a>> {
+        let offset = self.char_offset();
+        let LookAhead {
+            kind,
+            then,
+            alternatives,
+            failed_at,
+        } = self.frames[frame];
+        if self.memoize && matches!(kind, LookAheadKind::List(_) | LookAheadKind::Loop { .. }) {
+            if failed_at == Some(offset) {
+                return Some(Task::Fail);
+            }
+            // Only the alternatives saved after it was pushed
+            // can get to this lookahead again
+            if self.alternatives > alternatives {
+                self.saved.push(Saved::Tried { frame, offset });
             }
-            self.nc = nc;
         }
-        r
+        let task = match kind {
+            LookAheadKind::List(cases) => case::match_list(cases, self, then),
+            LookAheadKind::Loop { case, n, start } => {
+                if offset == start && n > case.min_repetitions() {
+                    Task::Then(then)
+                } else {
+                    case.repeat(self, n, then)
+                }
+            }
+            LookAheadKind::EndCapture => {
+                self.end_capture(offset);
+                Task::Then(then)
+            }
+            LookAheadKind::WordBoundary => {
+                if self.at_word_boundary() {
+                    Task::Then(then)
+                } else {
+                    Task::Fail
+                }
+            }
+            LookAheadKind::EndAtomic { saved } => {
+                self.cut(saved);
+                Task::Then(then)
+            }
+            #[cfg(all(feature = "diagnostics", feature = "std"))]
+            LookAheadKind::Traced { case, saved } => {
+                self.trace(|offset| TraceEvent::Exit {
+                    case,
+                    offset,
+                    matched: true,
+                });
+                if self.saved.len() == saved + 1 {
+                    // There's no other way to match it
+                    self.saved.pop();
+                } else {
+                    self.saved.push(Saved::Reentered(case));
+                }
+                Task::Then(then)
+            }
+            LookAheadKind::End => {
+                if let Some(furthest) = &mut self.furthest_end {
+                    // Keep looking for longer matches
+                    *furthest = offset.max(*furthest);
+                } else if self.end_at.is_none_or(|at| offset == at) {
+                    return None;
+                }
+                Task::Fail
+            }
+            LookAheadKind::Accept => return None,
+        };
+        Some(task)
     }
-    #[inline]
-    pub fn shallow_clone<'slf>(&'slf self) -> RegexCtx<'slf, 'a> {
-        RegexCtx {
-            captures: Cow::Borrowed(&self.captures),
-            nc: self.nc.clone(),
-            open_captures: Cow::Borrowed(&self.open_captures),
-            conf: self.conf,
+    /// Goes back to the last alternative saved, and returns what to do
+    /// from there. Returns [None] if there are no alternatives left
+    fn backtrack(&mut self) -> Option<Task<'a>> {
+        // A greedy loop gives back its positions one by one, so its entry
+        // stays in the stack until there are no more to give back
+        if let Some(&Saved::GiveBack {
+            then,
+            from,
+            frames,
+            decisions,
+        }) = self.saved.last()
+        {
+            let Some((nc, log)) = self.positions.pop() else {
+                unreachable!()
+            };
+            if self.positions.len() == from {
+                self.saved.pop();
+                self.alternatives -= 1;
+            }
+            self.rewind(Checkpoint { nc, log, decisions });
+            self.frames.truncate(frames);
+            return Some(Task::Then(then));
+        }
+        while let Some(saved) = self.saved.pop() {
+            match saved {
+                Saved::Alternative { alt, at, frames } => {
+                    self.alternatives -= 1;
+                    self.rewind(at);
+                    self.frames.truncate(frames);
+                    return Some(match alt {
+                        Alternative::Then(then) => Task::Then(then),
+                        Alternative::Branch { branches, i, then } => self.branch(branches, i, then),
+                        Alternative::Iterate { case, n, then } => case.iterate(self, n, then),
+                    });
+                }
+                Saved::GiveBack { .. } => {
+                    self.saved.push(saved);
+                    return self.backtrack();
+                }
+                Saved::Tried { frame, offset } => self.frames[frame].failed_at = Some(offset),
+                #[cfg(all(feature = "diagnostics", feature = "std"))]
+                Saved::Traced(case) => self.trace(|offset| TraceEvent::Exit {
+                    case,
+                    offset,
+                    matched: false,
+                }),
+                #[cfg(all(feature = "diagnostics", feature = "std"))]
+                Saved::Reentered(case) => self.trace(|offset| TraceEvent::Enter { case, offset }),
+            }
         }
+        None
     }
 }
@@ -0,0 +1,126 @@
+use std::io::{self, Read};
+
+use crate::{MatchEvent, Regex, RegexConf, StreamingMatcher};
+
+/// Size of the chunks read from the underlying reader
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Iterator over the matches of a [Regex] on the contents of a [reader](Read)
+///
+/// The input is read in chunks, and decoded as UTF-8. A character split
+/// between two reads is kept until the rest of it arrives.
+///
+/// Yields the spans of the matches, as offsets from the start of the
+/// data read. The input that can't be part of any future match is
+/// discarded, so large inputs can be scanned without holding them
+/// in memory. See [`StreamingMatcher`] for the details.
+/// If the reader fails or the input isn't valid UTF-8, an
+/// [error](io::Error) is yielded and the iteration stops.
+///
+/// # Example
+/// ```
+/// use regexpr::Regex;
+/// use std::io::Cursor;
+///
+/// let regex = Regex::compile("[0-9]+").unwrap();
+/// let mut matches = regex.find_matches_reader(Cursor::new("a12b345"));
+/// assert_eq!(matches.next().unwrap().unwrap(), (1, 3));
+/// assert_eq!(matches.next().unwrap().unwrap(), (4, 7));
+/// assert!(matches.next().is_none());
+/// ```
+#[derive(Debug)]
+pub struct ReaderMatcher<'r, R> {
+    stream: StreamingMatcher<'r>,
+    reader: R,
+    /// Bytes of an incomplete character at the end of the last read
+    pending: Vec<u8>,
+    chunk: Box<[u8]>,
+    failed: bool,
+}
+
+impl<'r, R: Read> ReaderMatcher<'r, R> {
+    #[must_use]
+    pub fn new(regex: &'r Regex, reader: R, conf: RegexConf) -> Self {
+        ReaderMatcher {
+            stream: StreamingMatcher::new(regex, conf),
+            reader,
+            pending: Vec::new(),
+            chunk: vec![0; CHUNK_SIZE].into_boxed_slice(),
+            failed: false,
+        }
+    }
+
+    /// Returns the input read so far that hasn't been discarded yet
+    ///
+    /// See [`StreamingMatcher::input`]
+    #[must_use]
+    pub fn input(&self) -> &str {
+        self.stream.input()
+    }
+
+    /// Returns the offset of [`input`](Self::input) in the whole input
+    #[must_use]
+    pub fn input_offset(&self) -> usize {
+        self.stream.input_offset()
+    }
+
+    /// Reads the next chunk, and feeds it to the stream
+    fn fill(&mut self) -> io::Result<()> {
+        let n = loop {
+            match self.reader.read(&mut self.chunk) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                res => break res?,
+            }
+        };
+        if n == 0 {
+            if !self.pending.is_empty() {
+                return Err(invalid_utf8());
+            }
+            self.stream.finish();
+            return Ok(());
+        }
+
+        self.pending.extend_from_slice(&self.chunk[..n]);
+        let s = match core::str::from_utf8(&self.pending) {
+            Ok(s) => s,
+            Err(err) if err.error_len().is_none() => {
+                core::str::from_utf8(&self.pending[..err.valid_up_to()])
+                    .map_err(|_| invalid_utf8())?
+            }
+            Err(_) => return Err(invalid_utf8()),
+        };
+        let valid = s.len();
+        self.stream.feed(s);
+        self.pending.drain(..valid);
+        Ok(())
+    }
+}
+
+fn invalid_utf8() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "stream did not contain valid UTF-8",
+    )
+}
+
+impl<R: Read> Iterator for ReaderMatcher<'_, R> {
+    type Item = io::Result<(usize, usize)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            match self.stream.poll()? {
+                MatchEvent::Match { span } => return Some(Ok(span)),
+                MatchEvent::NoMatch => return None,
+                MatchEvent::NeedMore => {
+                    if let Err(err) = self.fill() {
+                        self.failed = true;
+                        return Some(Err(err));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! [serde] support for [Regex]
+//!
+//! A [Regex] is serialized as the pattern it was compiled from,
+//! and deserializing recompiles it.
+
+use alloc::string::String;
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Regex;
+
+impl Serialize for Regex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Regex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let src = String::deserialize(deserializer)?;
+        Regex::compile(src).map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,204 @@
+use alloc::string::String;
+use core::cell::Cell;
+
+use crate::{Regex, RegexConf};
+
+/// Event produced when polling a [`StreamingMatcher`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchEvent {
+    /// A match was found at the given span of the input fed so far
+    Match { span: (usize, usize) },
+    /// The input fed so far isn't enough to decide the next match
+    NeedMore,
+    /// The input is finished and there are no more matches
+    NoMatch,
+}
+
+/// Matcher that receives its input incrementally
+///
+/// The input is fed in chunks with [`feed`](Self::feed), and matches
+/// are reported by [`poll`](Self::poll) as soon as more input can't
+/// change them. Once all the input has been fed, [`finish`](Self::finish)
+/// must be called so the remaining matches can be decided.
+///
+/// The input that can't be part of any future match is discarded,
+/// so the memory used doesn't grow with the size of the whole input,
+/// only with the size of the longest match (or partial match).
+/// The spans of the matches are relative to the start of the whole input.
+///
+/// While a match is undecided, each poll after new input is fed retries
+/// it from its start, so feeding larger chunks is faster. Polling again
+/// without feeding more input doesn't search.
+///
+/// # Example
+/// ```
+/// use regexpr::{MatchEvent, Regex};
+///
+/// let regex = Regex::compile("ab+").unwrap();
+/// let mut matcher = regex.streaming_matcher();
+///
+/// matcher.feed("xxab");
+/// assert_eq!(matcher.poll(), Some(MatchEvent::NeedMore));
+///
+/// matcher.feed("bbc");
+/// assert_eq!(matcher.poll(), Some(MatchEvent::Match { span: (2, 6) }));
+/// assert_eq!(matcher.poll(), Some(MatchEvent::NeedMore));
+///
+/// matcher.finish();
+/// assert_eq!(matcher.poll(), Some(MatchEvent::NoMatch));
+/// assert_eq!(matcher.poll(), None);
+/// ```
+#[derive(Debug, Clone)]
+pub struct StreamingMatcher<'r> {
+    regex: &'r Regex,
+    conf: RegexConf,
+    /// Input fed, and not yet discarded
+    buf: String,
+    /// Offset of `buf` in the whole input
+    offset: usize,
+    /// Offset in `buf` where the next match attempt starts
+    pos: usize,
+    /// Offset in the whole input where the last match ended
+    last_end: usize,
+    finished: bool,
+    state: State,
+}
+
+/// What the next poll of a [`StreamingMatcher`] has to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Look for the next match
+    Searching,
+    /// The last poll needed more input, and none was fed since
+    Waiting,
+    /// A match was found at the end of the finished input,
+    /// after which there can't be more matches
+    EndMatched,
+    /// [`MatchEvent::NoMatch`] was reported
+    Exhausted,
+}
+
+impl<'r> StreamingMatcher<'r> {
+    #[must_use]
+    pub fn new(regex: &'r Regex, conf: RegexConf) -> Self {
+        StreamingMatcher {
+            regex,
+            conf,
+            buf: String::new(),
+            offset: 0,
+            pos: 0,
+            last_end: 0,
+            finished: false,
+            state: State::Searching,
+        }
+    }
+
+    /// Appends a chunk to the input
+    ///
+    /// Chunks fed after calling [`finish`](Self::finish) are ignored
+    pub fn feed(&mut self, chunk: &str) {
+        if !self.finished && !chunk.is_empty() {
+            self.buf.push_str(chunk);
+            self.stop_waiting();
+        }
+    }
+
+    /// Marks the end of the input
+    pub fn finish(&mut self) {
+        self.finished = true;
+        self.stop_waiting();
+    }
+
+    fn stop_waiting(&mut self) {
+        if self.state == State::Waiting {
+            self.state = State::Searching;
+        }
+    }
+
+    /// Returns the input fed so far that hasn't been discarded yet
+    ///
+    /// It starts at [`input_offset`](Self::input_offset) of the whole
+    /// input. The span of the last match is guaranteed to be in it,
+    /// until the next call to [`poll`](Self::poll)
+    #[must_use]
+    pub fn input(&self) -> &str {
+        &self.buf
+    }
+
+    /// Returns the offset of [`input`](Self::input) in the whole input
+    #[must_use]
+    pub fn input_offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Discards the input before the next match attempt, keeping the
+    /// character right before it, since anchors and `\b` look at it
+    fn discard(&mut self) {
+        let cut = self.buf[..self.pos]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(i, _)| i);
+        self.buf.drain(..cut);
+        self.offset += cut;
+        self.pos -= cut;
+    }
+
+    /// Polls the next event
+    ///
+    /// Returns [`None`] after [`MatchEvent::NoMatch`] has been reported
+    pub fn poll(&mut self) -> Option<MatchEvent> {
+        match self.state {
+            State::Searching => {}
+            // Searching the same input again would give the same result
+            State::Waiting => return Some(MatchEvent::NeedMore),
+            State::EndMatched => {
+                self.state = State::Exhausted;
+                return Some(MatchEvent::NoMatch);
+            }
+            State::Exhausted => return None,
+        }
+
+        self.discard();
+
+        let eoi = Cell::new(false);
+        let mut matcher = self
+            .regex
+            .find_matches_with_conf(&self.buf, self.conf)
+            .with_base_offset(self.offset)
+            .with_prev_end(self.last_end.checked_sub(self.offset));
+        if !self.finished {
+            matcher = matcher.track_end(&eoi);
+        }
+        matcher.skip_to(self.pos);
+
+        let event = match matcher.next() {
+            Some(_) if eoi.get() => {
+                self.pos = matcher.end_reached_from().unwrap_or(self.pos);
+                self.state = State::Waiting;
+                MatchEvent::NeedMore
+            }
+            Some(m) if self.finished && m.start() == self.offset + self.buf.len() => {
+                self.state = State::EndMatched;
+                self.last_end = m.end();
+                MatchEvent::Match { span: m.span() }
+            }
+            Some(m) => {
+                self.pos = matcher.offset();
+                self.last_end = m.end();
+                MatchEvent::Match { span: m.span() }
+            }
+            None if self.finished => {
+                self.state = State::Exhausted;
+                MatchEvent::NoMatch
+            }
+            None => {
+                // The attempts before the first one that reached the
+                // end of the input can't match, even with more input
+                self.pos = matcher.end_reached_from().unwrap_or(self.buf.len());
+                self.state = State::Waiting;
+                MatchEvent::NeedMore
+            }
+        };
+        Some(event)
+    }
+}
@@ -8,7 +8,10 @@
 
 use std::borrow::Cow;
 
-use crate::{DEFAULT_REGEX_CONF, Regex, RegexConf, RegexTestable, ReplaceRegex};
+use crate::{
+    DEFAULT_REGEX_CONF, MatchEvent, Piece, Regex, RegexBuilder, RegexConf, RegexTestable,
+    ReplaceRegex,
+};
 
 macro_rules! template_with_conf {
     ($r:expr, $c:expr, $must_pass:expr, $must_fail:expr $(,)?) => {
@@ -33,6 +36,12 @@ macro_rules! template {
     };
 }
 
+/// Returns the spans of all the matches of `pattern` in `src`
+fn spans(pattern: &str, src: &str) -> Vec<(usize, usize)> {
+    let regex = Regex::compile(pattern).unwrap();
+    regex.find_matches(src).map(|m| m.span()).collect()
+}
+
 #[test]
 fn abc() {
     template!(
@@ -56,6 +65,19 @@ fn dot() {
 #[test]
 fn or() {
     template!("(abc|cba)", &["abc", "cba", "babc", "aabc"], &["cga"]);
+
+    // The rest of the pattern isn't tried again for every branch that led to it
+    let conf = RegexConf {
+        max_steps: 1_000_000,
+        ..DEFAULT_REGEX_CONF
+    };
+    for pattern in ["(a|a){200}c", "(a|a)*c", "(a|a)+c"] {
+        let regex = Regex::compile(pattern).unwrap();
+        let src = "a".repeat(200);
+        let mut matcher = regex.find_matches_with_conf(&src, conf);
+        assert!(matcher.next().is_none());
+        assert!(!matcher.aborted());
+    }
 }
 
 #[test]
@@ -115,6 +137,23 @@ fn start_end() {
 fn trait_test() {
     assert!("a??bbbc".matches_regex("a..b+c"));
     assert!(!"abc".matches_regex("a\\.c"));
+    assert!(!"abc".matches_regex("*abc"));
+
+    assert!("abc".try_matches_regex("a.c").unwrap());
+    assert!(!"abc".try_matches_regex("a\\.c").unwrap());
+    assert!("abc".try_matches_regex("+abc").is_err());
+
+    // Implementing matches_regex is enough
+    struct Chars(Vec<char>);
+    impl RegexTestable for Chars {
+        fn matches_regex(&self, regex: &str) -> bool {
+            self.0.iter().collect::<String>().matches_regex(regex)
+        }
+    }
+    let chars = Chars(vec!['a', 'b']);
+    assert!(chars.try_matches_regex("b$").unwrap());
+    assert!(!chars.try_matches_regex("c").unwrap());
+    assert!(chars.try_matches_regex("(b").is_err());
 }
 
 #[test]
@@ -129,12 +168,25 @@ fn nested() {
 #[test]
 fn fail() {
     for c in ["?", "*", "+"] {
-        let msg = format!("Expected pattern before '{c}'");
+        let msg = format!("Expected pattern before '{c}' at byte 0");
         match Regex::compile(c) {
             Ok(_) => panic!(),
             Err(err) => assert_eq!(err.to_string(), msg),
         }
     }
+
+    for (pattern, msg) in [
+        ("a(*)", "Expected pattern before '*' at byte 2"),
+        ("(a|*)", "Expected pattern before '*' at byte 3"),
+        ("(|*)", "Expected pattern before '*' at byte 2"),
+        ("a|+b", "Expected pattern before '+' at byte 2"),
+        ("ñ|?", "Expected pattern before '?' at byte 3"),
+        ("a({2})", "Expected pattern before '{' at byte 2"),
+        ("(ab)|{1,}", "Expected pattern before '{' at byte 5"),
+    ] {
+        let err = Regex::compile(pattern).unwrap_err();
+        assert_eq!(err.to_string(), msg, "{pattern}");
+    }
 }
 
 #[test]
@@ -167,6 +219,10 @@ fn find_matches() {
     assert_eq!(0, m.slice().len());
     assert_eq!("", m.slice());
 
+    let m = matches.next().unwrap();
+    assert_eq!((2, 2), m.span());
+    assert_eq!("", m.slice());
+
     assert!(matches.next().is_none());
 }
 
@@ -268,6 +324,107 @@ fn capture_or() {
     );
 }
 
+#[test]
+fn capture_names() {
+    let regex = Regex::compile("(?<a>x)(y)(?<c>z)").unwrap();
+    assert_eq!(regex.capture_count(), 3);
+    assert_eq!(
+        regex.capture_names().collect::<Vec<_>>(),
+        [Some("a"), None, Some("c")]
+    );
+
+    let regex = Regex::compile("abc").unwrap();
+    assert_eq!(regex.capture_count(), 0);
+    assert_eq!(regex.capture_names().count(), 0);
+}
+
+#[test]
+fn quantified_backreference() {
+    template!("^(\\w)\\1+$", &["aa", "zzzz"], &["a", "ab", "aab"]);
+    let regex = Regex::compile("(\\w)\\1+").unwrap();
+    let runs: Vec<_> = regex.find_matches("aaab xyyy").map(|m| m.slice()).collect();
+    assert_eq!(runs, ["aaa", "yyy"]);
+
+    template!("^(ab)\\1{2}$", &["ababab"], &["abab", "abababab"]);
+    template!("^(ab)\\1*$", &["ab", "ababab"], &["aba", "abb"]);
+    template!("^(a|b)\\1*?b$", &["ab", "aab", "bb"], &["abab"]);
+    template!("^(a)\\1{2,}a$", &["aaaa", "aaaaa"], &["aaa"]);
+
+    // Multi-char captures that must give back text when backtracking
+    let regex = Regex::compile("(a*)\\1+b").unwrap();
+    let m = regex.find("aaab").unwrap();
+    assert_eq!(m.span(), (0, 4));
+    assert_eq!(m.groups(), [Some("a")]);
+    // Repeating an empty capture doesn't loop forever
+    assert_eq!(
+        Regex::compile("(a*)\\1*b")
+            .unwrap()
+            .find("xb")
+            .unwrap()
+            .span(),
+        (1, 2)
+    );
+}
+
+#[test]
+fn unset_capture() {
+    // A backreference to a group that didn't participate never matches
+    template!("^(a)?b\\1$", &["aba"], &["b", "ab", "ba"]);
+    template!("(a)?b\\1", &["aba", "xaba"], &["b", "ab"]);
+    template!("^((a)|b)\\2$", &["aa"], &["b", "bb", "a"]);
+    template!("^((a)|(b))*\\2\\3$", &["abab", "baab"], &["bb", "aa"]);
+}
+
+#[test]
+fn capture_or_numbering() {
+    let regex = Regex::compile("(a(b)c|x(y)z)").unwrap();
+    assert_eq!(regex.capture_count(), 3);
+    assert_eq!(
+        regex.find("abc").unwrap().groups(),
+        [Some("abc"), Some("b"), None]
+    );
+    assert_eq!(
+        regex.find("xyz").unwrap().groups(),
+        [Some("xyz"), None, Some("y")]
+    );
+
+    let regex = Regex::compile("^(?<l>a(b)|c(d))(e|(f))$").unwrap();
+    assert_eq!(
+        regex.find("cdf").unwrap().groups(),
+        [Some("cd"), None, Some("d"), Some("f"), Some("f")]
+    );
+    let caps = regex.captures("abe").unwrap();
+    assert_eq!(caps.name("l"), Some("ab"));
+    assert_eq!(caps.get(2), Some("b"));
+    assert_eq!(caps.get(3), None);
+    assert_eq!(caps.get(5), None);
+}
+
+#[test]
+fn empty_branches() {
+    template!("^(|abc)$", &["", "abc"], &["ab", "abcabc"]);
+    template!("^(abc|)$", &["", "abc"], &["ab", "abcabc"]);
+    template!("^(a||b)$", &["", "a", "b"], &["ab"]);
+    template!("^(a|b|)c$", &["c", "ac", "bc"], &["abc"]);
+    template!("^x(|)y$", &["xy"], &["x y"]);
+
+    // At the top level
+    template!("^a$||^b$", &["a", "b", "ab"], &[]);
+    let regex = Regex::compile("|a").unwrap();
+    assert_eq!(regex.find("a").unwrap().span(), (0, 0));
+    let regex = Regex::compile("a|").unwrap();
+    assert_eq!(regex.find("a").unwrap().span(), (0, 1));
+
+    // The empty branch takes part in the match
+    let regex = Regex::compile("^(|a)(a*)$").unwrap();
+    assert_eq!(regex.find("aa").unwrap().groups(), [Some(""), Some("aa")]);
+
+    for pattern in ["(|abc)", "(abc|)", "a||b", "|"] {
+        let regex = Regex::compile(pattern).unwrap();
+        assert_eq!(Regex::compile(regex.to_pattern()).unwrap(), regex);
+    }
+}
+
 #[test]
 fn case_sensitive() {
     template_with_conf!(
@@ -275,6 +432,7 @@ fn case_sensitive() {
         RegexConf {
             case_sensitive: false,
             ignore_captures_in_result: false,
+            ..DEFAULT_REGEX_CONF
         },
         &["abcz", "ABCz", "AbcZ", "abCZbABc"],
         &["abz", "abdc"],
@@ -284,6 +442,7 @@ fn case_sensitive() {
         RegexConf {
             case_sensitive: true,
             ignore_captures_in_result: false,
+            ..DEFAULT_REGEX_CONF
         },
         &["abcz", "abca"],
         &["ABC", "Abc", "abcZ", "abCbABc", "ab", "abdc"],
@@ -310,4 +469,2161 @@ fn replace_regex() {
     let replaced = input.replace_regex("[0-9]", "P").unwrap();
     assert!(matches!(replaced, Cow::Borrowed(_)));
     assert_eq!(replaced, input);
+
+    let replaced = "abc".replace_regex("b", "$0$0").unwrap();
+    assert_eq!(replaced, "abbc");
+    let replaced = "k=v".replace_regex("(?<k>\\w)=(\\w)", "$2:${k}$$").unwrap();
+    assert_eq!(replaced, "v:k$");
+    let regex = Regex::compile("b").unwrap();
+    assert_eq!(regex.replace("abc", "$0"), "a$0c");
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn serde() {
+    let regex = Regex::compile("^a(b|c)+$").unwrap();
+    let json = serde_json::to_string(&regex).unwrap();
+    assert_eq!(json, r#""^a(b|c)+$""#);
+
+    let regex: Regex = serde_json::from_str(&json).unwrap();
+    assert!(regex.test("abcb"));
+    assert!(!regex.test("ad"));
+
+    let err = serde_json::from_str::<Regex>(r#""*a""#).unwrap_err();
+    assert!(err.to_string().contains("Expected pattern before '*'"));
+}
+
+#[test]
+fn streaming() {
+    fn stream(pattern: &str, chunks: &[&str]) -> Vec<(usize, usize)> {
+        let regex = Regex::compile(pattern).unwrap();
+        let mut matcher = regex.streaming_matcher();
+        let mut spans = Vec::new();
+        for chunk in chunks.iter().map(Some).chain([None]) {
+            match chunk {
+                Some(chunk) => matcher.feed(chunk),
+                None => matcher.finish(),
+            }
+            loop {
+                match matcher.poll() {
+                    Some(MatchEvent::Match { span }) => spans.push(span),
+                    Some(MatchEvent::NeedMore) => break,
+                    Some(MatchEvent::NoMatch) => {
+                        assert!(matcher.poll().is_none());
+                        break;
+                    }
+                    None => unreachable!(),
+                }
+            }
+        }
+
+        let input = chunks.concat();
+        let expected: Vec<_> = regex.find_matches(&input).map(|m| m.span()).collect();
+        assert_eq!(spans, expected);
+        spans
+    }
+
+    assert_eq!(
+        stream("ab+", &["a", "bb", "xab", "", "bbbx", "a"]),
+        [(0, 3), (4, 9)]
+    );
+    assert_eq!(
+        stream("a(1.*?2)b", &["a1", "22", "b a", "12", "b"]),
+        [(0, 5), (6, 10)]
+    );
+    assert_eq!(stream("abc$", &["abc", "abc"]), [(3, 6)]);
+    assert_eq!(stream("^ab", &["a", "bab"]), [(0, 2)]);
+    assert_eq!(
+        stream("a*", &["b", "aab"]),
+        [(0, 0), (1, 3), (3, 3), (4, 4)]
+    );
+    assert_eq!(stream("(ab|a)c", &["xa", "bc"]), [(1, 4)]);
+    assert_eq!(stream("$", &["ab", "", "c"]), [(3, 3)]);
+    assert_eq!(stream("", &["a", "b"]), [(0, 0), (1, 1), (2, 2)]);
+    assert_eq!(stream("añ", &["a", "ñ", "ña"]), [(0, 3)]);
+    assert_eq!(stream("\\bab\\b", &["xab a", "b ", "ab"]), [(4, 6), (7, 9)]);
+    assert_eq!(stream("(?m)^ñ", &["ñxñ\n", "ñ", "ñ"]), [(0, 2), (6, 8)]);
+    assert_eq!(stream("^a", &["ba", "a"]), []);
+
+    // Input that can't be part of a match is discarded
+    let regex = Regex::compile("ab+").unwrap();
+    let mut matcher = regex.streaming_matcher();
+    for _ in 0..10_000 {
+        matcher.feed("xyz");
+        assert_eq!(matcher.poll(), Some(MatchEvent::NeedMore));
+        assert!(matcher.input().len() <= 4);
+    }
+    matcher.feed("abb");
+    assert_eq!(matcher.poll(), Some(MatchEvent::NeedMore));
+    matcher.feed("c");
+    assert_eq!(
+        matcher.poll(),
+        Some(MatchEvent::Match {
+            span: (30_000, 30_003)
+        })
+    );
+    let offset = matcher.input_offset();
+    assert_eq!(&matcher.input()[30_000 - offset..30_003 - offset], "abb");
+
+    // Polling without new input doesn't search it again
+    let mut matcher = regex.streaming_matcher();
+    matcher.feed(&("a".to_string() + &"b".repeat(10_000)));
+    for _ in 0..10_000 {
+        assert_eq!(matcher.poll(), Some(MatchEvent::NeedMore));
+    }
+    matcher.feed("");
+    assert_eq!(matcher.poll(), Some(MatchEvent::NeedMore));
+    matcher.finish();
+    assert_eq!(
+        matcher.poll(),
+        Some(MatchEvent::Match { span: (0, 10_001) })
+    );
+}
+
+#[test]
+fn as_str() {
+    for pattern in ["", "abc", "^a(?<x>b|c)+\\k<x>$", "[^a-z]{2,}?"] {
+        let regex = Regex::compile(pattern).unwrap();
+        assert_eq!(regex.as_str(), pattern);
+        assert_eq!(regex.to_string(), pattern);
+    }
+}
+
+#[test]
+fn decision_trace() {
+    let conf = RegexConf {
+        trace_decisions: true,
+        ..DEFAULT_REGEX_CONF
+    };
+
+    let regex = Regex::compile("(a|ab)c").unwrap();
+    let m = regex.find_matches_with_conf("abc", conf).next().unwrap();
+    assert_eq!(m.slice(), "abc");
+    assert_eq!(m.decision_trace(), &[(0, 1)]);
+
+    let m = regex.find_matches("abc").next().unwrap();
+    assert!(m.decision_trace().is_empty());
+
+    let regex = Regex::compile("((x|y)(1|2))+").unwrap();
+    let m = regex.find_matches_with_conf("_x2y1", conf).next().unwrap();
+    assert_eq!(m.decision_trace(), &[(1, 0), (2, 1), (3, 1), (4, 0)]);
+}
+
+#[test]
+fn to_pattern() {
+    for (pattern, expected) in [
+        ("abc", "abc"),
+        ("a(bc)*", "a(bc)*"),
+        ("^(abc|cba)$", "^(abc|cba)$"),
+        ("a|b.c", "a|b.c"),
+        ("a+?b*?c?", "a+?b*?c?"),
+        ("\\.\\*\\(\\{", "\\.\\*\\(\\{"),
+        ("[^a-z01]{3,5}", "[^a-z01]{3,5}"),
+        ("[\\]\\-\\\\^]", "[\\]\\-\\\\^]"),
+        ("[\\^a]", "[\\^a]"),
+        ("a{6,6}b{,2}c{3,}", "a{6}b{,2}c{3,}"),
+        ("\\s\\S\\d\\D\\w\\W", "\\s\\S\\d\\D\\w\\W"),
+        ("(?<n>.)\\k<n>", "(.)\\1"),
+        ("(a)\\k<1>0", "(a)\\k<1>0"),
+        ("(a)(b)(c)(?:\\3)1{1,2}", "(a)(b)(c)\\k<3>1{1,2}"),
+        ("(a)(?:\\1)1*?", "(a)\\k<1>1*?"),
+        ("(a)(?:\\1)1?", "(a)\\k<1>1?"),
+        ("(a)\\1+\\1", "(a)\\1+\\1"),
+        ("x(?:a|b)", "x(?:a|b)"),
+        ("(?:ab)*(?:a|b)+(?:a?){2}", "(?:ab)*(?:a|b)+(?:a?){2}"),
+        ("a|(?:b|c)d", "a|(?:b|c)d"),
+        ("(?:a|b)|c", "(?:a|b)|c"),
+        ("(x)(?(1)(?:a|b)|c)", "(x)(?(1)(?:a|b)|c)"),
+    ] {
+        let regex = Regex::compile(pattern).unwrap();
+        let canonical = regex.to_pattern();
+        assert_eq!(canonical, expected);
+        let recompiled = Regex::compile(&canonical).unwrap();
+        assert_eq!(recompiled.to_pattern(), canonical);
+    }
+}
+
+#[test]
+fn word_boundary() {
+    template!(
+        "\\bcat\\b",
+        &["cat", "a cat", "cat!", "(cat)"],
+        &["category", "bobcat", "cats"],
+    );
+    template!("\\Bcat", &["bobcat", "_cat"], &["cat", "a cat"]);
+    template!("a\\b.*\\bb", &["a b", "a..b", "a.b."], &["ab", "a_b"]);
+}
+
+#[test]
+fn whole_word() {
+    let conf = RegexConf {
+        whole_word: true,
+        ..DEFAULT_REGEX_CONF
+    };
+    template_with_conf!(
+        "cat",
+        conf,
+        &["a cat", "cat", "cat."],
+        &["category", "bobcat"]
+    );
+
+    let regex = Regex::compile("a+").unwrap();
+    let spans: Vec<_> = regex
+        .find_matches_with_conf("aa baaa aaa_ aaa", conf)
+        .map(|m| m.span())
+        .collect();
+    assert_eq!(spans, [(0, 2), (13, 16)]);
+}
+
+#[test]
+fn max_steps() {
+    let regex = Regex::compile("(a+)+b").unwrap();
+    let conf = RegexConf {
+        max_steps: 200,
+        ..DEFAULT_REGEX_CONF
+    };
+
+    let mut matcher = regex.find_matches_with_conf("aaaaaaaaaaaaaaaaaaaaaaaac", conf);
+    assert!(matcher.next().is_none());
+    assert!(matcher.aborted());
+    assert!(matcher.next().is_none());
+
+    let mut matcher = regex.find_matches_with_conf("aab_ab", conf);
+    assert_eq!(matcher.next().unwrap().slice(), "aab");
+    assert_eq!(matcher.next().unwrap().slice(), "ab");
+    assert!(matcher.next().is_none());
+    assert!(!matcher.aborted());
+
+    let regex = Regex::compile("a").unwrap();
+    let conf = RegexConf {
+        max_steps: 3,
+        ..DEFAULT_REGEX_CONF
+    };
+    assert_eq!(regex.find_matches_with_conf("aaaaa", conf).count(), 3);
+
+    // The match found before the search is aborted is kept
+    let regex = Regex::compile("a|(a+)+\\1b").unwrap();
+    let conf = conf.max_steps(5000).longest(true);
+    let src = "a".repeat(30);
+    let mut matcher = regex.find_matches_with_conf(&src, conf);
+    assert_eq!(matcher.next().unwrap().span(), (0, 1));
+    assert!(matcher.aborted());
+    assert!(matcher.next().is_none());
+    #[allow(deprecated)]
+    let exceeded = matcher.steps_exceeded();
+    assert!(exceeded);
+}
+
+#[test]
+fn backreference_validation() {
+    for (pattern, msg) in [
+        ("\\2(a)(b)", "Forward/self reference to group 2 not allowed"),
+        ("(a)\\2(b)", "Forward/self reference to group 2 not allowed"),
+        ("(\\1a)", "Forward/self reference to group 1 not allowed"),
+        ("(a(b\\1))", "Forward/self reference to group 1 not allowed"),
+        ("(a(b)\\1)", "Forward/self reference to group 1 not allowed"),
+        ("(a)(b)\\3", "Backreference to unknown group 3"),
+        ("(a)\\0", "Backreference to unknown group 0"),
+    ] {
+        match Regex::compile(pattern) {
+            Ok(_) => panic!("{pattern} should've failed"),
+            Err(err) => assert_eq!(err.to_string(), msg),
+        }
+    }
+    assert!(Regex::compile("(a)(b)\\2\\1").is_ok());
+    assert!(Regex::compile("(a(b)\\2)\\1").is_ok());
+}
+
+#[test]
+fn expand() {
+    let regex = Regex::compile("(\\d+)-(\\d+)").unwrap();
+    let m = regex.find_matches("from 12-345").next().unwrap();
+    assert_eq!(m.expand("$2/$1"), "345/12");
+    assert_eq!(m.expand("${2}0 ${1}"), "3450 12");
+    assert_eq!(m.expand("[$0] $$1 $3 $x"), "[12-345] $1  $x");
+
+    let regex = Regex::compile("(?<user>\\w+)@(?<host>\\w+)").unwrap();
+    let m = regex.find_matches("me@host").next().unwrap();
+    assert_eq!(m.expand("${host}: ${user} ${other}"), "host: me ");
+}
+
+#[test]
+fn deadline() {
+    use std::time::{Duration, Instant};
+
+    let input = "a".repeat(100_000) + "b";
+    let regex = Regex::compile("(a|b)+c").unwrap();
+
+    let mut matcher = regex.find_matches_with_deadline(&input, Instant::now());
+    assert!(matcher.next().is_none());
+    assert!(matcher.aborted());
+
+    let regex = Regex::compile("ab").unwrap();
+    let deadline = Instant::now() + Duration::from_secs(60);
+    let mut matcher = regex.find_matches_with_deadline("ab_ab", deadline);
+    assert_eq!(matcher.by_ref().count(), 2);
+    assert!(!matcher.aborted());
+}
+
+#[test]
+fn base_offset() {
+    let doc = "ab ab ab";
+    let regex = Regex::compile("ab").unwrap();
+    let matches: Vec<_> = regex
+        .find_matches(&doc[3..])
+        .with_base_offset(3)
+        .map(|m| (m.span(), m.slice()))
+        .collect();
+    assert_eq!(matches, [((3, 5), "ab"), ((6, 8), "ab")]);
+    for ((start, end), slice) in matches {
+        assert_eq!(&doc[start..end], slice);
+    }
+}
+
+#[test]
+fn find() {
+    let regex = Regex::compile("b+").unwrap();
+    let m = regex.find("abbcb").unwrap();
+    assert_eq!(m.span(), (1, 3));
+    assert!(regex.find("ac").is_none());
+
+    let conf = RegexConf {
+        case_sensitive: false,
+        ..DEFAULT_REGEX_CONF
+    };
+    let regex = Regex::compile("[a-c]+").unwrap();
+    assert_eq!(regex.find_with_conf("xxABc", conf).unwrap().slice(), "ABc");
+}
+
+#[test]
+fn multiline_dot_all() {
+    assert_eq!(spans("^.*$", "a\nb"), []);
+    assert_eq!(spans("(?m)^.*$", "a\nb"), [(0, 1), (2, 3)]);
+    assert_eq!(spans("(?s)^.*$", "a\nb"), [(0, 3)]);
+    assert_eq!(spans("(?sm)^.*$", "a\nb"), [(0, 3)]);
+    assert_eq!(spans("(?ms)^.*?$", "a\nb"), [(0, 1), (2, 3)]);
+
+    assert_eq!(spans("a.b", "a\nb"), []);
+    assert_eq!(spans("(?s)a.b", "a\nb"), [(0, 3)]);
+    assert_eq!(spans("(?m)^b", "b\nb\nab"), [(0, 1), (2, 3)]);
+    assert_eq!(spans("(?m)a$", "a\na"), [(0, 1), (2, 3)]);
+    assert_eq!(spans("(?i)abc", "xABc"), [(1, 4)]);
+
+    let conf = RegexConf {
+        multiline: true,
+        dot_all: true,
+        ..DEFAULT_REGEX_CONF
+    };
+    let regex = Regex::compile("^.*$").unwrap();
+    let m = regex.find_matches_with_conf("a\nb", conf).next().unwrap();
+    assert_eq!(m.span(), (0, 3));
+
+    assert_eq!(Regex::compile("(?ms)a").unwrap().to_pattern(), "(?ms)a");
+
+    // Groups that don't capture, and set flags for the whole expression
+    let regex = Regex::compile("(?:a|b)+(c)").unwrap();
+    assert_eq!(regex.capture_count(), 1);
+    assert_eq!(regex.find("xabc").unwrap().groups(), [Some("c")]);
+    assert_eq!(spans("(?s:a.)b.", "a\nb\n"), [(0, 4)]);
+    assert_eq!(spans("x(?i:a)b", "XAB"), [(0, 3)]);
+    assert_eq!(spans("(?:)a", "a"), [(0, 1)]);
+    match Regex::compile("(?x)a") {
+        Ok(_) => panic!(),
+        Err(err) => assert_eq!(err.to_string(), "Unknown flag 'x'"),
+    }
+}
+
+#[test]
+fn match_count() {
+    let regex = Regex::compile("(a|b)c").unwrap();
+    assert_eq!(regex.match_count("ac bc cc abc"), 3);
+    assert_eq!(regex.match_count(""), 0);
+    assert_eq!(Regex::compile("").unwrap().match_count("abc"), 4);
+}
+
+#[test]
+fn anchored() {
+    let conf = RegexConf {
+        anchored: true,
+        ..DEFAULT_REGEX_CONF
+    };
+    template_with_conf!("a+b", conf, &["ab", "aab", "abab"], &["cab", " ab", ""]);
+
+    let regex = Regex::compile("ab").unwrap();
+    assert_eq!(regex.find_matches_with_conf("ababab", conf).count(), 1);
+}
+
+#[test]
+fn fuzz() {
+    for seed in 0..500 {
+        let (pattern, inputs) = Regex::fuzz_seed(seed);
+        let regex = Regex::compile(&pattern)
+            .unwrap_or_else(|err| panic!("[seed {seed}] {pattern:?} failed to compile: {err}"));
+        for input in inputs {
+            if let Err(err) = crate::fuzz::check(&regex, &input) {
+                panic!("[seed {seed}] {pattern:?} on {input:?}: {err}");
+            }
+        }
+    }
+}
+
+#[test]
+fn replace_variants() {
+    let regex = Regex::compile("[0-9]").unwrap();
+
+    assert_eq!(regex.replace("a1b2c3", "_"), "a_b_c_");
+    assert_eq!(regex.replace_first("a1b2c3", "_"), "a_b2c3");
+    assert_eq!(regex.replacen("a1b2c3", 2, "_"), "a_b_c3");
+    assert_eq!(
+        regex.replace_with("a1b2c3", |m| format!("<{}>", m.slice())),
+        "a<1>b<2>c<3>"
+    );
+
+    let input = "abc";
+    assert!(matches!(regex.replace(input, "_"), Cow::Borrowed("abc")));
+    assert!(matches!(
+        regex.replace_first(input, "_"),
+        Cow::Borrowed("abc")
+    ));
+    assert!(matches!(
+        regex.replacen(input, 3, "_"),
+        Cow::Borrowed("abc")
+    ));
+    assert!(matches!(
+        regex.replace_with(input, |_| "_"),
+        Cow::Borrowed("abc")
+    ));
+    assert!(matches!(regex.replacen("a1", 0, "_"), Cow::Borrowed("a1")));
+}
+
+#[test]
+fn groups() {
+    let regex = Regex::compile("(?<k>\\w+)=(\\d*)(;)?").unwrap();
+    let m = regex.find("key=").unwrap();
+    assert_eq!(m.groups(), &[Some("key"), Some(""), None]);
+    assert_eq!(m.group(0), Some("key="));
+    assert_eq!(m.group(1), Some("key"));
+    assert_eq!(m.group(2), Some(""));
+    assert_eq!(m.group(3), None);
+    assert_eq!(m.group(4), None);
+
+    let m = regex.find("a=12;").unwrap();
+    assert_eq!(m.groups(), &[Some("a"), Some("12"), Some(";")]);
+}
+
+#[test]
+fn unmatched_groups() {
+    let groups = |pattern: &str, src: &str| -> Vec<Option<String>> {
+        let regex = Regex::compile(pattern).unwrap();
+        let m = regex.find(src).unwrap();
+        m.groups().iter().map(|g| g.map(String::from)).collect()
+    };
+    let s = |s: &str| Some(String::from(s));
+
+    assert_eq!(groups("(a)|(b)", "b"), [None, s("b")]);
+    assert_eq!(groups("(a)|(b)", "a"), [s("a"), None]);
+    assert_eq!(groups("(x)?y", "y"), [None]);
+    assert_eq!(groups("(x?)y", "y"), [s("")]);
+    assert_eq!(groups("(a*)(b)?c", "c"), [s(""), None]);
+    assert_eq!(groups("((a)|b)+", "b"), [s("b"), None]);
+    assert_eq!(groups("((a)|b)+", "ab"), [s("b"), s("a")]);
+}
+
+#[test]
+fn reader() {
+    use std::io::{Cursor, Read};
+
+    /// Reader that returns at most `n` bytes on each read,
+    /// so multi-byte characters get split between reads
+    struct Trickle<R>(R, usize);
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.1);
+            self.0.read(&mut buf[..n])
+        }
+    }
+
+    let regex = Regex::compile("ñ+|€").unwrap();
+    let src = "aññb€cñ";
+    for n in 1..=4 {
+        let mut matcher = regex.find_matches_reader(Trickle(Cursor::new(src.as_bytes()), n));
+        let spans: Vec<_> = matcher.by_ref().map(Result::unwrap).collect();
+        assert_eq!(spans, [(1, 5), (6, 9), (10, 12)]);
+        assert_eq!(&src[matcher.input_offset()..], matcher.input());
+    }
+
+    let invalid: &[u8] = b"ab\xC3";
+    let regex = Regex::compile("b").unwrap();
+    let mut matcher = regex.find_matches_reader(Cursor::new(invalid));
+    assert!(matcher.next().unwrap().is_ok());
+    let err = matcher.next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(matcher.next().is_none());
+}
+
+#[test]
+fn dangling_escape() {
+    for (pattern, msg) in [
+        ("abc\\", "Incomplete escape at byte 3"),
+        ("\\", "Incomplete escape at byte 0"),
+        ("ñ\\", "Incomplete escape at byte 2"),
+        ("[a\\", "Incomplete escape at byte 2"),
+    ] {
+        match Regex::compile(pattern) {
+            Ok(_) => panic!("{pattern} should've failed"),
+            Err(err) => assert_eq!(err.to_string(), msg),
+        }
+    }
+}
+
+#[test]
+fn structural_hash() {
+    use std::collections::HashMap;
+
+    let hash = |pattern: &str| Regex::compile(pattern).unwrap().structural_hash();
+
+    for (a, b) in [("a\\-b", "a-b"), ("\\w+\\.", "\\w+\\."), ("x\\/", "x/")] {
+        assert_eq!(Regex::compile(a).unwrap(), Regex::compile(b).unwrap());
+        assert_eq!(hash(a), hash(b));
+    }
+    for (a, b) in [
+        ("ab", "ba"),
+        ("a+", "a*"),
+        ("a*", "a*?"),
+        ("(a)", "(?<x>a)"),
+        ("(?i)a", "a"),
+        ("[ab]", "a|b"),
+    ] {
+        assert_ne!(Regex::compile(a).unwrap(), Regex::compile(b).unwrap());
+        assert_ne!(hash(a), hash(b), "{a} and {b}");
+    }
+
+    let mut cache = HashMap::new();
+    cache.insert(Regex::compile("a-b").unwrap(), 1);
+    assert_eq!(cache.get(&Regex::compile("a\\-b").unwrap()), Some(&1));
+}
+
+#[test]
+fn find_first_n() {
+    let regex = Regex::compile("a[0-9]+").unwrap();
+    let src = "a123 ".repeat(1000);
+
+    // The budget is enough for a couple of matches, but not for
+    // scanning the whole input, so the search must stop right
+    // after the second match
+    let conf = RegexConf {
+        max_steps: 100,
+        ..DEFAULT_REGEX_CONF
+    };
+    let matches = regex.find_first_n_with_conf(&src, 2, conf);
+    assert_eq!(matches.len(), 2);
+    assert_eq!(matches[1].span(), (5, 9));
+
+    let mut all = regex.find_matches_with_conf(&src, conf);
+    assert!(all.by_ref().count() < 1000);
+    assert!(all.aborted());
+
+    assert_eq!(regex.find_first_n(&src, 2).len(), 2);
+    assert!(regex.find_first_n(&src, 0).is_empty());
+}
+
+#[test]
+fn literal_prefix() {
+    let regex = Regex::compile("foob[a-z]r").unwrap();
+    let src = format!("{}foobar fooba foobxr", "foobaz".repeat(1000));
+
+    // The literal prefix isn't used on case insensitive searches,
+    // which give the same results for a lowercase input
+    let conf = |max_steps, case_sensitive| RegexConf {
+        max_steps,
+        case_sensitive,
+        ..DEFAULT_REGEX_CONF
+    };
+    let spans = |conf| {
+        let mut matcher = regex.find_matches_with_conf(&src, conf);
+        let spans: Vec<_> = matcher.by_ref().map(|m| m.span()).collect();
+        (spans, matcher.aborted())
+    };
+
+    let expected = [(6000, 6006), (6013, 6019)];
+    assert_eq!(spans(conf(0, true)), (expected.to_vec(), false));
+    assert_eq!(spans(conf(0, false)), (expected.to_vec(), false));
+
+    // Skipping to the prefix saves the attempts at the other positions
+    assert_eq!(spans(conf(8000, true)), (expected.to_vec(), false));
+    assert!(spans(conf(8000, false)).1);
+
+    template!("añb", &["xxañb", "ñañb"], &["añ", "aña", "ñ"]);
+
+    let regex = Regex::compile("ERROR.*").unwrap();
+    let log = "INFO ñ ok\n".repeat(1000) + "ERROR bad\nINFO ERR\nERROR";
+    let spans: Vec<_> = regex.find_matches(&log).map(|m| m.span()).collect();
+    assert_eq!(spans, [(11000, 11009), (11019, 11024)]);
+}
+
+#[test]
+fn group_spans() {
+    let conf = RegexConf {
+        track_group_spans: true,
+        ..DEFAULT_REGEX_CONF
+    };
+
+    let regex = Regex::compile("(ab)(cd)").unwrap();
+    let mut matcher = regex.find_matches_with_conf("xabcd abcd", conf);
+    assert_eq!(matcher.group_spans(), &[]);
+    assert_eq!(matcher.next().unwrap().span(), (1, 5));
+    assert_eq!(matcher.group_spans(), &[Some((1, 3)), Some((3, 5))]);
+    assert_eq!(matcher.by_ref().count(), 1);
+    assert_eq!(matcher.group_spans(), &[Some((6, 8)), Some((8, 10))]);
+
+    // Groups keep their span from the last match they participated in
+    let regex = Regex::compile("(a)|(b)|(c)").unwrap();
+    let mut matcher = regex.find_matches_with_conf("ab_añ_a", conf);
+    matcher.by_ref().for_each(drop);
+    assert_eq!(matcher.group_spans(), &[Some((7, 8)), Some((1, 2)), None]);
+
+    let mut matcher = regex.find_matches("ab");
+    matcher.by_ref().for_each(drop);
+    assert!(matcher.group_spans().is_empty());
+}
+
+#[test]
+fn rfind() {
+    for (pattern, src) in [
+        ("aa", "aaaaa"),
+        ("b+", "abbcbb"),
+        ("a*", "baab"),
+        ("(x)|(y)", "xyx_y"),
+        ("^a", "aa"),
+        ("z", "abc"),
+        ("", ""),
+    ] {
+        let regex = Regex::compile(pattern).unwrap();
+        let last = regex.find_matches(src).last();
+        let rfind = regex.rfind(src);
+        assert_eq!(
+            rfind.as_ref().map(|m| m.span()),
+            last.as_ref().map(|m| m.span())
+        );
+        assert_eq!(
+            rfind.as_ref().map(|m| m.groups()),
+            last.as_ref().map(|m| m.groups())
+        );
+    }
+
+    let regex = Regex::compile("(x)|(y)").unwrap();
+    assert_eq!(regex.rfind("xyx_y").unwrap().groups(), [None, Some("y")]);
+}
+
+#[test]
+fn overlapping() {
+    let conf = RegexConf {
+        overlapping: true,
+        ..DEFAULT_REGEX_CONF
+    };
+    let spans = |pattern: &str, src: &str| -> Vec<(usize, usize)> {
+        let regex = Regex::compile(pattern).unwrap();
+        regex
+            .find_matches_with_conf(src, conf)
+            .map(|m| m.span())
+            .collect()
+    };
+
+    assert_eq!(spans("aa", "aaaa"), [(0, 2), (1, 3), (2, 4)]);
+    assert_eq!(spans("[ACGT]{3}", "ACGTA"), [(0, 3), (1, 4), (2, 5)]);
+    assert_eq!(spans("a*", "aab"), [(0, 2), (1, 2), (2, 2), (3, 3)]);
+    assert_eq!(spans("ññ", "ñññ"), [(0, 4), (2, 6)]);
+    assert_eq!(spans("^a", "aa"), [(0, 1)]);
+
+    let regex = Regex::compile("aa").unwrap();
+    assert_eq!(regex.match_count("aaaa"), 2);
+    assert_eq!(regex.find_matches_with_conf("aaaa", conf).count(), 3);
+}
+
+#[test]
+fn alphabet() {
+    let abc = ['a', 'b', 'c'];
+    let regex = Regex::compile_with_alphabet("x.y", &abc).unwrap();
+    for s in ["xay", "xby", "xcy"] {
+        assert!(regex.test(s), "{s}");
+    }
+    for s in ["xdy", "x y", "x\ny", "xy"] {
+        assert!(!regex.test(s), "{s}");
+    }
+
+    for (pattern, must_pass, must_fail) in [
+        ("^[^a]+$", &["bc", "cb"][..], &["ab", "bd", "d"][..]),
+        ("^\\W$", &[], &["a", "-"]),
+        ("^\\D\\S$", &["ab"], &["1a", "a ", "ad"]),
+        ("^[a-z]+$", &["abd", "xyz"], &["ab1"]),
+    ] {
+        let regex = Regex::compile_with_alphabet(pattern, &abc).unwrap();
+        for s in must_pass {
+            assert!(regex.test(s), "{pattern} should've matched {s}");
+        }
+        for s in must_fail {
+            assert!(!regex.test(s), "{pattern} shouldn't have matched {s}");
+        }
+    }
+
+    let conf = RegexConf {
+        case_sensitive: false,
+        ..DEFAULT_REGEX_CONF
+    };
+    let regex = Regex::compile_with_alphabet("^.$", &['A']).unwrap();
+    assert!(regex.test_with_conf("a", conf));
+    assert!(!regex.test("a"));
+
+    assert_ne!(regex, Regex::compile("^.$").unwrap());
+}
+
+#[test]
+fn find_longest() {
+    let longest = |pattern: &str, src: &str| {
+        let regex = Regex::compile(pattern).unwrap();
+        regex.find_longest(src).map(|m| m.span())
+    };
+
+    assert_eq!(longest(".*?b", "aabaab"), Some((0, 6)));
+    assert_eq!(longest(".*?b", "aabaabc"), Some((0, 6)));
+    assert_eq!(longest("a+?", "baaab"), Some((1, 4)));
+    assert_eq!(longest("(ab|a)(bc)??", "xabc"), Some((1, 4)));
+    assert_eq!(longest("añ*?", "xaññ"), Some((1, 6)));
+    assert_eq!(longest("a*?$", "baa"), Some((1, 3)));
+    assert_eq!(longest("b", "abab"), Some((1, 2)));
+    assert_eq!(longest("c", "abab"), None);
+
+    let regex = Regex::compile("(a+?)(a*?)").unwrap();
+    let m = regex.find_longest("aaa").unwrap();
+    assert_eq!(m.slice(), "aaa");
+    assert_eq!(m.groups(), [Some("a"), Some("aa")]);
+}
+
+#[test]
+fn exact_count() {
+    template!("^a{4}$", &["aaaa"], &["aaa", "aaaaa"]);
+    template!("^(ab){2}c$", &["ababc"], &["abc", "abababc", "abac"]);
+    template!(
+        "^\\d{4}-\\d{2}$",
+        &["2024-01", "1999-12"],
+        &["24-01", "2024-1", "20245-01"]
+    );
+    template!("^[ñx]{3}$", &["ñxñ", "xxx"], &["ñx", "ñxñx"]);
+
+    let regex = Regex::compile("a{4}").unwrap();
+    assert_eq!(regex.to_pattern(), "a{4}");
+}
+
+#[test]
+fn range_loop_bounds() {
+    template!("^a{2,3}$", &["aa", "aaa"], &["a", "aaaa", "aaaaa"]);
+    template!("^a{2,3}ab$", &["aaab", "aaaab"], &["aab", "aaaaab"]);
+    template!("a{2,3}b", &["aab", "aaab", "xaaaab"], &["ab", "a_ab"]);
+    template!("^(ab){1,2}ab$", &["abab", "ababab"], &["ab", "abababab"]);
+    template!("^a{,2}a$", &["a", "aa", "aaa"], &["aaaa"]);
+    template!("^(a*){2,}b$", &["b", "aab"], &["aac"]);
+
+    let regex = Regex::compile("a{2,3}").unwrap();
+    let spans: Vec<_> = regex.find_matches("aaaaaaa").map(|m| m.span()).collect();
+    assert_eq!(spans, [(0, 3), (3, 6)]);
+}
+
+#[test]
+fn builder() {
+    let b = RegexBuilder::new;
+
+    let regex = b()
+        .literal("a")
+        .group(b().alt([b().literal("b"), b().literal("c")]))
+        .build()
+        .unwrap();
+    assert!(regex.test("ab"));
+    assert!(regex.test("ac"));
+    assert!(!regex.test("ad"));
+    assert_eq!(regex.find("xac").unwrap().groups(), [Some("c")]);
+    assert_eq!(regex, Regex::compile("a(b|c)").unwrap());
+
+    // Groups are numbered in the order they're opened
+    let regex = b()
+        .start()
+        .group(b().group(b().range('a', 'z').plus()).literal("-"))
+        .backref(2)
+        .end()
+        .build()
+        .unwrap();
+    assert_eq!(regex.as_str(), "^(([a-z]+)-)\\2$");
+    assert_eq!(regex, Regex::compile(regex.as_str()).unwrap());
+    assert!(regex.test("ab-ab"));
+    assert!(!regex.test("ab-cd"));
+
+    let regex = b()
+        .digit()
+        .repeat(Some(2), Some(3))
+        .any()
+        .star()
+        .lazy()
+        .class("xy")
+        .opt()
+        .build()
+        .unwrap();
+    assert_eq!(regex.as_str(), "\\d{2,3}.*?[xy]?");
+    assert_eq!(regex.find("a1234").unwrap().slice(), "123");
+
+    // The generated pattern groups what the expression groups
+    let ab = || b().alt([b().literal("a"), b().literal("b")]);
+    for (builder, pattern) in [
+        (
+            b().literal("x").alt([b().literal("a"), b().literal("b")]),
+            "x(?:a|b)",
+        ),
+        (ab().star(), "(?:a|b)*"),
+        (ab().literal("c").opt(), "(?:a|b)c?"),
+        (b().literal("a").star().opt(), "(?:a*)?"),
+        (b().group(ab()).repeat(Some(2), None), "(a|b){2,}"),
+    ] {
+        let regex = builder.build().unwrap();
+        assert_eq!(regex.as_str(), pattern);
+        assert_eq!(Regex::compile(regex.as_str()).unwrap(), regex);
+    }
+
+    for (builder, msg) in [
+        (b().star(), "Expected pattern before '*'"),
+        (b().group(b().plus()), "Expected pattern before '+'"),
+        (b().literal("a").lazy(), "Expected '*' or '+' before lazy"),
+        (b().backref(0), "Backreference to unknown group 0"),
+        (
+            b().backref(1).group(b().literal("a")),
+            "Forward/self reference to group 1 not allowed",
+        ),
+        (
+            b().group(b().literal("a").backref(1)),
+            "Forward/self reference to group 1 not allowed",
+        ),
+    ] {
+        match builder.build() {
+            Ok(_) => panic!("{msg}"),
+            Err(err) => assert_eq!(err.to_string(), msg),
+        }
+    }
+}
+
+#[test]
+fn last_error() {
+    let regex = Regex::compile("(a+)+b").unwrap();
+    let conf = RegexConf {
+        max_steps: 200,
+        ..DEFAULT_REGEX_CONF
+    };
+
+    let mut matcher = regex.find_matches_with_conf("aaaaaaaaaaaaaaaaaaaaaaaac", conf);
+    assert!(matcher.last_error().is_none());
+    assert!(!matcher.finished_cleanly());
+    assert!(matcher.next().is_none());
+    assert_eq!(
+        matcher.last_error().unwrap().to_string(),
+        "Exceeded the limit of 200 steps"
+    );
+    assert!(!matcher.finished_cleanly());
+
+    let mut matcher = regex.find_matches_with_conf("aab_ab", conf);
+    assert_eq!(matcher.by_ref().count(), 2);
+    assert!(matcher.last_error().is_none());
+    assert!(matcher.finished_cleanly());
+
+    let deadline = std::time::Instant::now();
+    let src = "a".repeat(10_000);
+    let mut matcher = regex.find_matches_with_deadline(&src, deadline);
+    assert!(matcher.next().is_none());
+    assert_eq!(
+        matcher.last_error().unwrap().to_string(),
+        "Exceeded the deadline"
+    );
+}
+
+#[test]
+fn range_loop_backtrack() {
+    template!("a{1,3}ab", &["aaab", "aab", "xaaaab"], &["ab", "aaa"]);
+    template!("^x{0,2}xy$", &["xy", "xxy", "xxxy"], &["y", "xxxxy"]);
+    template!("^(ab){1,3}ab$", &["abab", "ababab", "abababab"], &["ab"]);
+    template!("^[a-c]{2,}c$", &["abc", "cccc"], &["ac", "abd"]);
+
+    let regex = Regex::compile("(a{1,3})(a+)").unwrap();
+    let m = regex.find("aaaa").unwrap();
+    assert_eq!(m.groups(), [Some("aaa"), Some("a")]);
+}
+
+#[test]
+fn case_insensitive_char() {
+    let conf = RegexConf {
+        case_sensitive: false,
+        ..DEFAULT_REGEX_CONF
+    };
+    template_with_conf!("abc", conf, &["ABC", "abc", "aBc"], &["AB", "abd"]);
+    template_with_conf!("ABC", conf, &["ABC", "abc", "xAbC"], &["AB", "abd"]);
+    template_with_conf!("^[A-C]Ñ$", conf, &["bñ", "CÑ"], &["dñ"]);
+    template_with_conf!("^(A)\\1$", conf, &["aA", "Aa"], &["ab"]);
+    template!("ABC", &["ABC"], &["abc", "aBc"]);
+    template!("(?i)ÑAND", &["ñand", "ÑaNd"], &["nand"]);
+}
+
+#[test]
+fn empty_branch_participation() {
+    let regex = Regex::compile("^((a)|(b)|())$").unwrap();
+    let groups = |src| regex.find(src).unwrap().groups().to_vec();
+
+    assert_eq!(groups(""), [Some(""), None, None, Some("")]);
+    assert_eq!(groups("a"), [Some("a"), Some("a"), None, None]);
+    assert_eq!(groups("b"), [Some("b"), None, Some("b"), None]);
+    assert!(regex.find("ab").is_none());
+
+    // Alternations inside loops
+    template!("^(a|b)*$", &["", "ab", "bba"], &["abc"]);
+    let regex = Regex::compile("^((a)|(b))*$").unwrap();
+    assert_eq!(
+        regex.find("ab").unwrap().groups(),
+        [Some("b"), Some("a"), Some("b")]
+    );
+
+    // A branch that matched in one iteration can be changed for the next ones
+    template!("^(a|ab)+$", &["abab", "aab", "a"], &["abb", "b"]);
+    template!("^(b*?|.)+$", &["ac", "bbc"], &[]);
+    let span =
+        |pattern: &str, src: &str| Regex::compile(pattern).unwrap().find(src).map(|m| m.span());
+    assert_eq!(span("^(a?|b)+c", "baac"), Some((0, 4)));
+    assert_eq!(span("(a|ac+)+$", "abbaca"), Some((3, 6)));
+}
+
+#[test]
+fn split_with_delimiters() {
+    let regex = Regex::compile(",").unwrap();
+    let pieces = regex.split_with_delimiters("a,,b");
+    assert!(matches!(
+        pieces.as_slice(),
+        [
+            Piece::Text("a"),
+            Piece::Delim(_),
+            Piece::Text(""),
+            Piece::Delim(_),
+            Piece::Text("b"),
+        ]
+    ));
+
+    for src in ["a,,b", "", ",", "abc", ",a,"] {
+        let pieces = regex.split_with_delimiters(src);
+        assert_eq!(pieces.iter().map(Piece::as_str).collect::<String>(), src);
+    }
+
+    let regex = Regex::compile("").unwrap();
+    let joined: String = regex
+        .split_with_delimiters("añb")
+        .iter()
+        .map(Piece::as_str)
+        .collect();
+    assert_eq!(joined, "añb");
+}
+
+#[test]
+fn case_folding() {
+    let conf = RegexConf {
+        case_sensitive: false,
+        ..DEFAULT_REGEX_CONF
+    };
+    template_with_conf!("^ß$", conf, &["ß", "ẞ"], &["s", "ss"]);
+    template_with_conf!("^ẞ$", conf, &["ß", "ẞ"], &["ss"]);
+    // İ lowercases to two chars, so it only matches itself
+    template_with_conf!("^İ$", conf, &["İ"], &["i", "I"]);
+    template_with_conf!("^i$", conf, &["i", "I"], &["İ", "ı"]);
+
+    #[cfg(feature = "unicode-case")]
+    {
+        template_with_conf!("^σ$", conf, &["σ", "Σ", "ς"], &["s"]);
+        template_with_conf!("^s$", conf, &["s", "S", "ſ"], &["ß"]);
+        template_with_conf!("^\u{13A0}$", conf, &["\u{13A0}", "\u{AB70}"], &["a"]);
+    }
+}
+
+#[test]
+fn ascii_case_insensitive() {
+    let conf = RegexConf {
+        case_sensitive: false,
+        ascii_case_insensitive: true,
+        ..DEFAULT_REGEX_CONF
+    };
+    template_with_conf!("^abc$", conf, &["ABC", "aBc"], &["ab"]);
+    template_with_conf!("^[a-c]ñ$", conf, &["Bñ", "cñ"], &["bÑ", "dñ"]);
+    template_with_conf!("^(a)\\1$", conf, &["aA", "Aa"], &["ab"]);
+    template_with_conf!("^Ñ$", conf, &["Ñ"], &["ñ"]);
+
+    // Without case insensitivity, it has no effect
+    let conf = RegexConf {
+        ascii_case_insensitive: true,
+        ..DEFAULT_REGEX_CONF
+    };
+    template_with_conf!("^abc$", conf, &["abc"], &["ABC"]);
+    template_with_conf!("(?i)^abc$", conf, &["ABC"], &["ABD"]);
+    template_with_conf!("(?i)^ñ$", conf, &["ñ"], &["Ñ"]);
+}
+
+#[test]
+fn captures_on_backtrack() {
+    let check = |r: &str, src: &str, expected: &[Option<&str>]| {
+        let regex = Regex::compile(r).unwrap();
+        assert_eq!(regex.find(src).unwrap().groups(), expected);
+    };
+    check(
+        "^(a|ab)(c|bcd)(d*)$",
+        "abcd",
+        &[Some("a"), Some("bcd"), Some("")],
+    );
+    check("((a)|b)*c", "abac", &[Some("a"), Some("a")]);
+    check("(a)*(a)", "aaa", &[Some("a"), Some("a")]);
+
+    let src = "x".repeat(10_000) + "=";
+    check("(\\w)+=", &src, &[Some("x")]);
+}
+
+#[test]
+fn empty_loop_iterations() {
+    let regex = Regex::compile("(a*)*b").unwrap();
+    assert_eq!(regex.find("b").unwrap().span(), (0, 1));
+    let m = regex.find("aaab").unwrap();
+    assert_eq!(m.span(), (0, 4));
+    // The iteration that matched nothing ended the loop, like in PCRE
+    assert_eq!(m.groups(), [Some("")]);
+    assert!(regex.find("aaa").is_none());
+
+    template!("^(a*)+b$", &["b", "aab"], &["aa"]);
+    template!("^(a*?)*b$", &["b", "aab"], &["aa"]);
+    template!("^(a|b|())*c$", &["c", "abc"], &["ab", "abd"]);
+    template!("^(a|b|())*?c$", &["c", "abc"], &["ab", "abd"]);
+}
+
+#[test]
+fn opt_backtracking() {
+    let regex = Regex::compile("((a)?a)").unwrap();
+    let m = regex.find("a").unwrap();
+    assert_eq!(m.span(), (0, 1));
+    assert_eq!(m.groups(), [Some("a"), None]);
+    assert_eq!(regex.find("aa").unwrap().groups(), [Some("aa"), Some("a")]);
+
+    // Inside loops, the optional is taken if the next iteration needs it
+    template!("^(a?b)*$", &["", "abab", "bab", "abb"], &["a", "aab"]);
+    template!("^(a?b)+$", &["abab", "bab"], &["", "aab"]);
+    template!("^(a?b){1,3}$", &["abab", "babab"], &["", "abababab"]);
+    template!("^(x(a)?b)*$", &["xabxb", "xbxab"], &["xaab"]);
+
+    // Skipping the optional in one iteration can be what lets a later one match
+    let span =
+        |pattern: &str, src: &str| Regex::compile(pattern).unwrap().find(src).map(|m| m.span());
+    assert_eq!(span("^(.?c)*$", "cbc"), Some((0, 3)));
+    assert_eq!(span("^(.a.?)*$", "baba"), Some((0, 4)));
+    assert_eq!(span("^(a?.)*$", "aab"), Some((0, 3)));
+}
+
+#[test]
+fn unicode_classes() {
+    template!("^[α-ω]+$", &["αβω", "λ"], &["Α", "a", "αa"]);
+    template!("^[😀-😂]$", &["😀", "😁", "😂"], &["😃", "a"]);
+    template!("^[a-zá-ú]+$", &["canción", "é"], &["ü", "Á"]);
+
+    // Negated classes consume a single char, whatever its length
+    template!("^[^a]$", &["ñ", "😀", "Α"], &["a", "ññ", ""]);
+    template!("^[^a-z]b$", &["ñb", "😀b"], &["ab", "ñ😀b"]);
+    template!("^[^😀]x$", &["éx", "😁x"], &["😀x", "x"]);
+    template!("^.[^ñ].$", &["😀a😀", "ééé"], &["añb", "😀😀"]);
+
+    let regex = Regex::compile("[^ñ]").unwrap();
+    let spans: Vec<_> = regex.find_matches("é😀ñx").map(|m| m.span()).collect();
+    assert_eq!(spans, [(0, 2), (2, 6), (8, 9)]);
+
+    let conf = RegexConf {
+        case_sensitive: false,
+        ..DEFAULT_REGEX_CONF
+    };
+    template_with_conf!("^[α-ω]$", conf, &["Λ", "λ"], &["a"]);
+}
+
+#[test]
+fn class_shorthands() {
+    template!("^[\\w\\s]+$", &["ab c", "a_1 \n"], &["a-b", ""]);
+    template!("^[^\\d]$", &["a", " ", "ñ"], &["1", ""]);
+    template!("^[\\d.]+$", &["1.5", "..."], &["1,5"]);
+    template!("^[a\\D]$", &["a", "b"], &["1"]);
+    template!("^[^\\s\\d]+$", &["abc"], &["a b", "a1"]);
+    template!("^[\\d-z]+$", &["1-z"], &["a"]);
+
+    let regex = Regex::compile("[^\\w\\s]").unwrap();
+    assert_eq!(regex.to_pattern(), "[^\\w\\s]");
+    assert_eq!(Regex::compile(regex.to_pattern()).unwrap(), regex);
+}
+
+#[test]
+fn input_anchors() {
+    let conf = RegexConf {
+        multiline: true,
+        ..DEFAULT_REGEX_CONF
+    };
+    template_with_conf!("^b", conf, &["b", "a\nb"], &["ab"]);
+    template_with_conf!("\\Ab", conf, &["b", "bc"], &["a\nb"]);
+    template_with_conf!("a$", conf, &["a", "a\nb"], &["ab"]);
+    template_with_conf!("a\\z", conf, &["a", "b\na"], &["a\nb", "a\n"]);
+    template_with_conf!("a\\Z", conf, &["a", "b\na", "a\n"], &["a\nb", "a\n\n"]);
+
+    template!("\\A\\d+\\z", &["123"], &["123\n", "a1", "1a"]);
+    template!("\\A\\d+\\Z", &["123", "123\n"], &["123\n\n", "a1"]);
+
+    let regex = Regex::compile("\\Aa|b\\z").unwrap();
+    assert_eq!(regex.to_pattern(), "\\Aa|b\\z");
+    let spans: Vec<_> = regex.find_matches("aab").map(|m| m.span()).collect();
+    assert_eq!(spans, [(0, 1), (2, 3)]);
+}
+
+#[test]
+fn literal_braces() {
+    template!("^a{$", &["a{"], &["a"]);
+    template!("^a{foo}$", &["a{foo}"], &["afoo"]);
+    template!("^{}$", &["{}"], &[""]);
+    template!("^a{1,2,3}$", &["a{1,2,3}"], &["a"]);
+    template!("\\{\"k\": \\d+}", &["{\"k\": 12}"], &["{\"k\": }"]);
+    template!("^{$", &["{"], &[""]);
+
+    // Well formed quantifiers are still strict
+    template!("^a{2}$", &["aa"], &["a{2}"]);
+    template!("^a{,}$", &["", "aaa"], &["a{,}"]);
+    assert!(Regex::compile("{2}").is_err());
+    assert!(Regex::compile("a{99999999999999999999999}").is_err());
+
+    let regex = Regex::compile("a{b").unwrap();
+    assert_eq!(Regex::compile(regex.to_pattern()).unwrap(), regex);
+}
+
+#[test]
+fn size_hint() {
+    let regex = Regex::compile("a*").unwrap();
+    let mut matcher = regex.find_matches("baa");
+    assert_eq!(matcher.size_hint(), (0, Some(4)));
+    assert_eq!(matcher.next().unwrap().span(), (0, 0));
+    assert_eq!(matcher.remaining(), "aa");
+    assert_eq!(matcher.size_hint(), (0, Some(3)));
+    assert_eq!(matcher.next().unwrap().span(), (1, 3));
+    assert_eq!(matcher.remaining(), "");
+    assert_eq!(matcher.size_hint(), (0, Some(1)));
+    assert_eq!(matcher.next().unwrap().span(), (3, 3));
+    assert!(matcher.next().is_none());
+    assert_eq!(matcher.size_hint(), (0, Some(0)));
+
+    let regex = Regex::compile("a?").unwrap();
+    for src in ["", "aaa", "abab", "ñaña"] {
+        let matcher = regex.find_matches(src);
+        let (_, upper) = matcher.size_hint();
+        assert!(matcher.count() <= upper.unwrap());
+    }
+}
+
+#[test]
+fn captures() {
+    let regex = Regex::compile("(?<year>\\d{4})-(?<month>\\d\\d)(-(\\d\\d))?").unwrap();
+    let caps = regex.captures("on 2024-05!").unwrap();
+    assert_eq!(caps.len(), 5);
+    assert_eq!(caps.get(0), Some("2024-05"));
+    assert_eq!(caps.name("year"), Some("2024"));
+    assert_eq!(caps.name("month"), Some("05"));
+    assert_eq!(caps.name("day"), None);
+    assert_eq!(caps.get(3), None);
+    assert_eq!(caps.get(4), None);
+    assert_eq!(caps.get(5), None);
+    assert_eq!(caps.get_match().span(), (3, 10));
+
+    let caps = regex.captures("2024-05-17").unwrap();
+    assert_eq!(caps.get(4), Some("17"));
+    assert!(regex.captures("2024").is_none());
+}
+
+#[test]
+fn captures_iter() {
+    let regex = Regex::compile("(?<tag>[a-z]+)(:(?<n>\\d+))?").unwrap();
+    let caps: Vec<_> = regex.captures_iter("x:1 y z:3").collect();
+    assert_eq!(caps.len(), 3);
+    let fields: Vec<_> = caps
+        .iter()
+        .map(|c| (c.name("tag").unwrap(), c.name("n")))
+        .collect();
+    assert_eq!(fields, [("x", Some("1")), ("y", None), ("z", Some("3"))]);
+    assert_eq!(caps[2].get_match().span(), (6, 9));
+
+    assert_eq!(regex.captures_iter("123").count(), 0);
+}
+
+#[test]
+fn conf_setters() {
+    const CONF: RegexConf = RegexConf::new().case_sensitive(false).max_steps(100);
+    let conf = CONF;
+    assert!(!conf.case_sensitive);
+    assert_eq!(conf.max_steps, 100);
+    assert!(!conf.multiline);
+
+    let regex = Regex::compile("^b$").unwrap();
+    assert!(regex.test_with_conf("a\nB", CONF.multiline(true)));
+    assert!(!regex.test_with_conf("a\nB", CONF));
+}
+
+#[test]
+fn anchored_empty_matches() {
+    assert_eq!(spans("", "AD"), [(0, 0), (1, 1), (2, 2)]);
+    assert_eq!(spans("^$", "AD"), []);
+    assert_eq!(spans("^$", ""), [(0, 0)]);
+    assert_eq!(spans("^", "AD"), [(0, 0)]);
+    assert_eq!(spans("$", "AD"), [(2, 2)]);
+    assert_eq!(spans("^$", "a\n\nb"), []);
+
+    assert_eq!(spans("(?m)^$", "AD"), []);
+    assert_eq!(spans("(?m)^$", "a\n\nb"), [(2, 2)]);
+    assert_eq!(spans("(?m)^$", "\n"), [(0, 0), (1, 1)]);
+    assert_eq!(spans("(?m)^", "a\nb"), [(0, 0), (2, 2)]);
+    assert_eq!(spans("(?m)$", "a\nb"), [(1, 1), (3, 3)]);
+    assert_eq!(spans("(?m)^$", "é\n\né"), [(3, 3)]);
+}
+
+#[test]
+fn display_with_groups() {
+    let regex = Regex::compile("(a)|(b)").unwrap();
+    let m = regex.find("b").unwrap();
+    assert_eq!(format!("{m}"), "[0,1]: \"b\"");
+    assert_eq!(
+        format!("{m:#}"),
+        "[0,1]: \"b\"\n  Captures:\n  1) <unmatched>\n  2) \"b\""
+    );
+
+    let regex = Regex::compile("ab").unwrap();
+    let m = regex.find("ab").unwrap();
+    assert_eq!(format!("{m:#}"), format!("{m}"));
+}
+
+#[test]
+fn is_match_at() {
+    let regex = Regex::compile("\\bab+").unwrap();
+    assert_eq!(regex.is_match_at("ab abbb", 0).unwrap(), Some(2));
+    assert_eq!(regex.is_match_at("ab abbb", 3).unwrap(), Some(7));
+    assert_eq!(regex.is_match_at("ab abbb", 2).unwrap(), None);
+    assert_eq!(regex.is_match_at("xab", 1).unwrap(), None);
+
+    let regex = Regex::compile("^ñ").unwrap();
+    assert_eq!(regex.is_match_at("ññ", 0).unwrap(), Some(2));
+    assert_eq!(regex.is_match_at("ññ", 2).unwrap(), None);
+    for offset in [1, 5] {
+        match regex.is_match_at("ññ", offset) {
+            Ok(_) => panic!("{offset} should've failed"),
+            Err(err) => assert_eq!(
+                err.to_string(),
+                format!("Offset {offset} is not on a char boundary")
+            ),
+        }
+    }
+
+    let regex = Regex::compile("a*").unwrap();
+    assert_eq!(regex.is_match_at("baa", 3).unwrap(), Some(3));
+}
+
+#[test]
+#[cfg(feature = "unicode-segmentation")]
+fn grapheme_dot() {
+    let conf = RegexConf {
+        grapheme_dot: true,
+        ..DEFAULT_REGEX_CONF
+    };
+    let find = |pattern: &str, src: &'static str, conf| {
+        let regex = Regex::compile(pattern).unwrap();
+        regex.find_with_conf(src, conf).map(|m| m.slice().len())
+    };
+
+    // 'e' followed by a combining acute accent
+    let src = "e\u{301}x";
+    assert_eq!(find("^.x$", src, DEFAULT_REGEX_CONF), None);
+    assert_eq!(find("^.x$", src, conf), Some(src.len()));
+
+    // A family emoji, made of four emojis joined by ZWJ
+    let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}";
+    assert_eq!(find("^.$", family, conf), Some(family.len()));
+    assert_eq!(
+        find("^.{7}$", family, DEFAULT_REGEX_CONF),
+        Some(family.len())
+    );
+    let regex = Regex::compile(".").unwrap();
+    assert_eq!(regex.find_matches_with_conf("a\u{301}bc", conf).count(), 3);
+
+    assert_eq!(find("^a.b$", "a\r\nb", conf), None);
+    let dot_all = RegexConf {
+        dot_all: true,
+        ..conf
+    };
+    assert_eq!(find("^a.b$", "a\r\nb", dot_all), Some(4));
+}
+
+#[test]
+fn shared_between_threads() {
+    use std::sync::Arc;
+    use std::thread;
+
+    let regex = Arc::new(Regex::compile("(a+)(b|c)").unwrap());
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let regex = Arc::clone(&regex);
+            thread::spawn(move || {
+                let src = "aab ac ".repeat(i + 1);
+                regex
+                    .find_matches(&src)
+                    .map(|m| m.groups()[1].unwrap().to_string())
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    for (i, handle) in handles.into_iter().enumerate() {
+        assert_eq!(handle.join().unwrap(), ["b", "c"].repeat(i + 1));
+    }
+}
+
+#[test]
+fn shortest_match() {
+    let regex = Regex::compile("(a|ab)(c|bcd)").unwrap();
+    assert_eq!(regex.shortest_match("xabcd"), Some(5));
+    assert_eq!(regex.find("xabcd").unwrap().span().1, 5);
+
+    let regex = Regex::compile("\\bfn\\b").unwrap();
+    assert_eq!(regex.shortest_match("fnx fn"), Some(6));
+    assert_eq!(regex.shortest_match("fnx"), None);
+    assert_eq!(Regex::compile("").unwrap().shortest_match("abc"), Some(0));
+}
+
+#[test]
+fn longest_mode() {
+    let conf = RegexConf::new().longest(true);
+    let spans = |pattern: &str, src: &str, conf| -> Vec<(usize, usize)> {
+        let regex = Regex::compile(pattern).unwrap();
+        regex
+            .find_matches_with_conf(src, conf)
+            .map(|m| m.span())
+            .collect()
+    };
+
+    assert_eq!(spans("a|ab", "abab", DEFAULT_REGEX_CONF), [(0, 1), (2, 3)]);
+    assert_eq!(spans("a|ab", "abab", conf), [(0, 2), (2, 4)]);
+    assert_eq!(spans("(a|ab)(c|bcd)", "abcd", conf), [(0, 4)]);
+    assert_eq!(spans("a+?", "baa b", conf), [(1, 3)]);
+    assert_eq!(spans("x*", "axx", conf), [(0, 0), (1, 3), (3, 3)]);
+    assert_eq!(spans("[a-z]+|[a-z]+[0-9]", "ab1 c", conf), [(0, 3), (4, 5)]);
+
+    let regex = Regex::compile("(a|ab)(b*)").unwrap();
+    let m = regex.find_with_conf("abb", conf).unwrap();
+    assert_eq!(m.span(), (0, 3));
+    assert_eq!(m.groups(), [Some("a"), Some("bb")]);
+
+    // A fixed end is kept
+    let regex = Regex::compile("a|ab").unwrap();
+    let m = regex
+        .find_matches_with_conf("ab", conf)
+        .ending_at(1)
+        .match_at(0);
+    assert_eq!(m.unwrap().span(), (0, 1));
+
+    // Each match is extended in one pass, not once per end it could have
+    let src = "ab".repeat(6000);
+    let conf = conf.max_steps(200_000);
+    let mut matcher = regex.find_matches_with_conf(&src, conf);
+    assert_eq!(matcher.by_ref().count(), 6000);
+    assert!(!matcher.aborted());
+}
+
+#[test]
+fn error_kinds() {
+    use crate::RegexErrorKind as K;
+
+    for (pattern, kind) in [
+        ("*a", K::UnexpectedQuantifier),
+        ("(+a)", K::UnexpectedQuantifier),
+        ("(ab", K::UnterminatedGroup),
+        ("((a)", K::UnterminatedGroup),
+        ("a)", K::UnopenedGroup),
+        ("(a))", K::UnopenedGroup),
+        ("[ab", K::UnterminatedClass),
+        ("[a-\\d]", K::BadRange),
+        ("a{99999999999999999999}", K::BadRepetition),
+        ("(a)\\2", K::UnknownBackreference),
+        ("\\k<x>(?<y>a)", K::UnknownBackreference),
+        ("\\1(a)", K::ForwardReference),
+        ("ab\\", K::BadEscape),
+        ("(?<x", K::BadGroupName),
+        ("(?q)", K::UnknownFlag),
+    ] {
+        match Regex::compile(pattern) {
+            Ok(_) => panic!("{pattern} should've failed"),
+            Err(err) => assert_eq!(err.kind(), kind, "{pattern}: {err}"),
+        }
+    }
+
+    let err = Regex::compile("a)").unwrap_err();
+    assert_eq!(err.to_string(), "Unexpected ')' at byte 1");
+    let err = Regex::compile("(a").unwrap_err();
+    assert_eq!(err.to_string(), "Expected closing ')'");
+
+    let err = RegexBuilder::new().star().build().unwrap_err();
+    assert_eq!(err.kind(), K::UnexpectedQuantifier);
+    let err = Regex::compile("a")
+        .unwrap()
+        .is_match_at("a", 2)
+        .unwrap_err();
+    assert_eq!(err.kind(), K::BadOffset);
+
+    let regex = Regex::compile("(a+)+b").unwrap();
+    let conf = RegexConf::new().max_steps(100);
+    let mut matcher = regex.find_matches_with_conf("aaaaaaaaaaaaaaaaaaaaaaaaac", conf);
+    assert!(matcher.next().is_none());
+    assert_eq!(matcher.last_error().unwrap().kind(), K::Aborted);
+
+    let err: crate::RegexError = "custom".into();
+    assert_eq!(err.kind(), K::Other);
+}
+
+#[test]
+fn quoted_literals() {
+    template!("^\\Qa.b*\\E$", &["a.b*"], &["axb", "a.bbb"]);
+    template!("^\\Qa.b*\\E+$", &["a.b*", "a.b***"], &["a.b", "a.ba.b*"]);
+    template!("^x\\Q(|)[\\d]\\Ey$", &["x(|)[\\d]y"], &["xy", "x1y"]);
+    template!("^\\Q$^", &["$^"], &["", "$"]);
+    template!("^\\Q\\E$", &[""], &["a"]);
+    template!("^a\\Q\\\\E$", &["a\\"], &["a"]);
+
+    let regex = Regex::compile("(\\Q.\\E)\\1").unwrap();
+    assert_eq!(regex.find("a..").unwrap().span(), (1, 3));
+    assert_eq!(Regex::compile(regex.to_pattern()).unwrap(), regex);
+}
+
+#[test]
+fn escape() {
+    assert_eq!(crate::escape("a.b*c"), "a\\.b\\*c");
+    let regex = Regex::compile(crate::escape("a.b*c")).unwrap();
+    assert!(regex.test("a.b*c"));
+    assert!(!regex.test("axbbc"));
+
+    let text = ".^$*+?()[]{}|\\ñ-";
+    let regex = Regex::compile(format!("^{}$", crate::escape(text))).unwrap();
+    assert!(regex.test(text));
+    assert_eq!(regex.find_matches(text).count(), 1);
+    assert_eq!(crate::escape(""), "");
+}
+
+#[test]
+fn find_matches_at() {
+    let regex = Regex::compile("ñ+").unwrap();
+    let spans = |start| -> Vec<(usize, usize)> {
+        regex
+            .find_matches_at("ññ añ", start)
+            .unwrap()
+            .map(|m| m.span())
+            .collect()
+    };
+    assert_eq!(spans(0), [(0, 4), (6, 8)]);
+    assert_eq!(spans(2), [(2, 4), (6, 8)]);
+    assert_eq!(spans(4), [(6, 8)]);
+    assert_eq!(spans(8), []);
+    for offset in [1, 9] {
+        let err = regex.find_matches_at("ññ añ", offset).unwrap_err();
+        assert_eq!(err.kind(), crate::RegexErrorKind::BadOffset);
+    }
+
+    let regex = Regex::compile("^a|$").unwrap();
+    let spans: Vec<_> = regex
+        .find_matches_at("aa", 1)
+        .unwrap()
+        .map(|m| m.span())
+        .collect();
+    assert_eq!(spans, [(2, 2)]);
+
+    let mut matcher = regex.find_matches("aa");
+    assert_eq!(matcher.next().unwrap().span(), (0, 1));
+    let mut matcher = matcher.starting_at(0).unwrap();
+    assert_eq!(matcher.next().unwrap().span(), (2, 2));
+}
+
+#[test]
+#[cfg(feature = "diagnostics")]
+fn match_stats() {
+    let stats = |pattern: &str, src: &str| {
+        let regex = Regex::compile(pattern).unwrap();
+        let mut matcher = regex.find_matches(src);
+        matcher.by_ref().count();
+        matcher.stats()
+    };
+
+    let simple = stats("ab", "xxab");
+    assert!(simple.steps > 0);
+    assert_eq!(simple.backtracks, 0);
+
+    // The nested loop has more ways to fail
+    let src = "aaaaaaaaaaaac";
+    let nested = stats("(a+)+b", src);
+    let flat = stats("a+b", src);
+    assert!(nested.steps > flat.steps);
+    assert!(nested.backtracks > flat.backtracks);
+    assert!(nested.checkpoints >= nested.backtracks);
+}
+
+#[test]
+fn replace_all() {
+    let regex = Regex::compile("(\\d+)-(\\d+)").unwrap();
+    assert_eq!(regex.replace_all("1-2 34-5", "$2-$1"), "2-1 5-34");
+    assert_eq!(regex.replace_all("1-2", "${1}0"), "10");
+    assert_eq!(regex.replace_all("1-2", "[$0]"), "[1-2]");
+    assert_eq!(regex.replace_all("abc", "$1"), Cow::Borrowed("abc"));
+    // `replace` inserts the replacement as is
+    assert_eq!(regex.replace("1-2", "$2"), "$2");
+
+    for src in ["x1-2y", "1-2 3-4", ""] {
+        assert_eq!(regex.replace_all(src, "_"), regex.replace(src, "_"));
+    }
+}
+
+#[test]
+fn prev_match_end() {
+    assert_eq!(spans("\\G\\w", "ab c"), [(0, 1), (1, 2)]);
+    assert_eq!(spans("\\G\\w+,?", "ab,cd, ef"), [(0, 3), (3, 6)]);
+    assert_eq!(spans("\\Ga", "ba"), []);
+    assert_eq!(spans("a\\G", "aa"), []);
+    assert_eq!(spans("\\G", "ab"), [(0, 0)]);
+    assert_eq!(spans("\\Ga|b", "aaba"), [(0, 1), (1, 2), (2, 3), (3, 4)]);
+    assert_eq!(spans("\\Ga|b", "abca"), [(0, 1), (1, 2)]);
+
+    let regex = Regex::compile("\\G\\d").unwrap();
+    let spans: Vec<_> = regex
+        .find_matches_at("ab12c3", 2)
+        .unwrap()
+        .map(|m| m.span())
+        .collect();
+    assert_eq!(spans, [(2, 3), (3, 4)]);
+    assert_eq!(regex.is_match_at("ab12", 3).unwrap(), Some(4));
+    assert_eq!(regex.to_pattern(), "\\G\\d");
+
+    let mut stream = regex.streaming_matcher();
+    for chunk in ["1", "2", "3x4"] {
+        stream.feed(chunk);
+    }
+    stream.finish();
+    let mut found = Vec::new();
+    while let Some(MatchEvent::Match { span }) = stream.poll() {
+        found.push(span);
+    }
+    assert_eq!(found, [(0, 1), (1, 2), (2, 3)]);
+}
+
+#[test]
+fn conditional() {
+    let regex = Regex::compile("^(\\()?\\d+(?(1)\\))$").unwrap();
+    assert!(regex.test("(12)"));
+    assert!(regex.test("12"));
+    assert!(!regex.test("(12"));
+    assert!(!regex.test("12)"));
+
+    let regex = Regex::compile("(<)?\\w+(?(1)>|;)").unwrap();
+    let found: Vec<_> = regex
+        .find_matches("<a> b; <c; d>")
+        .map(|m| m.slice())
+        .collect();
+    assert_eq!(found, ["<a>", "b;", "c;"]);
+
+    let regex = Regex::compile("^(?<q>\")?\\w+(?(<q>)\")$").unwrap();
+    assert!(regex.test("\"ab\""));
+    assert!(regex.test("ab"));
+    assert!(!regex.test("\"ab"));
+
+    for pattern in ["(a)?(?(1)b|c)", "(a)?(?(1)b)", "(a)?(?(1)|bc)"] {
+        let regex = Regex::compile(pattern).unwrap();
+        assert_eq!(regex.to_pattern(), pattern);
+    }
+
+    use crate::RegexErrorKind as K;
+    for (pattern, kind) in [
+        ("(?(2)a|b)(c)", K::UnknownBackreference),
+        ("(?(0)a)", K::UnknownBackreference),
+        ("(a)(?(1)a|b|c)", K::Other),
+        ("(a)(?(x)a)", K::BadGroupName),
+        ("(a)(?(1)a", K::UnterminatedGroup),
+        ("(a)(?(1", K::UnterminatedGroup),
+    ] {
+        assert_eq!(
+            Regex::compile(pattern).unwrap_err().kind(),
+            kind,
+            "{pattern}"
+        );
+    }
+}
+
+#[test]
+fn no_capture_bookkeeping() {
+    let regex = Regex::compile("[a-c]+d*").unwrap();
+    assert_eq!(regex.capture_count(), 0);
+    let matches: Vec<_> = regex.find_matches("abd x cc").collect();
+    assert_eq!(matches.len(), 2);
+    for m in &matches {
+        assert!(m.groups().is_empty());
+        assert_eq!(m.group(1), None);
+    }
+
+    // Without groups, the decisions are still undone when backtracking
+    let regex = Regex::compile("(?:a|ab)c").unwrap();
+    let conf = RegexConf::new().trace_decisions(true);
+    let m = regex.find_matches_with_conf("abc", conf).next().unwrap();
+    assert_eq!(m.decision_trace(), &[(0, 1)]);
+}
+
+#[test]
+fn full_match() {
+    let cases = [
+        ("a|ab", "ab", true),
+        ("a+?", "aaa", true),
+        ("[0-9]+", "12a", false),
+        ("[0-9]+", "a12", false),
+        ("^a$", "a\n", false),
+        ("(?m)^a$", "a\nb", false),
+        ("a*", "", true),
+        ("\\w+\\.rs", "main.rs", true),
+    ];
+    for (pattern, src, expected) in cases {
+        let regex = Regex::compile(pattern).unwrap();
+        assert_eq!(regex.is_full_match(src), expected, "{pattern} on {src:?}");
+        let anchored = Regex::compile(format!("\\A({pattern})\\z")).unwrap();
+        assert_eq!(anchored.test(src), expected, "{pattern} on {src:?}");
+    }
+}
+
+#[test]
+fn atomic_groups() {
+    assert!(!Regex::compile("(?>a+)a").unwrap().test("aaaa"));
+    assert!(Regex::compile("a+a").unwrap().test("aaaa"));
+
+    let find = |pattern: &str, src: &str| {
+        let regex = Regex::compile(pattern).unwrap();
+        regex.find(src).map(|m| m.span())
+    };
+    assert_eq!(find("(?>a+)b", "xaab"), Some((1, 4)));
+    assert_eq!(find("(?>a|ab)c", "abc"), None);
+    assert_eq!(find("(?>a*?)b", "aab"), Some((2, 3)));
+    assert_eq!(find("(?>(a+))\\1", "aaaa"), None);
+    assert_eq!(find("x(?>\\d+)?y", "x12y"), Some((0, 4)));
+    assert_eq!(find("^(?>\\w+):$", "key:"), Some((0, 4)));
+
+    let regex = Regex::compile("(?>(a)b+)c").unwrap();
+    assert_eq!(regex.capture_count(), 1);
+    assert_eq!(regex.captures("abbc").unwrap().get(1), Some("a"));
+    assert_eq!(regex.to_pattern(), "(?>(a)b+)c");
+    assert!(Regex::compile("(?>a").is_err());
+}
+
+#[test]
+fn rev_matches() {
+    let regex = Regex::compile("(a)|b+").unwrap();
+    let src = "abbxa_bb";
+    let forward: Vec<_> = regex.find_matches(src).map(|m| m.span()).collect();
+    let mut backward: Vec<_> = regex.rev_matches(src).map(|m| m.span()).collect();
+    backward.reverse();
+    assert_eq!(forward, backward);
+    assert_eq!(
+        regex.rev_matches(src).next().map(|m| m.span()),
+        regex.rfind(src).map(|m| m.span())
+    );
+    assert_eq!(regex.rev_matches("xyz").count(), 0);
+}
+
+#[test]
+fn line_terminator() {
+    let conf = RegexConf::new()
+        .multiline(true)
+        .line_terminator(b'\r')
+        .unwrap();
+    let regex = Regex::compile("^\\w+$").unwrap();
+    let lines: Vec<_> = regex
+        .find_matches_with_conf("ab\rcd\nef\rgh", conf)
+        .map(|m| m.slice())
+        .collect();
+    assert_eq!(lines, ["ab", "gh"]);
+
+    let regex = Regex::compile(".+").unwrap();
+    let conf = RegexConf::new().line_terminator(0x1e).unwrap();
+    let records: Vec<_> = regex
+        .find_matches_with_conf("a\nb\x1ec", conf)
+        .map(|m| m.slice())
+        .collect();
+    assert_eq!(records, ["a\nb", "c"]);
+    let conf = conf.dot_all(true);
+    assert_eq!(
+        regex.find_with_conf("a\x1eb", conf).unwrap().slice(),
+        "a\x1eb"
+    );
+
+    let regex = Regex::compile("a\\Z").unwrap();
+    assert!(regex.test_with_conf("a\r", RegexConf::new().line_terminator(b'\r').unwrap()));
+    assert!(!regex.test_with_conf("a\n", RegexConf::new().line_terminator(b'\r').unwrap()));
+
+    let err = RegexConf::new().line_terminator(0x80).unwrap_err();
+    assert_eq!(err.kind(), crate::RegexErrorKind::BadConf);
+    assert_eq!(
+        err.to_string(),
+        "The line terminator must be ASCII, found 0x80"
+    );
+
+    // Non ASCII terminators set directly fall back to a newline
+    let conf = RegexConf {
+        line_terminator: 0xff,
+        ..RegexConf::default()
+    };
+    assert_eq!(
+        Regex::compile(".+")
+            .unwrap()
+            .find_with_conf("a\nb", conf)
+            .unwrap()
+            .slice(),
+        "a"
+    );
+}
+
+#[test]
+fn first_set() {
+    use crate::MatchCase as M;
+    use crate::compiler::first_set;
+
+    let set = |pattern: &str| first_set(Regex::compile(pattern).unwrap().ast());
+    assert_eq!(set("abc").as_deref(), Some(&[M::Char('a')][..]));
+    assert_eq!(
+        set("a?b*c").as_deref(),
+        Some(&[M::Char('a'), M::Char('b'), M::Char('c')][..])
+    );
+    assert_eq!(
+        set("^(|x)\\d+").as_deref(),
+        Some(&[M::Char('x'), M::Decimal][..])
+    );
+    assert_eq!(
+        set("(?>a|b{2})").as_deref(),
+        Some(&[M::Char('a'), M::Char('b')][..])
+    );
+    for pattern in ["", "a*", "a|", "b{0,3}", ".a", "(a)?\\1x", "\\b"] {
+        assert_eq!(set(pattern), None, "{pattern}");
+    }
+
+    // The positions skipped don't change the matches
+    template!("(?i)ab", &["xAb", "aab"], &["a", "ba"]);
+    template!("^\\w+@\\w+$", &["a@b"], &["@b", " a@b"]);
+    let regex = Regex::compile_with_alphabet("[^b]c", &['a', 'c']).unwrap();
+    assert!(regex.test("bbac"));
+    assert!(!regex.test("bbbc"));
+    let regex = Regex::compile("\\s?[0-9]+").unwrap();
+    let spans: Vec<_> = regex.find_matches("a 12b3").map(|m| m.span()).collect();
+    assert_eq!(spans, [(1, 4), (5, 6)]);
+
+    // Without a literal prefix, checking the first set is cheaper
+    // than attempting a match at each position
+    let regex = Regex::compile("\\d+x|[a-c]+y").unwrap();
+    let src = "z".repeat(1000) + "12x";
+    let conf = RegexConf::new().max_steps(1500);
+    let mut matcher = regex.find_matches_with_conf(&src, conf);
+    assert_eq!(matcher.next().unwrap().span(), (1000, 1003));
+    assert!(!matcher.aborted());
+}
+
+#[test]
+fn chars_matcher() {
+    let regex = Regex::compile("(ñ+)€\\1|[0-9]+").unwrap();
+    let src = format!("{}ññ€ññ a{}", "x".repeat(1500), "7".repeat(2000));
+    let chars: &mut dyn Iterator<Item = char> = &mut src.chars();
+    let mut matcher = regex.find_matches_chars(chars);
+    let spans: Vec<_> = matcher.by_ref().collect();
+    let expected: Vec<_> = regex.find_matches(&src).map(|m| m.span()).collect();
+    assert_eq!(spans, expected);
+    assert_eq!(&src[matcher.input_offset()..], matcher.input());
+
+    let regex = Regex::compile("a*").unwrap();
+    let spans: Vec<_> = regex.find_matches_chars("".chars()).collect();
+    assert_eq!(spans, [(0, 0)]);
+}
+
+#[test]
+fn conf_variants() {
+    let regex = Regex::compile("(a)b").unwrap();
+    let conf = RegexConf::new().case_sensitive(false);
+    let src = "xAB ab";
+
+    assert_eq!(regex.find_with_conf(src, conf).unwrap().span(), (1, 3));
+    assert_eq!(regex.find(src).unwrap().span(), (4, 6));
+    assert_eq!(regex.match_count_with_conf(src, conf), 2);
+    assert_eq!(regex.match_count(src), 1);
+    assert_eq!(
+        regex.captures_with_conf(src, conf).unwrap().get(1),
+        Some("A")
+    );
+    assert_eq!(regex.captures(src).unwrap().get(1), Some("a"));
+    assert!(regex.test_with_conf("AB", conf));
+    assert!(!regex.test("AB"));
+
+    assert_eq!(regex.shortest_match_with_conf(src, conf), Some(3));
+    assert_eq!(regex.shortest_match(src), Some(6));
+    assert_eq!(regex.is_match_at_with_conf(src, 1, conf).unwrap(), Some(3));
+    assert_eq!(regex.is_match_at(src, 1).unwrap(), None);
+    assert!(regex.is_match_at_with_conf(src, 7, conf).is_err());
+    assert!(regex.is_full_match_with_conf("aB", conf));
+    assert!(!regex.is_full_match("aB"));
+
+    let spans: Vec<_> = regex.capture_indices_with_conf(src, conf).collect();
+    assert_eq!(spans[0], [Some((1, 3)), Some((1, 2))]);
+    assert_eq!(regex.capture_indices(src).count(), 1);
+    assert_eq!(regex.replace_with_conf(src, "_", conf), "x_ _");
+    assert_eq!(regex.replace(src, "_"), "xAB _");
+    assert_eq!(regex.replacen_with_conf(src, 1, "_", conf), "x_ ab");
+    assert_eq!(regex.replace_all_with_conf(src, "<$1>", conf), "x<A> <a>");
+    assert_eq!(
+        regex.replace_with_fn_conf(src, |m| m.slice().len().to_string(), conf),
+        "x2 2"
+    );
+    let pieces = regex.split_with_delimiters_with_conf(src, conf);
+    assert_eq!(pieces.len(), 5);
+    assert_eq!(regex.split_with_delimiters(src).len(), 3);
+    let parts: Vec<_> = regex.split_inclusive_with_conf(src, conf).collect();
+    assert_eq!(parts, ["xAB", " ab"]);
+
+    let conf = conf.ignore_captures_in_result(true);
+    assert_eq!(
+        regex.captures_with_conf(src, conf).unwrap().get(1),
+        Some("A")
+    );
+    let caps: Vec<_> = regex.captures_iter_with_conf(src, conf).collect();
+    assert_eq!(caps.len(), 2);
+    assert_eq!(caps[1].get(1), Some("a"));
+    let spans: Vec<_> = regex.capture_indices_with_conf(src, conf).collect();
+    assert_eq!(spans[1], [Some((4, 6)), Some((4, 5))]);
+    assert_eq!(regex.replace_all_with_conf(src, "$1", conf), "xA a");
+}
+
+#[test]
+fn repetition_bounds() {
+    use crate::RegexErrorKind as K;
+
+    let err = Regex::compile("a{5,2}").unwrap_err();
+    assert_eq!(err.kind(), K::BadRepetition);
+    assert_eq!(err.to_string(), "Repetition min greater than max: {5,2}");
+    for pattern in ["a{0,4000000000}", "a{70000}", "a{70000,}"] {
+        let err = Regex::compile(pattern).unwrap_err();
+        assert_eq!(err.kind(), K::BadRepetition, "{pattern}");
+    }
+    template!("^a{2,2}$", &["aa"], &["a", "aaa"]);
+    template!("^a{,65535}$", &["", "aaa"], &["b"]);
+
+    let err = RegexBuilder::new()
+        .literal("a")
+        .repeat(Some(3), Some(1))
+        .build()
+        .unwrap_err();
+    assert_eq!(err.kind(), K::BadRepetition);
+}
+
+#[test]
+fn class_edge_cases() {
+    template!("^[]a]+$", &["]", "a]a"], &["b", ""]);
+    template!("^[^]a]$", &["b", "-"], &["]", "a"]);
+    template!("^[a-]+$", &["a-", "-"], &["b"]);
+    template!("^[-a]+$", &["-a"], &["b"]);
+    template!("^[\\]\\-]+$", &["]-"], &["a"]);
+    template!("^[a\\-z]$", &["a", "-", "z"], &["b"]);
+    template!("^[!-\\]]$", &["!", "]", "A"], &["^", "a"]);
+    template!("^[]-a]$", &["]", "^", "a"], &["b", "-"]);
+
+    assert!(Regex::compile("[]").is_err());
+    assert!(Regex::compile("[^]").is_err());
+    assert!(Regex::compile("[a-\\w]").is_err());
+
+    for pattern in ["[]a]", "[a-]", "[^]-]", "[!-\\]]"] {
+        let regex = Regex::compile(pattern).unwrap();
+        assert_eq!(
+            Regex::compile(regex.to_pattern()).unwrap(),
+            regex,
+            "{pattern}"
+        );
+    }
+}
+
+#[test]
+fn pattern_analysis() {
+    let lens = |pattern: &str| {
+        let regex = Regex::compile(pattern).unwrap();
+        (regex.min_len(), regex.max_len())
+    };
+    assert_eq!(lens("abc"), (3, Some(3)));
+    assert_eq!(lens("a|bcd|"), (0, Some(3)));
+    assert_eq!(lens("^\\d{2,4}-[a-z]?$"), (3, Some(6)));
+    assert_eq!(lens("(ab)*c"), (1, None));
+    assert_eq!(lens("x{3,}"), (3, None));
+    assert_eq!(lens("(a)\\1"), (1, None));
+    assert_eq!(lens("(\\b)*ñ"), (1, Some(1)));
+    assert_eq!(lens("(a)?(?(1)bc|d)"), (1, Some(3)));
+    assert_eq!(lens(""), (0, Some(0)));
+
+    let anchors = |pattern: &str| {
+        let regex = Regex::compile(pattern).unwrap();
+        (regex.is_anchored_start(), regex.is_anchored_end())
+    };
+    assert_eq!(anchors("^a$"), (true, true));
+    assert_eq!(anchors("\\Aa\\z"), (true, true));
+    assert_eq!(anchors("(^a|^b)c"), (true, false));
+    assert_eq!(anchors("a\\Z"), (false, false));
+    assert_eq!(anchors("(?m)^a$"), (false, false));
+    assert_eq!(anchors("a|^b"), (false, false));
+    assert_eq!(anchors("(?>a$)"), (false, true));
+}
+
+#[test]
+fn match_accessors() {
+    let regex = Regex::compile("ñ+|\\b").unwrap();
+    for m in regex.find_matches("añña b") {
+        assert_eq!(m.span(), (m.start(), m.end()));
+        assert_eq!(m.len(), m.slice().len());
+        assert_eq!(m.is_empty(), m.start() == m.end());
+    }
+    let m = regex.find("xññ").unwrap();
+    assert_eq!((m.start(), m.end(), m.len()), (0, 0, 0));
+    assert!(m.is_empty());
+    let m = regex.find(" ññ").unwrap();
+    assert_eq!((m.start(), m.end(), m.len()), (1, 5, 4));
+    assert!(!m.is_empty());
+}
+
+#[test]
+fn depth_limits() {
+    use crate::RegexErrorKind as K;
+
+    let nested = |n: usize| format!("{}a{}", "(".repeat(n), ")".repeat(n));
+    let regex = Regex::compile(nested(250)).unwrap();
+    assert!(regex.test("a"));
+    assert_eq!(Regex::compile(regex.to_pattern()).unwrap(), regex);
+    for pattern in [nested(251), nested(100_000), "(?>".repeat(300)] {
+        let err = Regex::compile(pattern).unwrap_err();
+        assert_eq!(err.kind(), K::TooDeep);
+    }
+
+    let conf = RegexConf::new().max_depth(20);
+    let regex = Regex::compile(nested(30)).unwrap();
+    let mut matcher = regex.find_matches_with_conf("a", conf);
+    assert!(matcher.next().is_none());
+    assert!(matcher.aborted());
+    let err = matcher.last_error().unwrap();
+    assert_eq!(err.kind(), K::Aborted);
+    assert_eq!(err.to_string(), "Exceeded the maximum depth of 20");
+
+    let regex = Regex::compile(nested(5)).unwrap();
+    let mut matcher = regex.find_matches_with_conf("aba", conf);
+    assert_eq!(matcher.by_ref().count(), 2);
+    assert!(!matcher.aborted());
+}
+
+#[test]
+fn python_backreference() {
+    use crate::RegexErrorKind as K;
+
+    template!(
+        "^(?<q>['\"])\\w+(?P=q)$",
+        &["'abc'", "\"abc\""],
+        &["'abc\"", "\"abc'", "abc"],
+    );
+    assert_eq!(
+        Regex::compile("(?<q>.)(?P=q)").unwrap(),
+        Regex::compile("(?<q>.)\\k<q>").unwrap()
+    );
+
+    for (pattern, kind) in [
+        ("(?<q>a)(?P=x)", K::UnknownBackreference),
+        ("(?<q>a)(?P=q", K::BadGroupName),
+        ("(?P=q)(?<q>a)", K::UnknownBackreference),
+        ("(?<q>a(?P=q))", K::ForwardReference),
+    ] {
+        let err = Regex::compile(pattern).unwrap_err();
+        assert_eq!(err.kind(), kind, "{pattern}");
+    }
+    let err = Regex::compile("(?<q>a)(?P=x)").unwrap_err();
+    assert_eq!(err.to_string(), "Unknown capture 'x'");
+}
+
+#[test]
+fn capture_indices() {
+    let regex = Regex::compile("(a)|(b)()").unwrap();
+    let spans: Vec<_> = regex.capture_indices("xab").collect();
+    assert_eq!(
+        spans,
+        [
+            vec![Some((1, 2)), Some((1, 2)), None, None],
+            vec![Some((2, 3)), None, Some((2, 3)), Some((3, 3))],
+        ]
+    );
+
+    for (pattern, src) in [
+        ("(?<k>\\w+)=(?<v>\\w*)", "añ=1 b= c=ñ"),
+        ("(a)?(b|(c))+", "abcbc cb a"),
+        ("(<)?\\w+(?(1)>)", "<a> b <c"),
+        ("x*", "ñxñ"),
+    ] {
+        let regex = Regex::compile(pattern).unwrap();
+        let spans: Vec<_> = regex.capture_indices(src).collect();
+        let caps: Vec<_> = regex.captures_iter(src).collect();
+        assert_eq!(spans.len(), caps.len(), "{pattern}");
+        for (spans, caps) in spans.iter().zip(&caps) {
+            assert_eq!(spans.len(), caps.len());
+            for (n, span) in spans.iter().enumerate() {
+                assert_eq!(span.map(|(s, e)| &src[s..e]), caps.get(n), "{pattern}");
+            }
+        }
+    }
+}
+
+#[test]
+fn incomplete_escapes() {
+    use crate::RegexErrorKind as K;
+
+    for (pattern, msg) in [
+        ("\\", "Incomplete escape at byte 0"),
+        ("\\x", "Incomplete escape at byte 0"),
+        ("a\\x4", "Incomplete escape at byte 1"),
+        ("\\u{", "Incomplete escape at byte 0"),
+        ("ñ\\x{41", "Incomplete escape at byte 2"),
+        ("\\k<", "Incomplete escape at byte 0"),
+        ("(?<n>a)\\k<n", "Incomplete escape at byte 7"),
+        ("[a-\\", "Incomplete escape at byte 3"),
+        ("[\\u{", "Incomplete escape at byte 1"),
+        ("\\xZZ", "Invalid hex escape at byte 0"),
+        ("\\x4ñ", "Invalid hex escape at byte 0"),
+        ("\\u41", "Invalid hex escape at byte 0"),
+        ("\\x{}", "Invalid hex escape at byte 0"),
+        ("\\x{+41}", "Invalid hex escape at byte 0"),
+        ("\\u{D800}", "Invalid hex escape at byte 0"),
+        ("\\u{110000}", "Invalid hex escape at byte 0"),
+    ] {
+        let err = Regex::compile(pattern).unwrap_err();
+        assert_eq!(err.kind(), K::BadEscape, "{pattern}");
+        assert_eq!(err.to_string(), msg, "{pattern}");
+    }
+
+    template!("^\\x41\\x{e9}\\u{1F600}$", &["Aé😀"], &["A", "xé😀"]);
+    template!("^[\\x41-\\x{5A}\\u{5d}]+$", &["AZ]", "Q"], &["a", "-"]);
+    let regex = Regex::compile("\\x{2E}+").unwrap();
+    assert_eq!(Regex::compile(regex.to_pattern()).unwrap(), regex);
+    assert!(regex.test("..") && !regex.test("a"));
+}
+
+#[test]
+fn ascii_classes() {
+    let ascii = RegexConf::new().unicode(false);
+    template_with_conf!("^\\w+$", ascii, &["abc_09"], &["añb", "é"]);
+    template_with_conf!("^\\W+$", ascii, &["ñé-"], &["a"]);
+    template_with_conf!("^a\\sb$", ascii, &["a b", "a\u{b}b"], &["a\u{a0}b"]);
+    template_with_conf!("^a\\Sb$", ascii, &["a\u{a0}b"], &["a b"]);
+    template_with_conf!("^\\d$", ascii, &["7"], &["٣"]);
+    template_with_conf!("\\ba", ascii, &["ña"], &["_a"]);
+    template!("\\ba", &["a"], &["ña"]);
+    template_with_conf!("^.$", ascii, &["ñ", "😀"], &["ab"]);
+
+    let insensitive = ascii.case_sensitive(false);
+    template_with_conf!("^ABC$", insensitive, &["abc", "aBc"], &["ábc"]);
+    template_with_conf!("^Ñ$", insensitive, &["Ñ"], &["ñ"]);
+    template_with_conf!("^Ñ$", RegexConf::new().case_sensitive(false), &["ñ"], &[]);
+
+    #[cfg(feature = "unicode-segmentation")]
+    {
+        let graphemes = RegexConf::new().grapheme_dot(true);
+        let regex = Regex::compile("^.$").unwrap();
+        assert!(regex.test_with_conf("e\u{301}", graphemes));
+        assert!(!regex.test_with_conf("e\u{301}", graphemes.unicode(false)));
+    }
+}
+
+#[test]
+#[cfg(all(feature = "diagnostics", feature = "std"))]
+fn traced_search() {
+    use crate::TraceEvent;
+
+    let regex = Regex::compile("[a-z]+1").unwrap();
+    let mut depth = 0usize;
+    let mut max_depth = 0;
+    let mut events = Vec::new();
+    let matches = regex
+        .find_matches_traced("ab1", |event| {
+            match event {
+                TraceEvent::Enter { .. } => depth += 1,
+                TraceEvent::Exit { .. } => depth -= 1,
+            }
+            max_depth = max_depth.max(depth);
+            events.push((event.name(), event.offset()));
+        })
+        .count();
+    assert_eq!(matches, 1);
+    assert_eq!(depth, 0);
+    assert!(max_depth > 1);
+    assert_eq!(events.first(), Some(&("OneOrMore", 0)));
+    assert_eq!(events.last(), Some(&("OneOrMore", 3)));
+
+    // Each step enters a case, and going back into
+    // a case that matched enters it again
+    let (mut entered, mut exited) = (0, 0);
+    let mut matcher = regex.find_matches_traced("xy 1z1", |event| match event {
+        TraceEvent::Enter { .. } => entered += 1,
+        TraceEvent::Exit { .. } => exited += 1,
+    });
+    matcher.by_ref().for_each(drop);
+    let steps = matcher.stats().steps;
+    drop(matcher);
+    assert!(entered > steps);
+    assert_eq!(entered, exited);
+}
+
+#[test]
+fn comments() {
+    use crate::RegexErrorKind as K;
+
+    template!(
+        "^(?#year)\\d{4}-(?# month, 01 to 12 )\\d{2}$",
+        &["2024-05"],
+        &["2024-5", "(?#year)2024-05"],
+    );
+    assert_eq!(
+        Regex::compile("a(?#x)b(?#)c").unwrap(),
+        Regex::compile("abc").unwrap()
+    );
+    // Quantifiers after a comment apply to what's before it
+    template!("^a(?#many)+$", &["aaa"], &[""]);
+    template!("^[(?#)]+$", &["(?#)"], &["a"]);
+    template!("^\\(?#$", &["(#", "#"], &["(?#"]);
+
+    for pattern in ["a(?#", "(?#comment", "(a(?#b)"] {
+        let err = Regex::compile(pattern).unwrap_err();
+        assert_eq!(err.kind(), K::UnterminatedGroup, "{pattern}");
+    }
+    let err = Regex::compile("a(?#b").unwrap_err();
+    assert_eq!(err.to_string(), "Unterminated (?#...) comment");
+}
+
+#[test]
+fn split_inclusive() {
+    let split = |pattern: &str, src: &str| -> Vec<String> {
+        let regex = Regex::compile(pattern).unwrap();
+        let segments: Vec<_> = regex.split_inclusive(src).map(String::from).collect();
+        assert_eq!(segments.concat(), src);
+        segments
+    };
+    assert_eq!(split(";+", "a;b;;c;"), ["a;", "b;;", "c;"]);
+    assert_eq!(split(";", ";a"), [";", "a"]);
+    assert_eq!(split(";", "abc"), ["abc"]);
+    assert!(split(";", "").is_empty());
+    assert_eq!(split("x*", "ab"), ["a", "b"]);
+    assert_eq!(split("$", "añ"), ["añ"]);
+    assert_eq!(split("ñ?", "ñañ"), ["ñ", "añ"]);
+
+    let regex = Regex::compile("\\d").unwrap();
+    let mut segments = regex.split_inclusive("1a2b");
+    assert_eq!(segments.next(), Some("1"));
+    assert_eq!(segments.next(), Some("a2"));
+    assert_eq!(segments.next(), Some("b"));
+    assert_eq!(segments.next(), None);
+    assert_eq!(segments.next(), None);
 }